@@ -26,7 +26,7 @@ use tower_cookies::cookie::{CookieBuilder, SameSite};
 use chrono::prelude::*;
 use serde::de::StdError;
 use crate::context::user_context::UserContext;
-use crate::models::token_model::Claims;
+use crate::models::token_model::{Claims, TokenKind};
 
 
 pub fn handle_response(message: String, status: StatusCode) -> Response<String>{
@@ -43,6 +43,12 @@ pub fn handle_response(message: String, status: StatusCode) -> Response<String>{
 pub struct UserResponse{
     id: Option<ObjectId>,
     email: String,
+    versioning: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UserSettings{
+    versioning: Option<bool>,
 }
 
 pub async fn get_user(ctx: Result<UserContext, StatusCode>, user_col: State<UserCollection>) -> Result<Json<UserResponse>, StatusCode>{
@@ -57,6 +63,7 @@ pub async fn get_user(ctx: Result<UserContext, StatusCode>, user_col: State<User
                     let user_response = UserResponse{
                         id: user.id,
                         email: user.email,
+                        versioning: user.versioning.unwrap_or(false),
                     };
 
                     Ok(Json(user_response))
@@ -73,6 +80,30 @@ pub async fn get_user(ctx: Result<UserContext, StatusCode>, user_col: State<User
 }
 
 
+pub async fn update_settings(ctx: UserContext, user_col: State<UserCollection>, settings: Json<UserSettings>) -> Result<Json<UserResponse>, StatusCode>{
+    let filter = doc! {"_id": ctx.user_id};
+
+    if let Some(versioning) = settings.versioning {
+        let update = doc! {"$set": {"versioning": versioning}};
+
+        if user_col.user_collection.update_one(filter.clone(), update, None).await.is_err() {
+            return Err(StatusCode::BAD_REQUEST);
+        }
+    }
+
+    let user = user_col.user_collection.find_one(filter, None).await.unwrap_or(Option::None);
+
+    match user {
+        Some(user) => Ok(Json(UserResponse{
+            id: user.id,
+            email: user.email,
+            versioning: user.versioning.unwrap_or(false),
+        })),
+        None => Err(StatusCode::NOT_FOUND)
+    }
+}
+
+
 pub async fn sign_up(user_col: State<UserCollection>, user: Json<User>) -> Response<String>{
 
     let user_filter = doc!{"email": &user.email};
@@ -91,6 +122,8 @@ pub async fn sign_up(user_col: State<UserCollection>, user: Json<User>) -> Respo
                 id: None,
                 email: user.email.to_string(),
                 password: hashed_password,
+                quota: None,
+                versioning: None,
             };
 
             let new_user = user_col.user_collection.insert_one(&user_details, None).await;
@@ -132,6 +165,7 @@ pub async fn sing_in(cookies: Cookies, user_col: State<UserCollection>, user: Js
 
             if validate_password {
                 let claims = Claims{
+                    kind: TokenKind::Login,
                     id: user.id.clone(),
                     email: user.email.clone(),
                     exp: (Utc::now() + Duration::days(1)).timestamp()
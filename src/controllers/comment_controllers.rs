@@ -0,0 +1,82 @@
+use std::sync::Arc;
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use axum::Json;
+use bson::doc;
+use bson::oid::ObjectId;
+use serde::Deserialize;
+use crate::AppState;
+use crate::context::user_context::UserContext;
+use crate::controllers::permission_controllers::ItemParams;
+use crate::error::storage_error::StorageError;
+use crate::models::comment_model::{Comment, CommentThread};
+use crate::models::share_model::SharedItemType;
+
+
+#[derive(Deserialize, Debug)]
+pub struct CommentRequest {
+    pub file_id: ObjectId,
+    // the comment being answered, a new thread when missing
+    pub parent_id: Option<ObjectId>,
+    pub body: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct EditCommentRequest {
+    pub body: String,
+}
+
+fn valid_body(body: &str) -> bool {
+    !body.trim().is_empty()
+}
+
+// Threads on a file, or on the files directly inside a folder.
+pub async fn get_comments(ctx: UserContext, state: State<Arc<AppState>>, Query(params): Query<ItemParams>) -> Result<Json<Vec<CommentThread>>, StorageError>{
+    let threads = match params.item()? {
+        (SharedItemType::File, file_id) => state.file_comments(&ctx.user_id, &file_id).await?,
+        (SharedItemType::Folder, folder_id) => state.folder_comments(&ctx.user_id, &folder_id).await?,
+    };
+
+    Ok(Json(threads))
+}
+
+pub async fn add_comment(ctx: UserContext, state: State<Arc<AppState>>, request: Json<CommentRequest>) -> Result<Json<Comment>, StorageError>{
+    if !valid_body(&request.body) {
+        return Err(StatusCode::BAD_REQUEST.into());
+    }
+
+    let comment = state.add_comment(&ctx.user_id, &ctx.email, &request.file_id, request.parent_id, request.body.trim()).await?;
+    Ok(Json(comment))
+}
+
+pub async fn edit_comment(ctx: UserContext, state: State<Arc<AppState>>, Path(comment_id): Path<ObjectId>, request: Json<EditCommentRequest>) -> Result<Json<Comment>, StorageError>{
+    if !valid_body(&request.body) {
+        return Err(StatusCode::BAD_REQUEST.into());
+    }
+
+    let comment = state.edit_comment(&ctx.user_id, &comment_id, request.body.trim()).await?;
+    Ok(Json(comment))
+}
+
+pub async fn delete_comment(ctx: UserContext, state: State<Arc<AppState>>, Path(comment_id): Path<ObjectId>) -> Result<StatusCode, StorageError>{
+    state.delete_comment(&ctx.user_id, &comment_id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn resolve_comment(ctx: UserContext, state: State<Arc<AppState>>, Path(comment_id): Path<ObjectId>) -> Result<Json<Comment>, StorageError>{
+    let comment = state.set_resolved(&ctx.user_id, &comment_id, true).await?;
+    Ok(Json(comment))
+}
+
+pub async fn unresolve_comment(ctx: UserContext, state: State<Arc<AppState>>, Path(comment_id): Path<ObjectId>) -> Result<Json<Comment>, StorageError>{
+    let comment = state.set_resolved(&ctx.user_id, &comment_id, false).await?;
+    Ok(Json(comment))
+}
+
+// Comments the user was @mentioned in, newest last.
+pub async fn get_mentions(ctx: UserContext, state: State<Arc<AppState>>) -> Result<Json<Vec<Comment>>, StatusCode>{
+    match state.mentions_of(&ctx.user_id).await {
+        Ok(comments) => Ok(Json(comments)),
+        Err(_) => Err(StatusCode::BAD_REQUEST)
+    }
+}
@@ -1,25 +1,65 @@
 use std::sync::Arc;
 use axum::extract::{Query, State};
-use axum::http::StatusCode;
+use axum::http::{HeaderMap, StatusCode};
 use axum::Json;
 use bson::doc;
 use bson::oid::ObjectId;
 use crate::{AppState, Item};
 use crate::context::user_context::UserContext;
-use crate::models::folder_model::{Folder, FolderType};
 use axum::{response::IntoResponse};
+use crate::services::blob_services::StorageUsage;
+use serde::Deserialize;
+use crate::error::storage_error::StorageError;
 use crate::models::file_model::File;
+use crate::models::folder_model::Folder;
+use crate::models::permission_model::Role;
+use crate::services::listing_service::{next_cursor_headers, ListParams};
+use crate::services::permission_service::SharedItem;
 
 
-pub async fn get_dashboard(ctx: UserContext, state: State<Arc<AppState>>, params: Query<Vec<(String, ObjectId)>>) -> Result<Json<Vec<Item<File, Folder>>>, StatusCode>{
+#[derive(Deserialize, Debug)]
+pub struct DashboardParams {
+    // the folder to show, the root when missing
+    pub id: Option<ObjectId>,
+    // a shared drive to show instead of the user's own one
+    pub drive_id: Option<ObjectId>,
+}
 
-    let dashboard_result = state.get_dashboard_controller(&ctx.user_id, &params).await;
+pub async fn get_dashboard(ctx: UserContext, state: State<Arc<AppState>>, Query(params): Query<DashboardParams>, Query(list): Query<ListParams>) -> Result<(HeaderMap, Json<Vec<Item<File, Folder>>>), StorageError>{
 
-    return match dashboard_result {
-        Ok(dashboard) => {
-            Ok(Json(dashboard))
-        },
-        Err(_) => Err(StatusCode::BAD_REQUEST)
+    // usage of the selected drive, the user's own one unless a shared drive is asked for
+    let drive_owner = state.authorize_root(&ctx.user_id, params.drive_id, Role::Viewer).await?;
+
+    let storage = match state.get_quota_usage(&drive_owner).await {
+        Ok(storage) => storage,
+        Err(_) => return Err(StatusCode::BAD_REQUEST.into())
     };
 
-}
\ No newline at end of file
+    // a folder shared with the user is listed as its owner sees it
+    let owner_id = state.authorize_location(&ctx.user_id, params.id, params.drive_id, Role::Viewer).await?.id;
+
+    let mut listing = state.get_dashboard_controller(&owner_id, params.id, &list).await?;
+    state.with_item_stats(&owner_id, &mut listing.items).await?;
+
+    let mut headers = next_cursor_headers(&listing.next_cursor);
+    headers.extend(storage.headers());
+
+    Ok((headers, Json(listing.items)))
+
+}
+
+
+pub async fn get_storage_usage(ctx: UserContext, state: State<Arc<AppState>>) -> Result<Json<StorageUsage>, StatusCode>{
+    match state.get_storage_usage(&ctx.user_id).await {
+        Ok(usage) => Ok(Json(usage)),
+        Err(_) => Err(StatusCode::BAD_REQUEST)
+    }
+}
+
+
+pub async fn get_shared_with_me(ctx: UserContext, state: State<Arc<AppState>>) -> Result<Json<Vec<SharedItem>>, StatusCode>{
+    match state.shared_with_me(&ctx.user_id).await {
+        Ok(items) => Ok(Json(items)),
+        Err(_) => Err(StatusCode::BAD_REQUEST)
+    }
+}
@@ -0,0 +1,221 @@
+use std::sync::Arc;
+use axum::body::{boxed, BoxBody, Empty, StreamBody};
+use axum::extract::{Path, Query, State};
+use axum::http::{header, HeaderMap, HeaderValue, StatusCode};
+use axum::response::Response;
+use bson::doc;
+use bson::oid::ObjectId;
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use serde::Deserialize;
+use crate::AppState;
+use crate::context::user_context::UserContext;
+use crate::error::storage_error::StorageError;
+use crate::models::file_model::File;
+use crate::models::permission_model::Role;
+use crate::models::share_model::SharedItemType;
+use crate::storage::blob_store::BlobMeta;
+
+
+#[derive(Deserialize, Debug)]
+pub struct ContentParams {
+    pub disposition: Option<String>,
+}
+
+#[derive(Debug, PartialEq)]
+enum ByteRange {
+    Full,
+    Partial(u64, u64),
+    Unsatisfiable,
+}
+
+// Only single ranges are served as 206, anything we can't parse falls back to the whole body.
+fn parse_range(range: &str, size: u64) -> ByteRange {
+    let spec = match range.trim().strip_prefix("bytes=") {
+        Some(spec) if !spec.contains(',') => spec.trim(),
+        _ => return ByteRange::Full
+    };
+
+    let (start, end) = match spec.split_once('-') {
+        Some(bounds) => bounds,
+        None => return ByteRange::Full
+    };
+
+    let bounds = match (start.parse::<u64>(), end.parse::<u64>()) {
+        (Ok(start), Ok(end)) if start <= end => Some((start, end.min(size.saturating_sub(1)))),
+        (Ok(start), Err(_)) if end.is_empty() => Some((start, size.saturating_sub(1))),
+        (Err(_), Ok(suffix)) if start.is_empty() && suffix > 0 => Some((size.saturating_sub(suffix), size.saturating_sub(1))),
+        _ => return ByteRange::Full
+    };
+
+    match bounds {
+        Some((start, end)) if size > 0 && start < size => ByteRange::Partial(start, end),
+        _ => ByteRange::Unsatisfiable
+    }
+}
+
+fn entity_tag(blob: &BlobMeta) -> String {
+    let modified = blob.last_modified.map(|date| date.timestamp_millis()).unwrap_or(0);
+    format!("\"{:x}-{:x}\"", blob.size, modified)
+}
+
+fn etag_matches(header_value: &str, etag: &str) -> bool {
+    header_value.split(',').map(|tag| tag.trim().trim_start_matches("W/")).any(|tag| tag == "*" || tag == etag)
+}
+
+fn content_disposition(file_name: &str, inline: bool) -> String {
+    let kind = if inline { "inline" } else { "attachment" };
+
+    let fallback = file_name.chars()
+        .map(|c| if c.is_ascii() && !c.is_ascii_control() && c != '"' && c != '\\' { c } else { '_' })
+        .collect::<String>();
+
+    let encoded = utf8_percent_encode(file_name, NON_ALPHANUMERIC);
+
+    format!("{}; filename=\"{}\"; filename*=UTF-8''{}", kind, fallback, encoded)
+}
+
+// Types a browser only ever displays, never runs. Anything else, HTML and SVG above all, could run
+// script on our origin, so it is only handed out as an opaque download.
+fn inline_safe(file_type: &str) -> bool {
+    let essence = file_type.split(';').next().unwrap_or_default().trim().to_ascii_lowercase();
+
+    match essence.split_once('/') {
+        Some(("image", subtype)) => subtype != "svg+xml",
+        Some(("video", _)) | Some(("audio", _)) => true,
+        _ => essence == "application/pdf" || essence == "text/plain"
+    }
+}
+
+// Builds the response for a stored file, shared by every route that hands out file content.
+pub async fn file_content_response(state: &AppState, file: &File, headers: &HeaderMap, inline: bool) -> Result<Response<BoxBody>, StatusCode> {
+    let blob = match state.blob_store.head(&file.aws_file_name).await {
+        Ok(Some(blob)) => blob,
+        Ok(None) => return Err(StatusCode::NOT_FOUND),
+        Err(_) => return Err(StatusCode::INTERNAL_SERVER_ERROR)
+    };
+
+    let etag = entity_tag(&blob);
+
+    if let Some(if_none_match) = headers.get(header::IF_NONE_MATCH).and_then(|value| value.to_str().ok()) {
+        if etag_matches(if_none_match, &etag) {
+            return Response::builder()
+                .status(StatusCode::NOT_MODIFIED)
+                .header(header::ETAG, &etag)
+                .body(boxed(Empty::new()))
+                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    }
+
+    // a stale If-Range means the client's partial copy is outdated, so send everything again
+    let range_is_fresh = headers.get(header::IF_RANGE)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.trim() == etag)
+        .unwrap_or(true);
+
+    let range = match headers.get(header::RANGE).and_then(|value| value.to_str().ok()) {
+        Some(range) if range_is_fresh => parse_range(range, blob.size),
+        _ => ByteRange::Full
+    };
+
+    let file_name = file.original_file_name.clone().unwrap_or(file.file_name.clone());
+
+    // the stored type comes from the uploader, it is only repeated when it is harmless
+    let (content_type, inline) = match inline_safe(&file.file_type) {
+        true => (HeaderValue::from_str(&file.file_type).unwrap_or(HeaderValue::from_static("application/octet-stream")), inline),
+        false => (HeaderValue::from_static("application/octet-stream"), false)
+    };
+
+    let builder = Response::builder()
+        .header(header::ACCEPT_RANGES, "bytes")
+        .header(header::ETAG, &etag)
+        .header(header::CONTENT_TYPE, content_type)
+        .header(header::X_CONTENT_TYPE_OPTIONS, "nosniff")
+        .header(header::CONTENT_DISPOSITION, content_disposition(&file_name, inline));
+
+    let (builder, range) = match range {
+        ByteRange::Full => {
+            (builder.status(StatusCode::OK).header(header::CONTENT_LENGTH, blob.size), None)
+        },
+        ByteRange::Partial(start, end) => {
+            let builder = builder
+                .status(StatusCode::PARTIAL_CONTENT)
+                .header(header::CONTENT_LENGTH, end - start + 1)
+                .header(header::CONTENT_RANGE, format!("bytes {}-{}/{}", start, end, blob.size));
+
+            (builder, Some((start, end)))
+        },
+        ByteRange::Unsatisfiable => {
+            return Response::builder()
+                .status(StatusCode::RANGE_NOT_SATISFIABLE)
+                .header(header::CONTENT_RANGE, format!("bytes */{}", blob.size))
+                .body(boxed(Empty::new()))
+                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+
+    let stream = match state.blob_store.stream(&file.aws_file_name, range).await {
+        Ok(stream) => stream,
+        Err(_) => return Err(StatusCode::INTERNAL_SERVER_ERROR)
+    };
+
+    builder.body(boxed(StreamBody::new(stream))).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+pub async fn get_file_content(ctx: UserContext, state: State<Arc<AppState>>, Path(file_id): Path<ObjectId>, Query(params): Query<ContentParams>, headers: HeaderMap) -> Result<Response<BoxBody>, StorageError>{
+    let owner_id = state.authorize(&ctx.user_id, SharedItemType::File, &file_id, Role::Viewer).await?.id;
+    let filter = doc! {"_id": file_id, "user_id": owner_id};
+
+    let file = match state.file_collection.get_file(filter).await {
+        Ok(files) => files.into_iter().next(),
+        Err(_) => return Err(StatusCode::BAD_REQUEST.into())
+    };
+
+    match file {
+        Some(file) => {
+            let inline = params.disposition.as_deref() == Some("inline");
+            Ok(file_content_response(&state, &file, &headers, inline).await?)
+        },
+        None => Err(StatusCode::NOT_FOUND.into())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::{inline_safe, parse_range, ByteRange};
+
+    #[test]
+    fn parses_single_ranges() {
+        assert_eq!(parse_range("bytes=0-99", 1000), ByteRange::Partial(0, 99));
+        assert_eq!(parse_range("bytes=500-", 1000), ByteRange::Partial(500, 999));
+        assert_eq!(parse_range("bytes=-100", 1000), ByteRange::Partial(900, 999));
+        // the end is clamped to the last byte
+        assert_eq!(parse_range("bytes=900-2000", 1000), ByteRange::Partial(900, 999));
+        assert_eq!(parse_range("bytes=-2000", 1000), ByteRange::Partial(0, 999));
+    }
+
+    #[test]
+    fn serves_the_whole_body_for_what_it_cant_parse() {
+        assert_eq!(parse_range("bytes=0-10,20-30", 1000), ByteRange::Full);
+        assert_eq!(parse_range("items=0-10", 1000), ByteRange::Full);
+        assert_eq!(parse_range("bytes=10-5", 1000), ByteRange::Full);
+        assert_eq!(parse_range("bytes=abc", 1000), ByteRange::Full);
+        assert_eq!(parse_range("bytes=-0", 1000), ByteRange::Full);
+    }
+
+    #[test]
+    fn refuses_ranges_past_the_end() {
+        assert_eq!(parse_range("bytes=1000-", 1000), ByteRange::Unsatisfiable);
+        assert_eq!(parse_range("bytes=0-10", 0), ByteRange::Unsatisfiable);
+    }
+
+    #[test]
+    fn only_display_safe_types_are_inline() {
+        assert!(inline_safe("image/png"));
+        assert!(inline_safe("application/pdf"));
+        assert!(inline_safe("Text/Plain; charset=utf-8"));
+        assert!(!inline_safe("image/svg+xml"));
+        assert!(!inline_safe("text/html"));
+        assert!(!inline_safe("application/octet-stream"));
+    }
+}
@@ -1,7 +1,8 @@
 use std::collections::HashMap;
+use std::io;
 use std::sync::Arc;
-use axum::extract::{Query, State};
-use axum::http::StatusCode;
+use axum::extract::{Multipart, Path, Query, State};
+use axum::http::{header, HeaderMap, StatusCode};
 use axum::Json;
 use axum::response::Response;
 use crate::context::user_context::UserContext;
@@ -18,94 +19,167 @@ use serde::{Serialize, Deserialize};
 use crate::controllers::auth_controller::handle_response;
 use serde::{Serializer, Deserializer};
 use serde_qs::from_str;
-use crate::AppState;
+use crate::{AppState, Item};
+use crate::error::storage_error::StorageError;
+use crate::models::permission_model::Role;
+use crate::models::share_model::SharedItemType;
+use crate::services::blob_services::limited;
+use crate::services::listing_service::{next_cursor_headers, ListParams};
+use crate::services::naming_service::{valid_name, ConflictPolicy};
 
 
 #[derive(Deserialize, Debug)]
 pub struct MyQueryParams {
     pub file_type: Option<String>,
+    pub drive_id: Option<ObjectId>,
 }
 
-pub async fn get_files(ctx: Result<UserContext, StatusCode>, state: State<Arc<AppState>>, Query(query_params): Query<MyQueryParams>) -> Result<Json<Vec<File>>, StatusCode>{
+#[derive(Deserialize, Debug)]
+pub struct RenameRequest {
+    pub name: String,
+    pub conflict: Option<ConflictPolicy>,
+}
+
+pub async fn get_files(ctx: Result<UserContext, StatusCode>, state: State<Arc<AppState>>, Query(query_params): Query<MyQueryParams>, Query(list): Query<ListParams>) -> Result<(HeaderMap, Json<Vec<File>>), StorageError>{
     match ctx {
         Ok(user_context) => {
 
-            let mut filter = doc! {"user_id": user_context.user_id};
+            let owner_id = state.authorize_root(&user_context.user_id, query_params.drive_id, Role::Viewer).await?.id;
+
+            let mut filter = doc! {"user_id": owner_id};
 
             match query_params.file_type {
                 Some(file_type) => {
-                    filter = doc! {"user_id": user_context.user_id, "file_type": file_type}
+                    filter = doc! {"user_id": owner_id, "file_type": file_type}
                 },
                 None => ()
             }
 
-            let files = state.file_collection.get_file(filter).await;
+            let listing = state.list_items(Some(filter), None, &list).await?;
 
-            match files {
-                Ok(files) => {
-                    return Ok(Json(files));
-                },
-                Err(_) => {
-                    return Err(StatusCode::BAD_REQUEST);
-                }
-            }
+            let files = listing.items.into_iter().filter_map(|item| match item {
+                Item::File(file) => Some(file),
+                Item::Folder(_) => None
+            }).collect::<Vec<_>>();
+
+            Ok((next_cursor_headers(&listing.next_cursor), Json(files)))
 
 
         },
         Err(err) => {
-            return Err(err);
+            Err(err.into())
         }
     }
 
 
 }
 
-pub async fn upload_file(ctx: UserContext, state: State<Arc<AppState>>, params: Query<Vec<(String, ObjectId)>>, mut file: Json<File>) -> Result<Json<Vec<File>>, StatusCode>{
+pub async fn upload_file(ctx: UserContext, state: State<Arc<AppState>>, params: Query<Vec<(String, ObjectId)>>, mut file: Json<File>) -> Result<Json<Vec<File>>, StorageError>{
 
-    let new_file = state.file_collection.create_file(file, ctx.user_id).await;
+    let folder_id = params.0.to_vec().iter().filter(|obj| obj.0 == "folder_id").map(|obj| obj.1).collect::<Vec<_>>();
+    let drive_id = params.0.iter().find(|obj| obj.0 == "drive_id").map(|obj| obj.1);
+    file.id = None;
+    file.user_id = None;
+    file.folder_id = folder_id.first().cloned();
+    file.file_name = file.file_name.trim().to_string();
+
+    if !valid_name(&file.file_name) {
+        return Err(StatusCode::BAD_REQUEST.into());
+    }
+
+    // uploading into a folder shared with the user puts the file in the owner's drive
+    let owner = state.authorize_location(&ctx.user_id, file.folder_id, drive_id, Role::Editor).await?;
+
+    if state.locate_blob(&mut file, &ctx.user_id, &owner.id).await.is_err() {
+        return Err(StatusCode::BAD_REQUEST.into());
+    }
+
+    let hash = file.content_hash.clone().unwrap_or_default();
+
+    if let Err(err) = state.check_quota(&owner, file.size).await {
+        let _ = state.release_blob(&hash).await;
+        return Err(err);
+    }
+
+    let new_file = state.store_file(file.0, owner).await;
 
 
     return match new_file {
-        Ok(file) => {
-            let folder_id = params.0.to_vec().iter().filter(|obj| obj.0 == "folder_id").map(|obj| obj.1).collect::<Vec<_>>();
+        Ok(_) => {
+            let filter = doc! {"user_id": ctx.user_id, "folder_id": None::<ObjectId>};
+            let files = state.file_collection.get_file(filter).await.unwrap_or(vec![]);
+
+            Ok(Json(files))
+        },
+        Err(_) => {
+            let _ = state.release_blob(&hash).await;
+            Err(StatusCode::BAD_REQUEST.into())
+        }
+    }
+}
 
+// Same as upload_file, but the bytes come through us as multipart/form-data and are
+// streamed straight into the blob store, so size and location are never taken from the client.
+// Each file is cut off while streaming as soon as it goes over the owner's remaining quota.
+pub async fn upload_multipart_file(ctx: UserContext, state: State<Arc<AppState>>, params: Query<Vec<(String, ObjectId)>>, headers: HeaderMap, mut multipart: Multipart) -> Result<Json<Vec<File>>, StorageError>{
+    let folder_id = params.0.to_vec().iter().filter(|obj| obj.0 == "folder_id").map(|obj| obj.1).collect::<Vec<_>>();
+    let drive_id = params.0.iter().find(|obj| obj.0 == "drive_id").map(|obj| obj.1);
+    let owner = state.authorize_location(&ctx.user_id, folder_id.first().cloned(), drive_id, Role::Editor).await?;
 
-            if !folder_id.is_empty() {
-                let update_file_filter = doc! {"_id": file.inserted_id.as_object_id()};
+    // the whole form is a little bigger than the files in it, good enough to refuse early
+    let content_length = headers.get(header::CONTENT_LENGTH).and_then(|length| length.to_str().ok()).and_then(|length| length.parse::<u64>().ok());
 
-                let update_file = doc! {
-                    "$set":{
-                        "folder_id": folder_id.get(0).unwrap()
-                        }
-                };
+    if let Some(content_length) = content_length {
+        state.check_quota(&owner, content_length).await?;
+    }
 
-                state.file_collection.update_folder(update_file_filter, update_file).await;
+    while let Some(field) = multipart.next_field().await.map_err(|_| StorageError::from(StatusCode::BAD_REQUEST))? {
 
+        let file_name = match field.file_name() {
+            Some(file_name) => file_name.trim().to_string(),
+            None => continue
+        };
 
-                let update_folder_filter = doc! {
-                    "_id": folder_id.get(0).unwrap(),
-                };
+        if !valid_name(&file_name) {
+            return Err(StatusCode::BAD_REQUEST.into());
+        }
 
-                let update_folder = doc! {
-                    "$push": {
-                        "files": file.inserted_id.as_object_id(),
-                    },
-                };
+        let file_type = field.content_type().unwrap_or("application/octet-stream").to_string();
 
-                state.folder_collection.update_folder(update_folder_filter, update_folder).await;
-            }
+        // a missing or understated Content-Length must not let a file past the owner's quota
+        let available = match state.get_quota_usage(&owner).await {
+            Ok(usage) => usage.available_bytes,
+            Err(_) => return Err(StatusCode::INTERNAL_SERVER_ERROR.into())
+        };
 
+        let body = limited(field.map_err(io::Error::other).boxed(), available);
 
+        let blob = match state.ingest_blob(body).await {
+            Ok(blob) => blob,
+            Err(err) if err.kind() == io::ErrorKind::InvalidInput => return Err(StatusCode::PAYLOAD_TOO_LARGE.into()),
+            Err(_) => return Err(StatusCode::BAD_REQUEST.into())
+        };
 
-            let filter = doc! {"user_id": ctx.user_id, "folder_id": None::<ObjectId>};
-            let mut files = state.file_collection.get_file(filter).await.unwrap_or(vec![]);
+        if let Err(err) = state.check_quota(&owner, blob.size).await {
+            let _ = state.release_blob(&blob.hash).await;
+            return Err(err);
+        }
 
-            Ok(Json(files))
-        },
-        Err(_) => {
-            Err(StatusCode::BAD_REQUEST)
+        let mut file = File::from_blob(file_name, file_type, &blob, state.blob_store.location(&blob.key));
+        file.folder_id = folder_id.first().cloned();
+
+        let new_file = state.store_file(file, owner).await;
+
+        if new_file.is_err() {
+            let _ = state.release_blob(&blob.hash).await;
+            return Err(StatusCode::BAD_REQUEST.into());
         }
     }
+
+    let filter = doc! {"user_id": ctx.user_id, "folder_id": None::<ObjectId>};
+    let files = state.file_collection.get_file(filter).await.unwrap_or(vec![]);
+
+    Ok(Json(files))
 }
 
 #[derive(Debug, Deserialize)]
@@ -113,37 +187,36 @@ pub struct DeleteParams{
     ids: ObjectId
 }
 
-pub async fn delete_file(ctx: UserContext, state: State<Arc<AppState>>, params: Query<Vec<(String, ObjectId)>>) -> Result<Json<Vec<File>>, StatusCode>{
+pub async fn rename_file(ctx: UserContext, state: State<Arc<AppState>>, Path(file_id): Path<ObjectId>, request: Json<RenameRequest>) -> Result<Json<File>, StorageError>{
+    if !valid_name(&request.name) {
+        return Err(StatusCode::BAD_REQUEST.into());
+    }
 
+    let owner_id = state.authorize(&ctx.user_id, SharedItemType::File, &file_id, Role::Editor).await?.id;
 
-    let file_ids = params.0.to_vec().iter().filter(|obj| obj.0 == "ids").map(|obj| obj.1).collect::<Vec<_>>();
-    let folder_id = params.0.to_vec().iter().filter(|obj| obj.0 == "folder_id").map(|obj| obj.1).collect::<Vec<_>>();
+    let file = state.rename_file(&owner_id, &file_id, &request.name, request.conflict.unwrap_or_default()).await?;
+    Ok(Json(file))
+}
 
-    let update_filter = doc! {"_id": folder_id.get(0), "user_id": ctx.user_id};
+pub async fn delete_file(ctx: UserContext, state: State<Arc<AppState>>, params: Query<Vec<(String, ObjectId)>>) -> Result<Json<Vec<File>>, StorageError>{
 
-    let update = doc! {
-        "$pull": {
-            "files": {
-                "$in": file_ids
-            }
-        },
-    };
-
-    state.folder_collection.update_folder(update_filter, update).await;
-    let delete = state.file_collection.delete_files(&params, &ctx.user_id).await;
+    // shared files go to their owner's trash
+    let mut by_owner: HashMap<ObjectId, Vec<ObjectId>> = HashMap::new();
 
+    for (_, file_id) in params.0.iter().filter(|obj| obj.0 == "ids") {
+        let owner_id = state.authorize(&ctx.user_id, SharedItemType::File, file_id, Role::Editor).await?.id;
+        by_owner.entry(owner_id).or_default().push(*file_id);
+    }
 
-    match delete {
-        Ok(_) => {
-            let filter = doc! {"user_id": ctx.user_id};
-            let files = state.file_collection.get_file(filter).await.unwrap_or(vec![]);
-            return Ok(Json(files));
-        }
-        Err(_) => {
-            Err(StatusCode::BAD_REQUEST)
-        }
+    for (owner_id, file_ids) in by_owner {
+        let deleted_files = state.file_collection.get_file(doc! {"_id": {"$in": file_ids}, "user_id": owner_id}).await?;
+        state.trash_files(deleted_files, &owner_id).await?;
     }
 
+    let filter = doc! {"user_id": ctx.user_id};
+    let files = state.file_collection.get_file(filter).await.unwrap_or(vec![]);
+    Ok(Json(files))
+
 
 }
 
@@ -0,0 +1,231 @@
+use std::env;
+use std::io;
+use std::sync::Arc;
+use axum::body::Bytes;
+use axum::extract::{Multipart, Path, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::Json;
+use bson::doc;
+use bson::oid::ObjectId;
+use chrono::{DateTime, Utc};
+use dotenv::dotenv;
+use futures::{stream, StreamExt, TryStreamExt};
+use serde::{Deserialize, Serialize};
+use crate::AppState;
+use crate::context::user_context::UserContext;
+use crate::error::storage_error::StorageError;
+use crate::models::file_model::{File, Uploader};
+use crate::models::file_request_model::{FileRequest, FileRequestInfo};
+use crate::models::owner_model::OwnerKind;
+use crate::services::blob_services::limited;
+use crate::services::file_request_service::sniffed_type;
+use crate::services::naming_service::valid_name;
+
+
+// room for the form around the files: boundaries, part headers, name and email
+const FORM_OVERHEAD: u64 = 64 * 1024;
+
+// how much of a file is looked at to tell its type
+const SNIFF_BYTES: usize = 512;
+
+// Upper bound on a whole anonymous upload request, whatever the request itself allows.
+pub fn file_request_body_limit() -> usize {
+    dotenv().ok();
+    env::var("FILE_REQUEST_MAX_BODY_BYTES").ok().and_then(|size| size.parse::<usize>().ok()).unwrap_or(1024 * 1024 * 1024)
+}
+
+
+#[derive(Deserialize, Debug)]
+pub struct CreateFileRequest {
+    pub folder_id: ObjectId,
+    pub title: Option<String>,
+    pub require_uploader: Option<bool>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub max_file_size: Option<i64>,
+    pub allowed_types: Option<Vec<String>>,
+    pub max_files: Option<i64>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ReceivedFile {
+    pub file_name: String,
+    pub size: u64,
+}
+
+pub async fn create_file_request(ctx: UserContext, state: State<Arc<AppState>>, request: Json<CreateFileRequest>) -> Result<Json<FileRequest>, StorageError>{
+    if request.max_file_size.map(|max_file_size| max_file_size < 1).unwrap_or(false) || request.max_files.map(|max_files| max_files < 1).unwrap_or(false) {
+        return Err(StatusCode::BAD_REQUEST.into());
+    }
+
+    let file_request = FileRequest{
+        id: None,
+        token: String::new(),
+        user_id: ctx.user_id,
+        owner_kind: OwnerKind::User,
+        created_by: ctx.user_id,
+        folder_id: request.folder_id,
+        title: request.title.clone(),
+        require_uploader: request.require_uploader.unwrap_or(false),
+        expires_at: request.expires_at.map(bson::DateTime::from_chrono),
+        max_file_size: request.max_file_size,
+        allowed_types: request.allowed_types.clone(),
+        max_files: request.max_files,
+        files: 0,
+        revoked_at: None,
+        created_at: bson::DateTime::now(),
+    };
+
+    let file_request = state.create_file_request(&ctx.user_id, file_request).await?;
+    Ok(Json(file_request))
+}
+
+pub async fn get_file_requests(ctx: UserContext, state: State<Arc<AppState>>) -> Result<Json<Vec<FileRequest>>, StatusCode>{
+    let filter = doc! {"$or": [{"user_id": ctx.user_id}, {"created_by": ctx.user_id}]};
+
+    match state.file_request_collection.get_file_requests(filter).await {
+        Ok(requests) => Ok(Json(requests)),
+        Err(_) => Err(StatusCode::BAD_REQUEST)
+    }
+}
+
+pub async fn revoke_file_request(ctx: UserContext, state: State<Arc<AppState>>, Path(request_id): Path<ObjectId>) -> Result<StatusCode, StatusCode>{
+    let filter = doc! {"_id": request_id, "$or": [{"user_id": ctx.user_id}, {"created_by": ctx.user_id}], "revoked_at": null};
+
+    match state.file_request_collection.update_file_request(filter, doc! {"$set": {"revoked_at": bson::DateTime::now()}}).await {
+        Ok(result) if result.matched_count == 0 => Err(StatusCode::NOT_FOUND),
+        Ok(_) => Ok(StatusCode::NO_CONTENT),
+        Err(_) => Err(StatusCode::BAD_REQUEST)
+    }
+}
+
+// Public, what the upload page needs to know before sending anything.
+pub async fn open_file_request(state: State<Arc<AppState>>, Path(token): Path<String>) -> Result<Json<FileRequestInfo>, StorageError>{
+    let request = state.open_file_request(&token).await?;
+    Ok(Json(request.into()))
+}
+
+// Public, multipart/form-data like upload_multipart_file. The uploader's "name" and "email" fields
+// have to come before the files in the form. Files are cut off while streaming as soon as they go
+// over the size limit of the request or the owner's remaining quota.
+pub async fn upload_to_file_request(state: State<Arc<AppState>>, Path(token): Path<String>, headers: HeaderMap, mut multipart: Multipart) -> Result<Json<Vec<ReceivedFile>>, StorageError>{
+    let request = state.open_file_request(&token).await?;
+
+    let content_length = headers.get(header::CONTENT_LENGTH).and_then(|length| length.to_str().ok()).and_then(|length| length.parse::<u64>().ok());
+
+    let content_length = match content_length {
+        Some(content_length) => content_length,
+        None => return Err(StatusCode::LENGTH_REQUIRED.into())
+    };
+
+    let files = content_length.saturating_sub(FORM_OVERHEAD);
+
+    if let (Some(max_file_size), Some(max_files)) = (request.max_file_size, request.max_files) {
+        if files > (max_file_size as u64).saturating_mul((max_files - request.files).max(0) as u64) {
+            return Err(StatusCode::PAYLOAD_TOO_LARGE.into());
+        }
+    }
+
+    state.check_quota(&request.owner(), files).await?;
+
+    let mut name: Option<String> = None;
+    let mut email: Option<String> = None;
+    let mut received = vec![];
+
+    while let Some(field) = multipart.next_field().await.map_err(|_| StorageError::from(StatusCode::BAD_REQUEST))? {
+
+        let file_name = match field.file_name() {
+            Some(file_name) if valid_name(file_name) => file_name.trim().to_string(),
+            Some(_) => return Err(StatusCode::BAD_REQUEST.into()),
+            None => {
+                let field_name = field.name().unwrap_or_default().to_string();
+                let value = field.text().await.map_err(|_| StorageError::from(StatusCode::BAD_REQUEST))?.trim().to_string();
+
+                match field_name.as_str() {
+                    "name" if !value.is_empty() => name = Some(value),
+                    "email" if value.contains('@') => email = Some(value),
+                    _ => ()
+                }
+                continue;
+            }
+        };
+
+        if request.require_uploader && (name.is_none() || email.is_none()) {
+            return Err(StatusCode::BAD_REQUEST.into());
+        }
+
+        let declared_type = field.content_type().unwrap_or("application/octet-stream").to_string();
+
+        let available = match state.get_quota_usage(&request.owner()).await {
+            Ok(usage) => usage.available_bytes,
+            Err(_) => return Err(StatusCode::INTERNAL_SERVER_ERROR.into())
+        };
+        let limit = request.max_file_size.map(|max_file_size| max_file_size as u64).unwrap_or(u64::MAX).min(available);
+
+        let mut body = limited(field.map_err(io::Error::other).boxed(), limit);
+
+        // the type is decided by the content, not by what the uploader claims
+        let mut head: Vec<Bytes> = vec![];
+        let mut head_size = 0;
+
+        while head_size < SNIFF_BYTES {
+            match body.next().await {
+                Some(Ok(chunk)) => {
+                    head_size += chunk.len();
+                    head.push(chunk);
+                },
+                Some(Err(err)) if err.kind() == io::ErrorKind::InvalidInput => return Err(StatusCode::PAYLOAD_TOO_LARGE.into()),
+                Some(Err(_)) => return Err(StatusCode::BAD_REQUEST.into()),
+                None => break
+            }
+        }
+
+        let file_type = sniffed_type(&declared_type, &head.concat());
+
+        if !request.allows_type(&file_type) {
+            return Err(StatusCode::UNSUPPORTED_MEDIA_TYPE.into());
+        }
+
+        state.take_file_slot(&request).await?;
+
+        let body = stream::iter(head.into_iter().map(Ok)).chain(body).boxed();
+
+        let blob = match state.ingest_blob(body).await {
+            Ok(blob) => blob,
+            Err(err) => {
+                let _ = state.release_file_slot(&request).await;
+
+                if err.kind() == io::ErrorKind::InvalidInput {
+                    return Err(StatusCode::PAYLOAD_TOO_LARGE.into());
+                }
+                return Err(StatusCode::BAD_REQUEST.into());
+            }
+        };
+
+        // other uploads to the same owner may have used up the quota in the meantime
+        if let Err(err) = state.check_quota(&request.owner(), blob.size).await {
+            let _ = state.release_blob(&blob.hash).await;
+            let _ = state.release_file_slot(&request).await;
+            return Err(err);
+        }
+
+        let mut file = File::from_blob(file_name, file_type, &blob, state.blob_store.location(&blob.key));
+        file.uploaded_by = Some(Uploader{ name: name.clone(), email: email.clone(), file_request_id: request.id.unwrap() });
+
+        let size = file.size;
+        let file_name = file.file_name.clone();
+
+        if state.store_requested_file(&request, file).await.is_err() {
+            let _ = state.release_blob(&blob.hash).await;
+            let _ = state.release_file_slot(&request).await;
+            return Err(StatusCode::BAD_REQUEST.into());
+        }
+
+        received.push(ReceivedFile{ file_name, size });
+    }
+
+    if received.is_empty() {
+        return Err(StatusCode::BAD_REQUEST.into());
+    }
+
+    Ok(Json(received))
+}
@@ -1,129 +1,171 @@
 use std::fs::FileType;
+use std::collections::HashSet;
 use std::sync::Arc;
-use axum::extract::{Query, State};
-use axum::http::StatusCode;
+use axum::extract::{Path, Query, State};
+use axum::http::{HeaderMap, StatusCode};
 use axum::{Extension, Json};
 use bson::doc;
 use mongodb::bson::oid::ObjectId;
 use chrono::Utc;
-use crate::AppState;
+use serde::Deserialize;
+use crate::{AppState, Item};
+use crate::error::storage_error::StorageError;
 use crate::context::user_context::UserContext;
+use crate::controllers::file_controllers::RenameRequest;
+use crate::controllers::org_controllers::DriveParams;
 use crate::models::file_model::File;
-use crate::models::folder_model::{Folder, FolderJSON, FolderType};
+use crate::models::folder_model::{Breadcrumb, Folder, FolderJSON, FolderType};
+use crate::models::owner_model::Owner;
 use crate::services::file_services::FileCollection;
 use crate::services::folder_service::FolderCollection;
-use futures::future::{BoxFuture, Join};
-use futures::{FutureExt};
-
-
-
-async fn save_files_to_db(state: &State<Arc<AppState>>, files: &mut [File], user_id: &ObjectId, folder_id: Option<ObjectId>) -> mongodb::error::Result<Vec<ObjectId>>{
-    let mut files_array: Vec<ObjectId> = vec![];
-
-    for file in files{
-        file.folder_id = folder_id;
-        let inserted_file = state.file_collection.create_file(Json(file.clone()), user_id.clone()).await.unwrap();
+use crate::models::permission_model::Role;
+use crate::models::share_model::SharedItemType;
+use crate::services::listing_service::{next_cursor_headers, ListParams};
+use crate::services::naming_service::valid_name;
+use crate::services::tree_service::child_path;
+use futures::future::{Join};
+
+
+
+// Flattens an uploaded FolderJSON into Folder and File documents with fresh ids. Children only
+// point at their parent, a folder's files and folders are never stored on it.
+fn save_folders_to_db(folder: &mut FolderJSON, parent_path: Option<&str>, owner: &Owner, folders: &mut Vec<Folder>, files: &mut Vec<File>) {
+    let now = Utc::now();
+    let folder_id = Some(ObjectId::new());
+
+    folder.id = folder_id;
+    folder.path = Some(child_path(parent_path, &folder.folder_name));
+
+    let mut new_folder = Folder::new();
+
+    new_folder.id = folder_id;
+    new_folder.folder_name = folder.folder_name.clone();
+    new_folder.folder_type = folder.folder_type.clone();
+    new_folder.user_id = Some(owner.id);
+    new_folder.owner_kind = owner.kind;
+    new_folder.parent_id = folder.parent_id;
+    new_folder.path = folder.path.clone();
+    new_folder.created_at = Some(now);
+    new_folder.updated_at = Some(now);
+
+    folders.push(new_folder);
+
+    for file in folder.files.iter().flatten() {
+        // content_hash is checked by locate_blob, nothing else about the content is taken from the client
+        let mut new_file = file.clone();
+
+        new_file.id = Some(ObjectId::new());
+        new_file.user_id = Some(owner.id);
+        new_file.owner_kind = owner.kind;
+        new_file.original_file_name = Some(file.file_name.clone());
+        new_file.folder_id = folder_id;
+        new_file.created_at = Some(now);
+        new_file.updated_at = Some(now);
+        new_file.path = Some(child_path(folder.path.as_deref(), &file.file_name));
+
+        files.push(new_file);
+    }
 
-        files_array.push(inserted_file.inserted_id.as_object_id().unwrap());
+    for subfolder in folder.folders.iter_mut().flatten() {
+        subfolder.parent_id = folder_id;
+        subfolder.folder_type = Some(FolderType::Subfolder);
 
+        save_folders_to_db(subfolder, folder.path.as_deref(), owner, folders, files);
     }
-
-    Ok(files_array)
 }
 
+// Trims every name of an uploaded tree and checks it, siblings of the same kind need distinct names.
+fn clean_names(folder: &mut FolderJSON) -> bool {
+    folder.folder_name = folder.folder_name.trim().to_string();
 
+    let mut file_names = HashSet::new();
 
-fn save_folders_to_db<'a>(state: &'a State<Arc<AppState>>, mut folder: &'a mut FolderJSON, user_id: &'a ObjectId) -> BoxFuture<'a, mongodb::error::Result<ObjectId>> {
-    async move{
-
-        let mut new_folder = Folder::new();
-
-        let folder_id = Some(ObjectId::new());
+    for file in folder.files.iter_mut().flatten() {
+        file.file_name = file.file_name.trim().to_string();
 
-        folder.id = folder_id;
-
-        new_folder.id = folder_id.clone();
-
-        new_folder.folder_name = folder.folder_name.clone();
-        new_folder.folder_type = folder.folder_type.clone();
-        new_folder.user_id = Some(user_id.clone());
-        new_folder.parent_id = folder.parent_id;
-        new_folder.path = folder.path.clone();
-
-        if let Some(files) = &mut folder.files{
-
-            let mut files_array = save_files_to_db(&state, files, &user_id, folder_id).await.unwrap_or(vec![]);
-
-            new_folder.files = Some(files_array);
-
-        }else{
-            new_folder.files = Some(Vec::new());
+        if !valid_name(&file.file_name) || !file_names.insert(file.file_name.clone()) {
+            return false;
         }
+    }
 
+    let mut folder_names = HashSet::new();
 
-        if let Some(subfolders) = &mut folder.folders{
-
-            if subfolders.is_empty() {
-                new_folder.folders = Some(Vec::new());
-            }
-
-            let mut folders_array: Vec<ObjectId> = vec![];
-
-            for subfolder in subfolders {
-
-                subfolder.parent_id = folder.id.clone();
-                subfolder.folder_type = Some(FolderType::Subfolder);
-
-
-                save_folders_to_db(&state, subfolder, &user_id).await.unwrap();
-                folders_array.push(subfolder.id.unwrap());
-                new_folder.folders = Some(folders_array.clone());
-
-            }
-
-        }else{
-            new_folder.folders = Some(Vec::new());
+    for subfolder in folder.folders.iter_mut().flatten() {
+        if !clean_names(subfolder) || !folder_names.insert(subfolder.folder_name.clone()) {
+            return false;
         }
+    }
 
-        let inserted_id = state.folder_collection.create_folder(&mut Json(new_folder.clone()), &user_id).await.unwrap();
+    valid_name(&folder.folder_name)
+}
 
+fn folder_json_size(folder: &FolderJSON) -> u64 {
+    let files_size = folder.files.iter().flatten().map(|file| file.size).sum::<u64>();
+    let folders_size = folder.folders.iter().flatten().map(folder_json_size).sum::<u64>();
 
-        Ok(inserted_id.inserted_id.as_object_id().unwrap())
-    }.boxed()
+    files_size + folders_size
+}
 
+async fn release_hashes(state: &AppState, hashes: &[String]) {
+    for hash in hashes {
+        let _ = state.release_blob(hash).await;
+    }
 }
 
-pub async fn create_folder(ctx: UserContext, state: State<Arc<AppState>>, params: Query<Vec<(String, ObjectId)>>, mut folder: Json<FolderJSON>) -> Result<Json<Vec<Folder>>, StatusCode>{
+pub async fn create_folder(ctx: UserContext, state: State<Arc<AppState>>, params: Query<Vec<(String, ObjectId)>>, mut folder: Json<FolderJSON>) -> Result<Json<Vec<Folder>>, StorageError>{
     let folder_id = params.0.to_vec().iter().filter(|obj| obj.0 == "folder_id").map(|obj| obj.1).collect::<Vec<_>>();
+    let drive_id = params.0.iter().find(|obj| obj.0 == "drive_id").map(|obj| obj.1);
 
-    if folder_id.is_empty() {
+    // a folder created inside a shared folder belongs to the owner of that folder
+    let owner = state.authorize_location(&ctx.user_id, folder_id.first().cloned(), drive_id, Role::Editor).await?;
+    let owner_id = owner.id;
 
-        folder.folder_type = Some(FolderType::Folder);
+    if !clean_names(&mut folder) {
+        return Err(StatusCode::BAD_REQUEST.into());
+    }
 
-        save_folders_to_db(&state, &mut folder, &ctx.user_id).await;
+    // sizes are only known once the blobs are located, this refuses obvious overruns early
+    state.check_quota(&owner, folder_json_size(&folder)).await?;
 
+    let parent_path = match folder_id.first() {
+        Some(parent_id) => match state.folder_collection.get_folder(doc! {"_id": parent_id, "user_id": owner_id}).await?.into_iter().next() {
+            Some(parent) => parent.path,
+            None => return Err(StatusCode::NOT_FOUND.into())
+        },
+        None => None
+    };
 
-    }else{
+    folder.folder_type = Some(if folder_id.is_empty() { FolderType::Folder } else { FolderType::Subfolder });
+    folder.parent_id = folder_id.first().cloned();
 
-        folder.folder_type = Some(FolderType::Subfolder);
-        folder.parent_id = Some(folder_id.get(0).unwrap().clone());
+    let mut folders = vec![];
+    let mut files = vec![];
 
-        let id = save_folders_to_db(&state, &mut folder, &ctx.user_id).await.unwrap();
+    save_folders_to_db(&mut folder, parent_path.as_deref(), &owner, &mut folders, &mut files);
 
-        let update_filter = doc! {"_id": folder_id.get(0).unwrap(), "user_id": ctx.user_id};
+    let mut located: Vec<String> = vec![];
 
-        let update = doc! {
-                    "$push": {
-                        "folders": id
-                    },
-            };
+    for file in files.iter_mut() {
+        if state.locate_blob(file, &ctx.user_id, &owner_id).await.is_err() {
+            release_hashes(&state, &located).await;
+            return Err(StatusCode::BAD_REQUEST.into());
+        }
+        located.extend(file.content_hash.clone());
+    }
 
-        state.folder_collection.update_folder(update_filter, update).await;
+    if let Err(err) = state.check_quota(&owner, files.iter().map(|file| file.size).sum()).await {
+        release_hashes(&state, &located).await;
+        return Err(err);
+    }
 
+    // the whole subtree is written at once or not at all
+    if let Err(err) = state.create_subtree(folders, files).await {
+        release_hashes(&state, &located).await;
+        return Err(err);
     }
 
 
-    let filter = doc! {"user_id": ctx.user_id, "folder_type": FolderType::Folder};
+    let filter = doc! {"user_id": owner_id, "folder_type": FolderType::Folder};
     let folder_to_display = state.folder_collection.get_folder(filter).await.unwrap_or(vec![]);
 
     Ok(Json(folder_to_display))
@@ -131,86 +173,108 @@ pub async fn create_folder(ctx: UserContext, state: State<Arc<AppState>>, params
 
 }
 
-pub async fn get_folders(ctx: UserContext, state: State<Arc<AppState>>) -> Result<Json<Vec<Folder>>, StatusCode>{
-    let filter = doc! {"user_id": ctx.user_id, "folder_type": FolderType::Folder};
+pub async fn get_folders(ctx: UserContext, state: State<Arc<AppState>>, Query(params): Query<DriveParams>, Query(list): Query<ListParams>) -> Result<(HeaderMap, Json<Vec<Folder>>), StorageError>{
+    let owner_id = state.authorize_root(&ctx.user_id, params.drive_id, Role::Viewer).await?.id;
 
-    let folder_to_display = state.folder_collection.get_folder(filter).await.unwrap_or(vec![]);
-    Ok(Json(folder_to_display))
+    let filter = doc! {"user_id": owner_id, "folder_type": FolderType::Folder};
+
+    let listing = state.list_items(None, Some(filter), &list).await?;
+
+    let mut folder_to_display = listing.items.into_iter().filter_map(|item| match item {
+        Item::Folder(folder) => Some(folder),
+        Item::File(_) => None
+    }).collect::<Vec<_>>();
+
+    state.with_stats(&owner_id, folder_to_display.iter_mut()).await?;
+
+    Ok((next_cursor_headers(&listing.next_cursor), Json(folder_to_display)))
 }
 
-pub async fn get_folder_details(ctx: UserContext, state: State<Arc<AppState>>, params: Query<Vec<(String, ObjectId)>>) -> Result<Json<Folder>, StatusCode>{
-    let folder = state.folder_collection.get_folder_by_id(&params, &ctx.user_id).await;
+pub async fn get_folder_details(ctx: UserContext, state: State<Arc<AppState>>, params: Query<Vec<(String, ObjectId)>>) -> Result<Json<Folder>, StorageError>{
+    let owner_id = match params.0.first() {
+        Some((_, folder_id)) => state.authorize(&ctx.user_id, SharedItemType::Folder, folder_id, Role::Viewer).await?.id,
+        None => return Err(StatusCode::BAD_REQUEST.into())
+    };
+
+    let folder = state.folder_collection.get_folder_by_id(&params, &owner_id).await;
 
     match folder {
-        Ok(folder) => {
-            Ok(Json(folder.get(0).unwrap().clone()))
+        Ok(mut folder) if !folder.is_empty() => {
+            state.with_stats(&owner_id, folder.iter_mut()).await?;
+            Ok(Json(folder.remove(0)))
+        }
+        Ok(_) => {
+            Err(StatusCode::NOT_FOUND.into())
         }
         Err(_) => {
-            Err(StatusCode::BAD_REQUEST)
+            Err(StatusCode::BAD_REQUEST.into())
         }
     }
 }
 
 
-fn delete_folder_from_db<'a>(state: &'a State<Arc<AppState>>, folders: &'a [Folder], user_id: &'a ObjectId) -> BoxFuture<'a, mongodb::error::Result<()>>{
-
-    async move{
+#[derive(Deserialize, Debug)]
+pub struct TreeParams {
+    // the folder to export, the whole drive when missing
+    pub id: Option<ObjectId>,
+    pub drive_id: Option<ObjectId>,
+    pub depth: Option<usize>,
+}
 
-        for folder in folders {
+pub async fn get_folder_tree(ctx: UserContext, state: State<Arc<AppState>>, Query(params): Query<TreeParams>) -> Result<Json<FolderJSON>, StorageError>{
+    let owner_id = state.authorize_location(&ctx.user_id, params.id, params.drive_id, Role::Viewer).await?.id;
 
-            if let Some(subfolders) = &folder.folders{
-                for subfolder in subfolders {
-                    let filter = doc! {"_id": subfolder, "user_id": user_id};
+    match state.folder_tree(params.id, &owner_id, params.depth).await {
+        Ok(Some(tree)) => Ok(Json(tree)),
+        Ok(None) => Err(StatusCode::NOT_FOUND.into()),
+        Err(_) => Err(StatusCode::BAD_REQUEST.into())
+    }
+}
 
-                    let mut folders = state.folder_collection.get_folder(filter).await.unwrap();
+pub async fn get_breadcrumbs(ctx: UserContext, state: State<Arc<AppState>>, Path(folder_id): Path<ObjectId>) -> Result<Json<Vec<Breadcrumb>>, StorageError>{
+    let owner_id = state.authorize(&ctx.user_id, SharedItemType::Folder, &folder_id, Role::Viewer).await?.id;
 
-                    delete_folder_from_db(&state, &folders, &user_id).await;
-                }
-            }
+    match state.folder_collection.get_breadcrumbs(&folder_id, &owner_id).await {
+        Ok(crumbs) if crumbs.is_empty() => Err(StatusCode::NOT_FOUND.into()),
+        Ok(crumbs) => Ok(Json(crumbs)),
+        Err(_) => Err(StatusCode::BAD_REQUEST.into())
+    }
+}
 
-            if let Some(files) = &folder.files{
-                for file in files {
-                    let file_filter = doc! {"_id": file, "user_id": user_id};
-                    state.file_collection.delete_one_file(file_filter).await;
-                }
-            }
+pub async fn rename_folder(ctx: UserContext, state: State<Arc<AppState>>, Path(folder_id): Path<ObjectId>, request: Json<RenameRequest>) -> Result<Json<Folder>, StorageError>{
+    if !valid_name(&request.name) {
+        return Err(StatusCode::BAD_REQUEST.into());
+    }
 
-            let update_filter = doc! {"_id": folder.parent_id, "user_id": user_id};
+    let owner_id = state.authorize(&ctx.user_id, SharedItemType::Folder, &folder_id, Role::Editor).await?.id;
 
-            let update = doc! {
-                    "$pull": {
-                        "folders": folder.id
-                    },
-            };
+    let folder = state.rename_folder(&owner_id, &folder_id, &request.name, request.conflict.unwrap_or_default()).await?;
+    Ok(Json(folder))
+}
 
-            let filter = doc! {"_id": folder.id, "user_id": user_id};
 
-            state.folder_collection.update_folder(update_filter, update).await;
-            state.folder_collection.delete_one_folder(filter).await;
+// Shared folders go to their owner's trash.
+async fn trash_folders(state: &State<Arc<AppState>>, folder_ids: Vec<ObjectId>, user_id: &ObjectId) -> Result<(), StorageError>{
 
+    for folder_id in folder_ids {
+        let owner_id = state.authorize(user_id, SharedItemType::Folder, &folder_id, Role::Editor).await?.id;
 
+        if let Some(folder) = state.folder_collection.get_folder(doc! {"_id": folder_id, "user_id": owner_id}).await?.into_iter().next() {
+            state.trash_folder(folder, &owner_id).await?;
         }
+    }
 
-        Ok(())
-    }.boxed()
+    Ok(())
 }
 
 
-pub async fn delete_folder(ctx: UserContext, state: State<Arc<AppState>>, params: Query<Vec<(String, ObjectId)>>) -> Result<Json<Vec<Folder>>, StatusCode>{
+pub async fn delete_folder(ctx: UserContext, state: State<Arc<AppState>>, params: Query<Vec<(String, ObjectId)>>) -> Result<Json<Vec<Folder>>, StorageError>{
 
-    let folders = state.folder_collection.get_folder_by_id(&params, &ctx.user_id).await;
+    let folder_ids = params.0.iter().map(|obj| obj.1).collect::<Vec<_>>();
 
+    trash_folders(&state, folder_ids, &ctx.user_id).await?;
 
-    let is_folders_deleted = delete_folder_from_db(&state, &folders.clone().unwrap(), &ctx.user_id).await;
-
-    match is_folders_deleted {
-        Ok(_) => {
-            let filter = doc! {"user_id": ctx.user_id, "folder_type": FolderType::Folder};
-            let folders = state.folder_collection.get_folder(filter).await.unwrap_or(vec![]);
-            return Ok(Json(folders));
-        }
-        Err(_) => {
-            Err(StatusCode::BAD_REQUEST)
-        }
-    }
+    let filter = doc! {"user_id": ctx.user_id, "folder_type": FolderType::Folder};
+    let folders = state.folder_collection.get_folder(filter).await.unwrap_or(vec![]);
+    Ok(Json(folders))
 }
\ No newline at end of file
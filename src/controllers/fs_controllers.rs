@@ -0,0 +1,43 @@
+use std::sync::Arc;
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::Json;
+use bson::doc;
+use crate::{AppState, Item};
+use crate::context::user_context::UserContext;
+use crate::models::file_model::File;
+use crate::models::folder_model::Folder;
+
+
+// "a//b/" and "/a/b" both become "/a/b", None when nothing is left
+fn normalize_path(path: &str) -> Option<String> {
+    let segments = path.split('/').filter(|segment| !segment.is_empty()).collect::<Vec<_>>();
+
+    if segments.is_empty() {
+        return None;
+    }
+
+    Some(format!("/{}", segments.join("/")))
+}
+
+// A trailing slash only matches folders, otherwise a file wins over a folder of the same path.
+pub async fn resolve_path(ctx: UserContext, state: State<Arc<AppState>>, Path(path): Path<String>) -> Result<Json<Item<File, Folder>>, StatusCode>{
+    let folder_only = path.ends_with('/');
+    let path = normalize_path(&path).ok_or(StatusCode::BAD_REQUEST)?;
+
+    let filter = doc! {"user_id": ctx.user_id, "path": &path};
+
+    if !folder_only {
+        match state.file_collection.get_file(filter.clone()).await {
+            Ok(files) => if let Some(file) = files.into_iter().next() {
+                return Ok(Json(Item::File(file)));
+            },
+            Err(_) => return Err(StatusCode::BAD_REQUEST)
+        }
+    }
+
+    match state.folder_collection.get_folder(filter).await {
+        Ok(folders) => folders.into_iter().next().map(|folder| Json(Item::Folder(folder))).ok_or(StatusCode::NOT_FOUND),
+        Err(_) => Err(StatusCode::BAD_REQUEST)
+    }
+}
@@ -0,0 +1,43 @@
+use std::env;
+use std::sync::Arc;
+use axum::extract::{Query, State};
+use axum::http::StatusCode;
+use axum::Json;
+use chrono::Duration;
+use dotenv::dotenv;
+use serde::Deserialize;
+use crate::AppState;
+use crate::context::user_context::UserContext;
+use crate::services::gc_service::GcReport;
+
+
+#[derive(Deserialize, Debug)]
+pub struct GcParams {
+    pub dry_run: Option<bool>,
+    pub grace_hours: Option<i64>,
+}
+
+pub fn gc_grace_period() -> Duration {
+    dotenv().ok();
+    let hours = env::var("GC_GRACE_HOURS").ok().and_then(|hours| hours.parse::<i64>().ok()).unwrap_or(24);
+    Duration::hours(hours)
+}
+
+// GC looks at every user's data, so only the emails listed in ADMIN_EMAILS may run it
+pub fn is_admin(ctx: &UserContext) -> bool {
+    dotenv().ok();
+    env::var("ADMIN_EMAILS").unwrap_or_default().split(',').any(|email| email.trim() == ctx.email)
+}
+
+pub async fn run_gc(ctx: UserContext, state: State<Arc<AppState>>, Query(params): Query<GcParams>) -> Result<Json<GcReport>, StatusCode>{
+    if !is_admin(&ctx) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let grace = params.grace_hours.map(Duration::hours).unwrap_or(gc_grace_period());
+
+    match state.collect_garbage(params.dry_run.unwrap_or(true), grace).await {
+        Ok(report) => Ok(Json(report)),
+        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR)
+    }
+}
@@ -1,4 +1,18 @@
 pub mod auth_controller;
 pub mod file_controllers;
 pub mod folder_controllers;
-pub mod dashboard_controllers;
\ No newline at end of file
+pub mod dashboard_controllers;
+pub mod download_controllers;
+pub mod presign_controllers;
+pub mod tus_controllers;
+pub mod gc_controllers;
+pub mod version_controllers;
+pub mod trash_controllers;
+pub mod tree_controllers;
+pub mod fs_controllers;
+pub mod search_controllers;
+pub mod share_controllers;
+pub mod permission_controllers;
+pub mod org_controllers;
+pub mod file_request_controllers;
+pub mod comment_controllers;
\ No newline at end of file
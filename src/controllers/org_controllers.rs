@@ -0,0 +1,101 @@
+use std::sync::Arc;
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::Json;
+use bson::doc;
+use bson::oid::ObjectId;
+use serde::Deserialize;
+use crate::AppState;
+use crate::context::user_context::UserContext;
+use crate::error::storage_error::StorageError;
+use crate::models::organization_model::{Drive, OrgRole, Organization};
+use crate::services::naming_service::valid_name;
+
+
+#[derive(Deserialize, Debug)]
+pub struct DriveParams {
+    // a shared drive to work in instead of the user's own one
+    pub drive_id: Option<ObjectId>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct OrganizationRequest {
+    pub name: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct MemberRequest {
+    pub email: String,
+    pub role: OrgRole,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct DriveRequest {
+    pub name: String,
+    pub quota: Option<u64>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct DriveUpdate {
+    pub name: Option<String>,
+    pub quota: Option<u64>,
+    pub versioning: Option<bool>,
+}
+
+pub async fn create_organization(ctx: UserContext, state: State<Arc<AppState>>, request: Json<OrganizationRequest>) -> Result<Json<Organization>, StorageError>{
+    if request.name.trim().is_empty() {
+        return Err(StatusCode::BAD_REQUEST.into());
+    }
+
+    let organization = state.create_organization(&ctx.user_id, &ctx.email, request.name.trim()).await?;
+    Ok(Json(organization))
+}
+
+pub async fn get_organizations(ctx: UserContext, state: State<Arc<AppState>>) -> Result<Json<Vec<Organization>>, StorageError>{
+    let organizations = state.organization_collection.get_organizations(doc! {"members.user_id": ctx.user_id}).await?;
+    Ok(Json(organizations))
+}
+
+pub async fn set_member(ctx: UserContext, state: State<Arc<AppState>>, Path(org_id): Path<ObjectId>, request: Json<MemberRequest>) -> Result<Json<Organization>, StorageError>{
+    state.set_org_member(&ctx.user_id, &org_id, request.email.trim(), request.role).await?;
+
+    let organization = state.authorize_org(&ctx.user_id, &org_id, OrgRole::Admin).await?;
+    Ok(Json(organization))
+}
+
+pub async fn remove_member(ctx: UserContext, state: State<Arc<AppState>>, Path((org_id, member_id)): Path<(ObjectId, ObjectId)>) -> Result<StatusCode, StorageError>{
+    state.remove_org_member(&ctx.user_id, &org_id, &member_id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn create_drive(ctx: UserContext, state: State<Arc<AppState>>, Path(org_id): Path<ObjectId>, request: Json<DriveRequest>) -> Result<Json<Drive>, StorageError>{
+    if !valid_name(&request.name) {
+        return Err(StatusCode::BAD_REQUEST.into());
+    }
+
+    let drive = state.create_drive(&ctx.user_id, &org_id, &request.name, request.quota).await?;
+    Ok(Json(drive))
+}
+
+pub async fn get_org_drives(ctx: UserContext, state: State<Arc<AppState>>, Path(org_id): Path<ObjectId>) -> Result<Json<Vec<Drive>>, StorageError>{
+    state.authorize_org(&ctx.user_id, &org_id, OrgRole::Member).await?;
+
+    let drives = state.drive_collection.get_drives(doc! {"org_id": org_id}).await?;
+    Ok(Json(drives))
+}
+
+// Admins rename drives and change their quota and versioning.
+pub async fn update_drive(ctx: UserContext, state: State<Arc<AppState>>, Path((org_id, drive_id)): Path<(ObjectId, ObjectId)>, request: Json<DriveUpdate>) -> Result<Json<Drive>, StorageError>{
+    if request.name.as_deref().map(|name| !valid_name(name)).unwrap_or(false) {
+        return Err(StatusCode::BAD_REQUEST.into());
+    }
+
+    let drive = state.update_drive(&ctx.user_id, &org_id, &drive_id, request.name.as_deref(), request.quota, request.versioning).await?;
+    Ok(Json(drive))
+}
+
+// Every drive the user can pick with `drive_id`, across all of their organizations.
+pub async fn get_drives(ctx: UserContext, state: State<Arc<AppState>>) -> Result<Json<Vec<Drive>>, StorageError>{
+    let drives = state.user_drives(&ctx.user_id).await?;
+    Ok(Json(drives))
+}
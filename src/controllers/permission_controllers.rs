@@ -0,0 +1,69 @@
+use std::sync::Arc;
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use axum::Json;
+use bson::doc;
+use bson::oid::ObjectId;
+use serde::Deserialize;
+use crate::AppState;
+use crate::context::user_context::UserContext;
+use crate::error::storage_error::StorageError;
+use crate::models::permission_model::{Permission, Role};
+use crate::models::share_model::SharedItemType;
+
+
+#[derive(Deserialize, Debug)]
+pub struct ItemParams {
+    // exactly one of file_id and folder_id
+    pub file_id: Option<ObjectId>,
+    pub folder_id: Option<ObjectId>,
+}
+
+impl ItemParams {
+    pub fn item(&self) -> Result<(SharedItemType, ObjectId), StorageError> {
+        match (self.file_id, self.folder_id) {
+            (Some(file_id), None) => Ok((SharedItemType::File, file_id)),
+            (None, Some(folder_id)) => Ok((SharedItemType::Folder, folder_id)),
+            _ => Err(StatusCode::BAD_REQUEST.into())
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct GrantRequest {
+    pub file_id: Option<ObjectId>,
+    pub folder_id: Option<ObjectId>,
+    pub email: String,
+    pub role: Role,
+}
+
+pub async fn grant_access(ctx: UserContext, state: State<Arc<AppState>>, request: Json<GrantRequest>) -> Result<Json<Permission>, StorageError>{
+    let (item_type, item_id) = ItemParams{ file_id: request.file_id, folder_id: request.folder_id }.item()?;
+
+    let permission = state.grant_access(&ctx.user_id, item_type, &item_id, request.email.trim(), request.role).await?;
+    Ok(Json(permission))
+}
+
+// Who has access to the item through a grant on it, grants on folders above it are listed there.
+pub async fn get_permissions(ctx: UserContext, state: State<Arc<AppState>>, Query(params): Query<ItemParams>) -> Result<Json<Vec<Permission>>, StorageError>{
+    let (item_type, item_id) = params.item()?;
+
+    state.authorize(&ctx.user_id, item_type, &item_id, Role::Viewer).await?;
+
+    let permissions = state.permission_collection.get_permissions(doc! {"item_id": item_id}).await?;
+    Ok(Json(permissions))
+}
+
+// Co-owners can take any grant away, grantees can give up their own.
+pub async fn revoke_permission(ctx: UserContext, state: State<Arc<AppState>>, Path(permission_id): Path<ObjectId>) -> Result<StatusCode, StorageError>{
+    let permission = state.permission_collection.get_permissions(doc! {"_id": permission_id}).await?
+        .into_iter().next()
+        .ok_or(StorageError::Status(StatusCode::NOT_FOUND))?;
+
+    if permission.grantee_id != ctx.user_id {
+        state.authorize(&ctx.user_id, permission.item_type, &permission.item_id, Role::CoOwner).await?;
+    }
+
+    state.permission_collection.delete_permissions(doc! {"_id": permission_id}).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,184 @@
+use std::env;
+use std::sync::Arc;
+use std::time::Duration;
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use axum::Json;
+use bson::doc;
+use bson::oid::ObjectId;
+use chrono::Utc;
+use dotenv::dotenv;
+use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
+use serde::{Deserialize, Serialize};
+use crate::AppState;
+use crate::error::storage_error::StorageError;
+use crate::context::user_context::UserContext;
+use crate::models::file_model::File;
+use crate::models::owner_model::Owner;
+use crate::models::permission_model::Role;
+use crate::models::share_model::SharedItemType;
+use crate::models::token_model::{TokenKind, UploadClaims};
+use crate::services::blob_services::new_blob_key;
+use crate::services::naming_service::valid_name;
+use crate::storage::blob_store::PresignMethod;
+
+
+#[derive(Debug, Deserialize)]
+pub struct PresignUploadRequest {
+    pub file_name: String,
+    pub file_type: String,
+    pub size: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PresignUploadResponse {
+    pub upload_url: String,
+    pub upload_token: String,
+    pub key: String,
+    pub expires_in: u64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FinalizeUploadRequest {
+    pub upload_token: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PresignDownloadResponse {
+    pub url: String,
+    pub expires_in: u64,
+}
+
+fn presign_ttl() -> Duration {
+    dotenv().ok();
+    let seconds = env::var("PRESIGN_TTL_SECS").ok().and_then(|ttl| ttl.parse::<u64>().ok()).unwrap_or(900);
+    Duration::from_secs(seconds)
+}
+
+fn jwt_secret() -> String {
+    dotenv().ok();
+    env::var("JWT_TOKEN").expect("JWT_TOKEN not found in env")
+}
+
+
+pub async fn presign_upload(ctx: UserContext, state: State<Arc<AppState>>, request: Json<PresignUploadRequest>) -> Result<Json<PresignUploadResponse>, StorageError>{
+    // the name rides along in the signed token, finalize_upload takes it as it is
+    let file_name = request.file_name.trim().to_string();
+
+    if !valid_name(&file_name) {
+        return Err(StatusCode::BAD_REQUEST.into());
+    }
+
+    // the upload lands in the user's own drive unless finalize_upload is told otherwise, checked again there
+    state.check_quota(&Owner::user(ctx.user_id), request.size).await?;
+
+    let ttl = presign_ttl();
+    let key = new_blob_key(&ctx.user_id);
+
+    let upload_url = match state.blob_store.presigned_url(PresignMethod::Put, &key, ttl, None) {
+        Some(url) => url,
+        None => return Err(StatusCode::NOT_IMPLEMENTED.into())
+    };
+
+    let claims = UploadClaims{
+        kind: TokenKind::Upload,
+        user_id: ctx.user_id,
+        key: key.clone(),
+        file_name,
+        file_type: request.file_type.clone(),
+        size: request.size,
+        exp: Utc::now().timestamp() + ttl.as_secs() as i64,
+    };
+
+    let upload_token = match encode(&Header::default(), &claims, &EncodingKey::from_secret(jwt_secret().as_ref())) {
+        Ok(token) => token,
+        Err(_) => return Err(StatusCode::INTERNAL_SERVER_ERROR.into())
+    };
+
+    Ok(Json(PresignUploadResponse{ upload_url, upload_token, key, expires_in: ttl.as_secs() }))
+}
+
+pub async fn finalize_upload(ctx: UserContext, state: State<Arc<AppState>>, params: Query<Vec<(String, ObjectId)>>, request: Json<FinalizeUploadRequest>) -> Result<Json<Vec<File>>, StorageError>{
+    let claims = match decode::<UploadClaims>(&request.upload_token, &DecodingKey::from_secret(jwt_secret().as_ref()), &Validation::default()) {
+        Ok(token_data) => token_data.claims,
+        Err(_) => return Err(StatusCode::BAD_REQUEST.into())
+    };
+
+    if claims.kind != TokenKind::Upload {
+        return Err(StatusCode::BAD_REQUEST.into());
+    }
+
+    if claims.user_id != ctx.user_id {
+        return Err(StatusCode::FORBIDDEN.into());
+    }
+
+    let folder_id = params.0.to_vec().iter().filter(|obj| obj.0 == "folder_id").map(|obj| obj.1).collect::<Vec<_>>();
+    let drive_id = params.0.iter().find(|obj| obj.0 == "drive_id").map(|obj| obj.1);
+
+    // uploading into a folder shared with the user puts the file in the owner's drive
+    let owner = state.authorize_location(&ctx.user_id, folder_id.first().cloned(), drive_id, Role::Editor).await?;
+
+    let blob = match state.blob_store.head(&claims.key).await {
+        Ok(Some(blob)) => blob,
+        Ok(None) => return Err(StatusCode::NOT_FOUND.into()),
+        Err(_) => return Err(StatusCode::BAD_GATEWAY.into())
+    };
+
+    if blob.size != claims.size {
+        let _ = state.blob_store.delete(&claims.key).await;
+        return Err(StatusCode::BAD_REQUEST.into());
+    }
+
+    if let Err(err) = state.check_quota(&owner, blob.size).await {
+        let _ = state.blob_store.delete(&claims.key).await;
+        return Err(err);
+    }
+
+    // finalizing twice with the same token must not register the object twice
+    let existing = state.file_collection.get_file(doc! {"aws_file_name": &claims.key, "user_id": owner.id}).await.unwrap_or(vec![]);
+
+    if !existing.is_empty() {
+        return Err(StatusCode::CONFLICT.into());
+    }
+
+    let blob = match state.adopt_blob(&claims.key).await {
+        Ok(blob) => blob,
+        Err(_) => return Err(StatusCode::BAD_GATEWAY.into())
+    };
+
+    let mut file = File::from_blob(claims.file_name, claims.file_type, &blob, state.blob_store.location(&blob.key));
+    file.folder_id = folder_id.first().cloned();
+
+    if state.store_file(file, owner).await.is_err() {
+        let _ = state.release_blob(&blob.hash).await;
+        return Err(StatusCode::BAD_REQUEST.into());
+    }
+
+    let filter = doc! {"user_id": ctx.user_id, "folder_id": None::<ObjectId>};
+    let files = state.file_collection.get_file(filter).await.unwrap_or(vec![]);
+
+    Ok(Json(files))
+}
+
+pub async fn presign_download(ctx: UserContext, state: State<Arc<AppState>>, Path(file_id): Path<ObjectId>) -> Result<Json<PresignDownloadResponse>, StorageError>{
+    let owner_id = state.authorize(&ctx.user_id, SharedItemType::File, &file_id, Role::Viewer).await?.id;
+    let filter = doc! {"_id": file_id, "user_id": owner_id};
+
+    let file = match state.file_collection.get_file(filter).await {
+        Ok(files) => files.into_iter().next(),
+        Err(_) => return Err(StatusCode::BAD_REQUEST.into())
+    };
+
+    let file = match file {
+        Some(file) => file,
+        None => return Err(StatusCode::NOT_FOUND.into())
+    };
+
+    let ttl = presign_ttl();
+    let file_name = file.original_file_name.clone().unwrap_or(file.file_name.clone());
+
+    match state.blob_store.presigned_url(PresignMethod::Get, &file.aws_file_name, ttl, Some(&file_name)) {
+        Some(url) => Ok(Json(PresignDownloadResponse{ url, expires_in: ttl.as_secs() })),
+        None => Err(StatusCode::NOT_IMPLEMENTED.into())
+    }
+}
@@ -0,0 +1,16 @@
+use std::sync::Arc;
+use axum::extract::{Query, State};
+use axum::Json;
+use crate::AppState;
+use crate::context::user_context::UserContext;
+use crate::error::storage_error::StorageError;
+use crate::models::permission_model::Role;
+use crate::services::search_service::{SearchQuery, SearchResults};
+
+
+pub async fn search(ctx: UserContext, state: State<Arc<AppState>>, Query(query): Query<SearchQuery>) -> Result<Json<SearchResults>, StorageError>{
+    let owner_id = state.authorize_location(&ctx.user_id, query.folder_id, query.drive_id, Role::Viewer).await?.id;
+
+    let results = state.search(&owner_id, &query).await?;
+    Ok(Json(results))
+}
@@ -0,0 +1,234 @@
+use std::sync::Arc;
+use axum::body::BoxBody;
+use axum::extract::{Path, Query, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::Json;
+use axum::response::Response;
+use bson::doc;
+use bson::oid::ObjectId;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use crate::{AppState, Item};
+use crate::context::user_context::UserContext;
+use crate::controllers::download_controllers::{file_content_response, ContentParams};
+use crate::error::storage_error::StorageError;
+use crate::models::file_model::File;
+use crate::models::folder_model::Folder;
+use crate::models::share_model::{ShareResponse, SharedItemType};
+use crate::services::listing_service::ListParams;
+
+
+#[derive(Deserialize, Debug)]
+pub struct CreateShareRequest {
+    // exactly one of file_id and folder_id
+    pub file_id: Option<ObjectId>,
+    pub folder_id: Option<ObjectId>,
+    pub password: Option<String>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub max_downloads: Option<i64>,
+}
+
+// Password protected links take the access token from `unlock_shared_item` in this header
+const SHARE_ACCESS_HEADER: &str = "x-share-access";
+
+#[derive(Deserialize, Debug)]
+pub struct ShareAccess {
+    // the access token for plain download links, which can't send headers
+    pub access_token: Option<String>,
+    // a folder below the shared one to list
+    pub folder_id: Option<ObjectId>,
+}
+
+impl ShareAccess {
+    fn token<'a>(&'a self, headers: &'a HeaderMap) -> Option<&'a str> {
+        headers.get(SHARE_ACCESS_HEADER).and_then(|value| value.to_str().ok()).or(self.access_token.as_deref())
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct UnlockShareRequest {
+    pub password: String,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ShareAccessResponse {
+    pub access_token: String,
+    pub expires_in: i64,
+}
+
+// Only what a link holder needs, no storage keys or owner ids
+#[derive(Serialize, Debug)]
+pub struct SharedEntry {
+    #[serde(rename = "_id")]
+    pub id: Option<ObjectId>,
+    pub name: String,
+    pub item_type: SharedItemType,
+    pub size: Option<u64>,
+    pub file_type: Option<String>,
+    #[serde(rename = "updatedAt")]
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct SharedContents {
+    pub item_type: SharedItemType,
+    pub name: String,
+    pub items: Vec<SharedEntry>,
+    pub next_cursor: Option<String>,
+}
+
+impl From<Item<File, Folder>> for SharedEntry {
+    fn from(item: Item<File, Folder>) -> Self {
+        match item {
+            Item::File(file) => Self {
+                id: file.id,
+                name: file.file_name,
+                item_type: SharedItemType::File,
+                size: Some(file.size),
+                file_type: Some(file.file_type),
+                updated_at: file.updated_at,
+            },
+            Item::Folder(folder) => Self {
+                id: folder.id,
+                name: folder.folder_name,
+                item_type: SharedItemType::Folder,
+                size: folder.stats.map(|stats| stats.size),
+                file_type: None,
+                updated_at: folder.updated_at,
+            }
+        }
+    }
+}
+
+
+pub async fn create_share(ctx: UserContext, state: State<Arc<AppState>>, request: Json<CreateShareRequest>) -> Result<Json<ShareResponse>, StorageError>{
+    let (item_type, item_id) = match (request.file_id, request.folder_id) {
+        (Some(file_id), None) => (SharedItemType::File, file_id),
+        (None, Some(folder_id)) => (SharedItemType::Folder, folder_id),
+        _ => return Err(StatusCode::BAD_REQUEST.into())
+    };
+
+    if request.max_downloads.map(|max_downloads| max_downloads < 1).unwrap_or(false) {
+        return Err(StatusCode::BAD_REQUEST.into());
+    }
+
+    let share = state.create_share(&ctx.user_id, item_type, item_id, request.password.as_deref(), request.expires_at, request.max_downloads).await?;
+    Ok(Json(share.into()))
+}
+
+pub async fn get_shares(ctx: UserContext, state: State<Arc<AppState>>) -> Result<Json<Vec<ShareResponse>>, StatusCode>{
+    let filter = doc! {"$or": [{"user_id": ctx.user_id}, {"created_by": ctx.user_id}]};
+
+    match state.share_collection.get_shares(filter).await {
+        Ok(shares) => Ok(Json(shares.into_iter().map(ShareResponse::from).collect())),
+        Err(_) => Err(StatusCode::BAD_REQUEST)
+    }
+}
+
+pub async fn revoke_share(ctx: UserContext, state: State<Arc<AppState>>, Path(share_id): Path<ObjectId>) -> Result<StatusCode, StatusCode>{
+    let filter = doc! {"_id": share_id, "$or": [{"user_id": ctx.user_id}, {"created_by": ctx.user_id}], "revoked_at": null};
+
+    match state.share_collection.update_share(filter, doc! {"$set": {"revoked_at": bson::DateTime::now()}}).await {
+        Ok(result) if result.matched_count == 0 => Err(StatusCode::NOT_FOUND),
+        Ok(_) => Ok(StatusCode::NO_CONTENT),
+        Err(_) => Err(StatusCode::BAD_REQUEST)
+    }
+}
+
+// Public, the password goes in the body once and is exchanged for a short lived access token.
+pub async fn unlock_shared_item(state: State<Arc<AppState>>, Path(token): Path<String>, request: Json<UnlockShareRequest>) -> Result<Json<ShareAccessResponse>, StorageError>{
+    let (access_token, expires_in) = state.unlock_share(&token, &request.password).await?;
+    Ok(Json(ShareAccessResponse{ access_token, expires_in }))
+}
+
+// Public, the token is the only credential besides the access token of password protected links.
+pub async fn open_shared_item(state: State<Arc<AppState>>, Path(token): Path<String>, Query(access): Query<ShareAccess>, Query(list): Query<ListParams>, headers: HeaderMap) -> Result<Json<SharedContents>, StorageError>{
+    let share = state.open_share(&token, access.token(&headers)).await?;
+
+    match share.item_type {
+        SharedItemType::File => {
+            let file = state.shared_file(&share, &share.item_id).await?;
+
+            Ok(Json(SharedContents{
+                item_type: SharedItemType::File,
+                name: file.file_name.clone(),
+                items: vec![Item::File(file).into()],
+                next_cursor: None,
+            }))
+        },
+        SharedItemType::Folder => {
+            let folder = state.shared_folder(&share, access.folder_id).await?;
+
+            let file_filter = doc! {"user_id": share.user_id, "folder_id": folder.id};
+            let folder_filter = doc! {"user_id": share.user_id, "parent_id": folder.id};
+            let mut listing = state.list_items(Some(file_filter), Some(folder_filter), &list).await?;
+            state.with_item_stats(&share.user_id, &mut listing.items).await?;
+
+            Ok(Json(SharedContents{
+                item_type: SharedItemType::Folder,
+                name: folder.folder_name,
+                items: listing.items.into_iter().map(SharedEntry::from).collect(),
+                next_cursor: listing.next_cursor,
+            }))
+        }
+    }
+}
+
+pub async fn download_shared_file(state: State<Arc<AppState>>, Path((token, file_id)): Path<(String, ObjectId)>, Query(access): Query<ShareAccess>, Query(params): Query<ContentParams>, headers: HeaderMap) -> Result<Response<BoxBody>, StorageError>{
+    let share = state.open_share(&token, access.token(&headers)).await?;
+    let file = state.shared_file(&share, &file_id).await?;
+
+    let inline = params.disposition.as_deref() == Some("inline");
+    let response = file_content_response(&state, &file, &headers, inline).await?;
+
+    // every body that starts at the first byte is another download, only resuming the rest of one is free
+    if starts_at_first_byte(&response) {
+        state.count_share_download(&share).await?;
+    }
+
+    Ok(response)
+}
+
+fn starts_at_first_byte(response: &Response<BoxBody>) -> bool {
+    match response.status() {
+        StatusCode::OK => true,
+        StatusCode::PARTIAL_CONTENT => response.headers().get(header::CONTENT_RANGE)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.starts_with("bytes 0-"))
+            .unwrap_or(true),
+        _ => false
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use axum::body::{boxed, BoxBody, Empty};
+    use axum::http::{header, StatusCode};
+    use axum::response::Response;
+    use super::starts_at_first_byte;
+
+    fn response(status: StatusCode, content_range: Option<&str>) -> Response<BoxBody> {
+        let mut builder = Response::builder().status(status);
+
+        if let Some(content_range) = content_range {
+            builder = builder.header(header::CONTENT_RANGE, content_range);
+        }
+
+        builder.body(boxed(Empty::new())).unwrap()
+    }
+
+    #[test]
+    fn counts_bodies_from_the_first_byte() {
+        assert!(starts_at_first_byte(&response(StatusCode::OK, None)));
+        assert!(starts_at_first_byte(&response(StatusCode::PARTIAL_CONTENT, Some("bytes 0-999/1000"))));
+        assert!(starts_at_first_byte(&response(StatusCode::PARTIAL_CONTENT, Some("bytes 0-0/1000"))));
+    }
+
+    #[test]
+    fn resuming_and_bodiless_responses_are_free() {
+        assert!(!starts_at_first_byte(&response(StatusCode::PARTIAL_CONTENT, Some("bytes 500-999/1000"))));
+        assert!(!starts_at_first_byte(&response(StatusCode::NOT_MODIFIED, None)));
+        assert!(!starts_at_first_byte(&response(StatusCode::RANGE_NOT_SATISFIABLE, Some("bytes */1000"))));
+    }
+}
@@ -0,0 +1,80 @@
+use std::sync::Arc;
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use axum::Json;
+use bson::doc;
+use bson::oid::ObjectId;
+use serde::Deserialize;
+use crate::AppState;
+use crate::context::user_context::UserContext;
+use crate::controllers::org_controllers::DriveParams;
+use crate::error::storage_error::StorageError;
+use crate::models::permission_model::Role;
+use crate::models::trash_model::TrashItem;
+use crate::services::naming_service::ConflictPolicy;
+
+
+#[derive(Deserialize, Debug)]
+pub struct RestoreParams {
+    pub to_root: Option<bool>,
+    // what to do when the name has been taken since the delete, numbered by default
+    pub conflict: Option<ConflictPolicy>,
+}
+
+async fn user_trash(state: &AppState, user_id: &ObjectId) -> Result<Json<Vec<TrashItem>>, StatusCode> {
+    match state.trash_collection.get_trash(doc! {"user_id": user_id}).await {
+        Ok(items) => Ok(Json(items)),
+        Err(_) => Err(StatusCode::BAD_REQUEST)
+    }
+}
+
+async fn find_trash_item(state: &AppState, item_id: &ObjectId, user_id: &ObjectId) -> Result<TrashItem, StatusCode> {
+    match state.trash_collection.get_trash(doc! {"_id": item_id, "user_id": user_id}).await {
+        Ok(items) => items.into_iter().next().ok_or(StatusCode::NOT_FOUND),
+        Err(_) => Err(StatusCode::BAD_REQUEST)
+    }
+}
+
+// Items deleted from a shared drive go to the trash of the drive, its members empty it together.
+async fn trash_owner(state: &AppState, user_id: &ObjectId, drive_id: Option<ObjectId>) -> Result<ObjectId, StatusCode> {
+    state.authorize_root(user_id, drive_id, Role::Editor).await.map(|owner| owner.id).map_err(|err| match err {
+        StorageError::Status(status) => status,
+        _ => StatusCode::BAD_REQUEST
+    })
+}
+
+pub async fn get_trash(ctx: UserContext, state: State<Arc<AppState>>, Query(drive): Query<DriveParams>) -> Result<Json<Vec<TrashItem>>, StatusCode>{
+    let owner_id = trash_owner(&state, &ctx.user_id, drive.drive_id).await?;
+    user_trash(&state, &owner_id).await
+}
+
+pub async fn restore_from_trash(ctx: UserContext, state: State<Arc<AppState>>, Path(item_id): Path<ObjectId>, Query(params): Query<RestoreParams>, Query(drive): Query<DriveParams>) -> Result<Json<Vec<TrashItem>>, StorageError>{
+    let owner_id = trash_owner(&state, &ctx.user_id, drive.drive_id).await?;
+    let item = find_trash_item(&state, &item_id, &owner_id).await?;
+
+    state.restore_trash_item(item, params.to_root.unwrap_or(false), params.conflict.unwrap_or(ConflictPolicy::AutoSuffix)).await?;
+
+    Ok(user_trash(&state, &owner_id).await?)
+}
+
+pub async fn delete_from_trash(ctx: UserContext, state: State<Arc<AppState>>, Path(item_id): Path<ObjectId>, Query(drive): Query<DriveParams>) -> Result<Json<Vec<TrashItem>>, StatusCode>{
+    let owner_id = trash_owner(&state, &ctx.user_id, drive.drive_id).await?;
+    let item = find_trash_item(&state, &item_id, &owner_id).await?;
+
+    if state.purge_trash_items(&[item]).await.is_err() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    user_trash(&state, &owner_id).await
+}
+
+pub async fn empty_trash(ctx: UserContext, state: State<Arc<AppState>>, Query(drive): Query<DriveParams>) -> Result<Json<Vec<TrashItem>>, StatusCode>{
+    let owner_id = trash_owner(&state, &ctx.user_id, drive.drive_id).await?;
+    let items = user_trash(&state, &owner_id).await?;
+
+    if state.purge_trash_items(&items).await.is_err() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    user_trash(&state, &owner_id).await
+}
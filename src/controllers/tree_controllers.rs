@@ -0,0 +1,54 @@
+use std::sync::Arc;
+use axum::extract::State;
+use axum::Json;
+use bson::oid::ObjectId;
+use serde::Deserialize;
+use crate::AppState;
+use crate::context::user_context::UserContext;
+use crate::error::storage_error::StorageError;
+use crate::models::permission_model::Role;
+use crate::services::naming_service::ConflictPolicy;
+use crate::services::tree_service::{CopiedItems, MovedItems};
+
+
+#[derive(Deserialize, Debug)]
+pub struct MoveRequest {
+    pub file_ids: Option<Vec<ObjectId>>,
+    pub folder_ids: Option<Vec<ObjectId>>,
+    // None moves the items to the root
+    pub destination: Option<ObjectId>,
+    // the drive whose root `destination: None` means, the user's own one when missing
+    pub drive_id: Option<ObjectId>,
+    pub conflict: Option<ConflictPolicy>,
+}
+
+pub async fn move_items(ctx: UserContext, state: State<Arc<AppState>>, request: Json<MoveRequest>) -> Result<Json<MovedItems>, StorageError>{
+    let file_ids = request.file_ids.clone().unwrap_or_default();
+    let folder_ids = request.folder_ids.clone().unwrap_or_default();
+
+    let owner_id = state.authorize_transfer(&ctx.user_id, &file_ids, &folder_ids, request.destination, request.drive_id, Role::Editor).await?.id;
+
+    let moved = state.move_items(&owner_id, &file_ids, &folder_ids, request.destination, request.conflict.unwrap_or_default()).await?;
+    Ok(Json(moved))
+}
+
+#[derive(Deserialize, Debug)]
+pub struct CopyRequest {
+    pub file_ids: Option<Vec<ObjectId>>,
+    pub folder_ids: Option<Vec<ObjectId>>,
+    pub destination: Option<ObjectId>,
+    pub drive_id: Option<ObjectId>,
+    // copies are numbered like duplicate uploads unless asked otherwise
+    pub conflict: Option<ConflictPolicy>,
+}
+
+pub async fn copy_items(ctx: UserContext, state: State<Arc<AppState>>, request: Json<CopyRequest>) -> Result<Json<CopiedItems>, StorageError>{
+    let file_ids = request.file_ids.clone().unwrap_or_default();
+    let folder_ids = request.folder_ids.clone().unwrap_or_default();
+    let policy = request.conflict.unwrap_or(ConflictPolicy::AutoSuffix);
+
+    let owner = state.authorize_transfer(&ctx.user_id, &file_ids, &folder_ids, request.destination, request.drive_id, Role::Viewer).await?;
+
+    let copied = state.copy_items(&owner, &file_ids, &folder_ids, request.destination, policy).await?;
+    Ok(Json(copied))
+}
@@ -0,0 +1,375 @@
+use std::collections::HashMap;
+use std::env;
+use std::io;
+use std::sync::Arc;
+use axum::body::{boxed, BoxBody, Empty};
+use axum::extract::{BodyStream, Path, Query, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::http::response::Builder;
+use axum::response::{IntoResponse, Response};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use bson::doc;
+use bson::oid::ObjectId;
+use chrono::{Duration, Utc};
+use dotenv::dotenv;
+use futures::{StreamExt, TryStreamExt};
+use tokio::fs::{self, OpenOptions};
+use tokio::io::AsyncWriteExt;
+use crate::AppState;
+use crate::context::user_context::UserContext;
+use crate::models::file_model::File;
+use crate::models::permission_model::Role;
+use crate::models::upload_model::Upload;
+use crate::services::naming_service::valid_name;
+
+
+const TUS_VERSION: &str = "1.0.0";
+const TUS_EXTENSIONS: &str = "creation,termination,expiration";
+const OFFSET_OCTET_STREAM: &str = "application/offset+octet-stream";
+
+fn tus_response(status: StatusCode) -> Builder {
+    Response::builder()
+        .status(status)
+        .header("Tus-Resumable", TUS_VERSION)
+        .header(header::CACHE_CONTROL, "no-store")
+}
+
+// RFC 7231 HTTP-date, which is what tus expects in Upload-Expires
+fn http_date(date: bson::DateTime) -> String {
+    date.to_chrono().format("%a, %d %b %Y %H:%M:%S GMT").to_string()
+}
+
+fn empty(builder: Builder) -> Response<BoxBody> {
+    builder.body(boxed(Empty::new())).unwrap()
+}
+
+fn tus_error(status: StatusCode) -> Response<BoxBody> {
+    match status {
+        StatusCode::PRECONDITION_FAILED => empty(tus_response(status).header("Tus-Version", TUS_VERSION)),
+        _ => empty(tus_response(status))
+    }
+}
+
+fn max_upload_size() -> Option<u64> {
+    dotenv().ok();
+    env::var("TUS_MAX_SIZE").ok().and_then(|size| size.parse::<u64>().ok())
+}
+
+fn upload_expiration() -> Duration {
+    dotenv().ok();
+    let hours = env::var("TUS_EXPIRATION_HOURS").ok().and_then(|hours| hours.parse::<i64>().ok()).unwrap_or(24);
+    Duration::hours(hours)
+}
+
+fn header_str<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers.get(name).and_then(|value| value.to_str().ok())
+}
+
+fn check_resumable(headers: &HeaderMap) -> Result<(), StatusCode> {
+    match header_str(headers, "Tus-Resumable") {
+        Some(TUS_VERSION) => Ok(()),
+        _ => Err(StatusCode::PRECONDITION_FAILED)
+    }
+}
+
+// Upload-Metadata is a comma separated list of `key base64(value)` pairs
+fn parse_metadata(metadata: &str) -> HashMap<String, String> {
+    metadata.split(',')
+        .filter_map(|pair| {
+            let mut parts = pair.trim().splitn(2, ' ');
+            let key = parts.next()?.to_string();
+            let value = match parts.next() {
+                Some(encoded) => String::from_utf8(STANDARD.decode(encoded.trim()).ok()?).ok()?,
+                None => String::new()
+            };
+            Some((key, value))
+        })
+        .collect()
+}
+
+async fn find_upload(state: &AppState, upload_id: &ObjectId, user_id: &ObjectId) -> Result<Upload, StatusCode> {
+    let filter = doc! {"_id": upload_id, "user_id": user_id};
+
+    match state.upload_collection.get_upload(filter).await {
+        Ok(Some(upload)) if upload.expires_at.to_chrono() < Utc::now() => Err(StatusCode::GONE),
+        Ok(Some(upload)) => Ok(upload),
+        Ok(None) => Err(StatusCode::NOT_FOUND),
+        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR)
+    }
+}
+
+// Moves a fully received upload out of staging and registers it like any other uploaded file.
+async fn complete_upload(state: &State<Arc<AppState>>, upload: &Upload) -> io::Result<ObjectId> {
+    let upload_id = upload.id.unwrap();
+    let staging_file = state.upload_collection.staging_file(&upload_id);
+
+    let blob = state.ingest_staged_file(&staging_file).await?;
+
+    let mut file = File::from_blob(upload.file_name.clone(), upload.file_type.clone(), &blob, state.blob_store.location(&blob.key));
+    file.folder_id = upload.folder_id;
+
+    // access to a shared folder may have been taken away while the upload was running
+    let owner = match state.authorize_location(&upload.user_id, upload.folder_id, upload.drive_id, Role::Editor).await {
+        Ok(owner) => owner,
+        Err(_) => {
+            let _ = state.release_blob(&blob.hash).await;
+            return Err(io::Error::new(io::ErrorKind::PermissionDenied, "Not allowed to upload into this folder"));
+        }
+    };
+
+    let new_file = match state.store_file(file, owner).await {
+        Ok(new_file) => new_file,
+        Err(err) => {
+            let _ = state.release_blob(&blob.hash).await;
+            return Err(io::Error::other(err));
+        }
+    };
+
+    let file_id = new_file.file_id();
+
+    let _ = state.upload_collection.update_upload(doc! {"_id": upload_id}, doc! {"$set": {"file_id": file_id}}).await;
+    let _ = fs::remove_file(&staging_file).await;
+
+    Ok(file_id)
+}
+
+
+pub async fn tus_options() -> Response<BoxBody> {
+    let mut builder = tus_response(StatusCode::NO_CONTENT)
+        .header("Tus-Version", TUS_VERSION)
+        .header("Tus-Extension", TUS_EXTENSIONS);
+
+    if let Some(max_size) = max_upload_size() {
+        builder = builder.header("Tus-Max-Size", max_size);
+    }
+
+    empty(builder)
+}
+
+pub async fn tus_create(ctx: UserContext, state: State<Arc<AppState>>, params: Query<Vec<(String, ObjectId)>>, headers: HeaderMap) -> Response<BoxBody> {
+    if let Err(status) = check_resumable(&headers) {
+        return tus_error(status);
+    }
+
+    let length = match header_str(&headers, "Upload-Length").and_then(|length| length.parse::<u64>().ok()) {
+        Some(length) => length,
+        None => return tus_error(StatusCode::BAD_REQUEST)
+    };
+
+    if max_upload_size().map(|max_size| length > max_size).unwrap_or(false) {
+        return tus_error(StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    let metadata = header_str(&headers, "Upload-Metadata").map(parse_metadata).unwrap_or_default();
+
+    let file_name = metadata.get("filename").map(|file_name| file_name.trim().to_string());
+
+    if file_name.as_deref().map(|file_name| !valid_name(file_name)).unwrap_or(false) {
+        return tus_error(StatusCode::BAD_REQUEST);
+    }
+
+    // folder_id may come as a query param like in upload_file, or inside the tus metadata
+    let folder_id = params.0.to_vec().iter().filter(|obj| obj.0 == "folder_id").map(|obj| obj.1).next()
+        .or(metadata.get("folder_id").and_then(|id| ObjectId::parse_str(id).ok()));
+    let drive_id = params.0.iter().find(|obj| obj.0 == "drive_id").map(|obj| obj.1)
+        .or(metadata.get("drive_id").and_then(|id| ObjectId::parse_str(id).ok()));
+
+    let owner = match state.authorize_location(&ctx.user_id, folder_id, drive_id, Role::Editor).await {
+        Ok(owner) => owner,
+        Err(err) => return err.into_response()
+    };
+
+    if let Err(err) = state.check_quota(&owner, length).await {
+        return err.into_response();
+    }
+
+    let upload_id = ObjectId::new();
+    let now = Utc::now();
+
+    let upload = Upload{
+        id: Some(upload_id),
+        user_id: ctx.user_id,
+        length,
+        offset: 0,
+        file_name: file_name.unwrap_or(upload_id.to_hex()),
+        file_type: metadata.get("filetype").cloned().unwrap_or("application/octet-stream".to_string()),
+        folder_id,
+        drive_id,
+        file_id: None,
+        locked_at: None,
+        created_at: bson::DateTime::from_chrono(now),
+        expires_at: bson::DateTime::from_chrono(now + upload_expiration()),
+    };
+
+    if fs::File::create(state.upload_collection.staging_file(&upload_id)).await.is_err() {
+        return tus_error(StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    if state.upload_collection.create_upload(&upload).await.is_err() {
+        let _ = fs::remove_file(state.upload_collection.staging_file(&upload_id)).await;
+        return tus_error(StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    if length == 0 && complete_upload(&state, &upload).await.is_err() {
+        return tus_error(StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    empty(tus_response(StatusCode::CREATED)
+        .header(header::LOCATION, format!("/api/tus/{}", upload_id))
+        .header("Upload-Expires", http_date(upload.expires_at)))
+}
+
+pub async fn tus_head(ctx: UserContext, state: State<Arc<AppState>>, Path(upload_id): Path<ObjectId>, headers: HeaderMap) -> Response<BoxBody> {
+    if let Err(status) = check_resumable(&headers) {
+        return tus_error(status);
+    }
+
+    match find_upload(&state, &upload_id, &ctx.user_id).await {
+        Ok(upload) => empty(tus_response(StatusCode::OK)
+            .header("Upload-Offset", upload.offset)
+            .header("Upload-Length", upload.length)
+            .header("Upload-Expires", http_date(upload.expires_at))),
+        Err(status) => tus_error(status)
+    }
+}
+
+pub async fn tus_patch(ctx: UserContext, state: State<Arc<AppState>>, Path(upload_id): Path<ObjectId>, headers: HeaderMap, body: BodyStream) -> Response<BoxBody> {
+    if let Err(status) = check_resumable(&headers) {
+        return tus_error(status);
+    }
+
+    if header_str(&headers, header::CONTENT_TYPE.as_str()) != Some(OFFSET_OCTET_STREAM) {
+        return tus_error(StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    }
+
+    let upload = match find_upload(&state, &upload_id, &ctx.user_id).await {
+        Ok(upload) => upload,
+        Err(status) => return tus_error(status)
+    };
+
+    let offset = match header_str(&headers, "Upload-Offset").and_then(|offset| offset.parse::<u64>().ok()) {
+        Some(offset) => offset,
+        None => return tus_error(StatusCode::BAD_REQUEST)
+    };
+
+    if offset != upload.offset || upload.file_id.is_some() {
+        return tus_error(StatusCode::CONFLICT);
+    }
+
+    // two PATCHes at the same offset would both append to the staging file
+    let upload = match state.upload_collection.claim_upload(&upload_id, &ctx.user_id, offset).await {
+        Ok(Some(upload)) => upload,
+        Ok(None) => return tus_error(StatusCode::CONFLICT),
+        Err(_) => return tus_error(StatusCode::INTERNAL_SERVER_ERROR)
+    };
+
+    let staging_file = state.upload_collection.staging_file(&upload_id);
+
+    let mut file = match OpenOptions::new().write(true).open(&staging_file).await {
+        Ok(file) => file,
+        Err(_) => {
+            let _ = state.upload_collection.release_upload(&upload_id, offset).await;
+            return tus_error(StatusCode::NOT_FOUND);
+        }
+    };
+
+    // anything past the recorded offset is left over from an interrupted request
+    if file.set_len(offset).await.is_err() {
+        let _ = state.upload_collection.release_upload(&upload_id, offset).await;
+        return tus_error(StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    let mut body = body.map_err(io::Error::other);
+    let mut new_offset = offset;
+    let mut too_large = false;
+
+    // keep whatever arrived before the connection dropped, the client resumes from there
+    while let Some(Ok(chunk)) = body.next().await {
+        if new_offset + chunk.len() as u64 > upload.length {
+            too_large = true;
+            break;
+        }
+
+        if file.write_all(&chunk).await.is_err() {
+            break;
+        }
+
+        new_offset += chunk.len() as u64;
+    }
+
+    let _ = file.flush().await;
+    let _ = file.set_len(new_offset).await;
+
+    // a finished upload stays claimed, completing it is still part of this PATCH
+    let recorded = match new_offset == upload.length {
+        true => state.upload_collection.update_upload(doc! {"_id": upload_id}, doc! {"$set": {"offset": new_offset as i64}}).await,
+        false => state.upload_collection.release_upload(&upload_id, new_offset).await
+    };
+
+    if recorded.is_err() {
+        return tus_error(StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    if too_large {
+        return tus_error(StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    if new_offset == upload.length && complete_upload(&state, &upload).await.is_err() {
+        // an empty PATCH at the end retries completing it
+        let _ = state.upload_collection.release_upload(&upload_id, new_offset).await;
+        return tus_error(StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    empty(tus_response(StatusCode::NO_CONTENT)
+        .header("Upload-Offset", new_offset)
+        .header("Upload-Expires", http_date(upload.expires_at)))
+}
+
+pub async fn tus_delete(ctx: UserContext, state: State<Arc<AppState>>, Path(upload_id): Path<ObjectId>, headers: HeaderMap) -> Response<BoxBody> {
+    if let Err(status) = check_resumable(&headers) {
+        return tus_error(status);
+    }
+
+    if let Err(status) = find_upload(&state, &upload_id, &ctx.user_id).await {
+        return tus_error(status);
+    }
+
+    let _ = fs::remove_file(state.upload_collection.staging_file(&upload_id)).await;
+
+    match state.upload_collection.delete_upload(doc! {"_id": upload_id, "user_id": ctx.user_id}).await {
+        Ok(_) => empty(tus_response(StatusCode::NO_CONTENT)),
+        Err(_) => tus_error(StatusCode::INTERNAL_SERVER_ERROR)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+    use super::{http_date, parse_metadata};
+
+    #[test]
+    fn decodes_metadata_values() {
+        let metadata = parse_metadata("filename d29ybGRfZG9taW5hdGlvbl9wbGFuLnBkZg==, filetype YXBwbGljYXRpb24vcGRm,is_confidential");
+
+        assert_eq!(metadata.get("filename").map(String::as_str), Some("world_domination_plan.pdf"));
+        assert_eq!(metadata.get("filetype").map(String::as_str), Some("application/pdf"));
+        // a key without a value is allowed
+        assert_eq!(metadata.get("is_confidential").map(String::as_str), Some(""));
+    }
+
+    #[test]
+    fn skips_values_that_are_not_base64_text() {
+        let metadata = parse_metadata("filename not-base64!, filetype dGV4dC9wbGFpbg==");
+
+        assert!(!metadata.contains_key("filename"));
+        assert_eq!(metadata.get("filetype").map(String::as_str), Some("text/plain"));
+    }
+
+    #[test]
+    fn formats_expiry_as_an_http_date() {
+        let date = bson::DateTime::from_chrono(Utc.with_ymd_and_hms(2015, 10, 21, 7, 28, 0).unwrap());
+        assert_eq!(http_date(date), "Wed, 21 Oct 2015 07:28:00 GMT");
+    }
+}
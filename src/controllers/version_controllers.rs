@@ -0,0 +1,96 @@
+use std::sync::Arc;
+use axum::body::BoxBody;
+use axum::extract::{Path, Query, State};
+use axum::http::{HeaderMap, StatusCode};
+use axum::Json;
+use axum::response::Response;
+use bson::doc;
+use bson::oid::ObjectId;
+use chrono::Duration;
+use serde::Deserialize;
+use crate::AppState;
+use crate::context::user_context::UserContext;
+use crate::controllers::download_controllers::{file_content_response, ContentParams};
+use crate::error::storage_error::StorageError;
+use crate::models::file_model::File;
+use crate::models::permission_model::Role;
+use crate::models::share_model::SharedItemType;
+use crate::models::version_model::FileVersion;
+use crate::services::version_service::VersionLimits;
+
+
+#[derive(Deserialize, Debug)]
+pub struct PruneParams {
+    pub keep: Option<usize>,
+    pub older_than_days: Option<i64>,
+}
+
+async fn find_user_file(state: &AppState, file_id: &ObjectId, user_id: &ObjectId) -> Result<File, StatusCode> {
+    let filter = doc! {"_id": file_id, "user_id": user_id};
+
+    match state.file_collection.get_file(filter).await {
+        Ok(files) => files.into_iter().next().ok_or(StatusCode::NOT_FOUND),
+        Err(_) => Err(StatusCode::BAD_REQUEST)
+    }
+}
+
+async fn find_version(state: &AppState, file_id: &ObjectId, user_id: &ObjectId, version: i64) -> Result<FileVersion, StatusCode> {
+    let filter = doc! {"file_id": file_id, "user_id": user_id, "version": version};
+
+    match state.version_collection.get_versions(filter).await {
+        Ok(versions) => versions.into_iter().next().ok_or(StatusCode::NOT_FOUND),
+        Err(_) => Err(StatusCode::BAD_REQUEST)
+    }
+}
+
+pub async fn get_file_versions(ctx: UserContext, state: State<Arc<AppState>>, Path(file_id): Path<ObjectId>) -> Result<Json<Vec<FileVersion>>, StorageError>{
+    let owner_id = state.authorize(&ctx.user_id, SharedItemType::File, &file_id, Role::Viewer).await?.id;
+    find_user_file(&state, &file_id, &owner_id).await?;
+
+    match state.version_collection.get_versions(doc! {"file_id": file_id, "user_id": owner_id}).await {
+        Ok(versions) => Ok(Json(versions)),
+        Err(_) => Err(StatusCode::BAD_REQUEST.into())
+    }
+}
+
+pub async fn get_version_content(ctx: UserContext, state: State<Arc<AppState>>, Path((file_id, version)): Path<(ObjectId, i64)>, Query(params): Query<ContentParams>, headers: HeaderMap) -> Result<Response<BoxBody>, StorageError>{
+    let owner_id = state.authorize(&ctx.user_id, SharedItemType::File, &file_id, Role::Viewer).await?.id;
+    let mut file = find_user_file(&state, &file_id, &owner_id).await?;
+    let version = find_version(&state, &file_id, &owner_id, version).await?;
+
+    // serve the old content under the file's current name
+    file.aws_file_name = version.aws_file_name;
+    file.file_type = version.file_type;
+
+    let inline = params.disposition.as_deref() == Some("inline");
+    Ok(file_content_response(&state, &file, &headers, inline).await?)
+}
+
+pub async fn restore_file_version(ctx: UserContext, state: State<Arc<AppState>>, Path((file_id, version)): Path<(ObjectId, i64)>) -> Result<Json<File>, StorageError>{
+    let owner_id = state.authorize(&ctx.user_id, SharedItemType::File, &file_id, Role::Editor).await?.id;
+    let file = find_user_file(&state, &file_id, &owner_id).await?;
+    let version = find_version(&state, &file_id, &owner_id, version).await?;
+
+    if state.restore_version(&file, &version).await.is_err() {
+        return Err(StatusCode::BAD_REQUEST.into());
+    }
+
+    Ok(Json(find_user_file(&state, &file_id, &owner_id).await?))
+}
+
+pub async fn prune_file_versions(ctx: UserContext, state: State<Arc<AppState>>, Path(file_id): Path<ObjectId>, Query(params): Query<PruneParams>) -> Result<Json<Vec<FileVersion>>, StorageError>{
+    let owner_id = state.authorize(&ctx.user_id, SharedItemType::File, &file_id, Role::Editor).await?.id;
+    find_user_file(&state, &file_id, &owner_id).await?;
+
+    let configured = VersionLimits::from_env();
+
+    let limits = VersionLimits{
+        max_versions: params.keep.or(configured.max_versions),
+        max_age: params.older_than_days.map(Duration::days).or(configured.max_age),
+    };
+
+    match state.prune_versions(&file_id, limits).await {
+        Ok(pruned) => Ok(Json(pruned)),
+        Err(_) => Err(StatusCode::BAD_REQUEST.into())
+    }
+}
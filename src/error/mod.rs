@@ -1 +1,2 @@
-pub mod auth_error;
\ No newline at end of file
+pub mod auth_error;
+pub mod storage_error;
\ No newline at end of file
@@ -0,0 +1,64 @@
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+
+pub enum StorageError{
+    QuotaExceeded{ used: u64, quota: u64, requested: u64 },
+    NameConflict{ name: String },
+    Status(StatusCode),
+}
+
+
+impl StorageError {
+
+    pub fn create_error(&self) -> Response<String>{
+        let (status, response_body) = match self {
+            Self::QuotaExceeded{ used, quota, requested } => {
+                let response_body = serde_json::json!({
+                    "message": "Storage quota exceeded.",
+                    "used_bytes": used,
+                    "quota_bytes": quota,
+                    "requested_bytes": requested,
+                    "available_bytes": quota.saturating_sub(*used),
+                });
+
+                (StatusCode::INSUFFICIENT_STORAGE, response_body)
+            },
+            Self::NameConflict{ name } => {
+                let response_body = serde_json::json!({
+                    "message": "An item with this name already exists.",
+                    "name": name,
+                });
+
+                (StatusCode::CONFLICT, response_body)
+            },
+            Self::Status(status) => {
+                let message = status.canonical_reason().unwrap_or("Something went wrong!");
+                (*status, serde_json::json!({ "message": message }))
+            }
+        };
+
+        Response::builder()
+            .status(status)
+            .header("content-type", "application/json")
+            .body(response_body.to_string())
+            .unwrap()
+    }
+}
+
+impl From<StatusCode> for StorageError {
+    fn from(status: StatusCode) -> Self {
+        Self::Status(status)
+    }
+}
+
+impl From<mongodb::error::Error> for StorageError {
+    fn from(_: mongodb::error::Error) -> Self {
+        Self::Status(StatusCode::BAD_REQUEST)
+    }
+}
+
+impl IntoResponse for StorageError {
+    fn into_response(self) -> axum::response::Response {
+        self.create_error().into_response()
+    }
+}
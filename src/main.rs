@@ -6,6 +6,7 @@ mod services;
 mod error;
 mod context;
 mod middleware;
+mod storage;
 
 use std::env;
 use std::fmt::Debug;
@@ -13,18 +14,19 @@ use std::net::SocketAddr;
 use std::sync::{Arc, Mutex};
 use axum::{Router, Server};
 use axum::body::HttpBody;
-use axum::http::{header, Method};
-use axum::routing::{delete, get, post};
+use axum::extract::DefaultBodyLimit;
+use axum::http::{header, HeaderName, Method};
+use axum::routing::{delete, get, head, patch, post};
 use mongodb::{Client, Collection, options::ClientOptions};
 use dotenv::dotenv;
-use crate::controllers::auth_controller::{get_user, logout, sign_up, sing_in};
+use crate::controllers::auth_controller::{get_user, logout, sign_up, sing_in, update_settings};
 use crate::models::user_model::{User};
 use crate::services::auth_services::UserCollection;
 use tower_http::cors;
 use tower_http::cors::{Any, CorsLayer, AllowOrigin, AllowMethods};
 use tower_cookies::CookieManagerLayer;
 use axum::middleware as axum_middleware;
-use crate::controllers::file_controllers::{delete_file, get_files, upload_file};
+use crate::controllers::file_controllers::{delete_file, get_files, rename_file, upload_file, upload_multipart_file};
 use crate::middleware::auth_middleware::verify_token;
 use crate::services::file_services::FileCollection;
 use crate::services::trait_service::StorageCollection;
@@ -33,11 +35,41 @@ use axum::handler::Handler;
 use axum::middleware::AddExtension;
 use chrono::{DateTime, Utc};
 use serde::Serialize;
-use crate::controllers::dashboard_controllers::get_dashboard;
-use crate::controllers::folder_controllers::{create_folder, delete_folder, get_folder_details, get_folders};
+use crate::controllers::dashboard_controllers::{get_dashboard, get_shared_with_me, get_storage_usage};
+use crate::controllers::download_controllers::get_file_content;
+use crate::controllers::presign_controllers::{finalize_upload, presign_download, presign_upload};
+use crate::controllers::gc_controllers::{gc_grace_period, run_gc};
+use crate::controllers::version_controllers::{get_file_versions, get_version_content, prune_file_versions, restore_file_version};
+use crate::controllers::trash_controllers::{delete_from_trash, empty_trash, get_trash, restore_from_trash};
+use crate::controllers::tree_controllers::{copy_items, move_items};
+use crate::controllers::fs_controllers::resolve_path;
+use crate::controllers::search_controllers::search;
+use crate::controllers::permission_controllers::{get_permissions, grant_access, revoke_permission};
+use crate::controllers::comment_controllers::{add_comment, delete_comment, edit_comment, get_comments, get_mentions, resolve_comment, unresolve_comment};
+use crate::controllers::file_request_controllers::{create_file_request, file_request_body_limit, get_file_requests, open_file_request, revoke_file_request, upload_to_file_request};
+use crate::controllers::org_controllers::{create_drive, create_organization, get_drives, get_org_drives, get_organizations, remove_member, set_member, update_drive};
+use crate::controllers::share_controllers::{create_share, download_shared_file, get_shares, open_shared_item, revoke_share, unlock_shared_item};
+use crate::services::listing_service::NEXT_CURSOR_HEADER;
+use crate::services::quota_service::{STORAGE_AVAILABLE_HEADER, STORAGE_QUOTA_HEADER, STORAGE_USED_HEADER};
+use crate::controllers::tus_controllers::{tus_create, tus_delete, tus_head, tus_options, tus_patch};
+use crate::controllers::folder_controllers::{create_folder, delete_folder, get_breadcrumbs, get_folder_details, get_folder_tree, get_folders, rename_folder};
 use crate::models::file_model::File;
 use crate::models::folder_model::Folder;
 use crate::services::folder_service::FolderCollection;
+use crate::services::upload_service::UploadCollection;
+use crate::services::blob_services::BlobCollection;
+use crate::services::version_service::VersionCollection;
+use crate::services::trash_service::TrashCollection;
+use crate::services::share_service::ShareCollection;
+use crate::services::permission_service::PermissionCollection;
+use crate::services::organization_service::OrganizationCollection;
+use crate::services::drive_service::DriveCollection;
+use crate::services::file_request_service::FileRequestCollection;
+use crate::services::comment_service::CommentCollection;
+use crate::services::stats_service::FolderStatsCache;
+use crate::storage::blob_store::BlobStore;
+use crate::storage::local_store::LocalBlobStore;
+use crate::storage::s3_store::S3BlobStore;
 
 async fn root() -> &'static str {
     "Hello, World!"
@@ -47,11 +79,24 @@ async fn root() -> &'static str {
 pub struct AppState {
     pub file_collection: FileCollection,
     pub folder_collection: FolderCollection,
+    pub blob_store: Arc<dyn BlobStore>,
+    pub upload_collection: UploadCollection,
+    pub blob_collection: BlobCollection,
+    pub user_collection: UserCollection,
+    pub version_collection: VersionCollection,
+    pub trash_collection: TrashCollection,
+    pub share_collection: ShareCollection,
+    pub permission_collection: PermissionCollection,
+    pub organization_collection: OrganizationCollection,
+    pub drive_collection: DriveCollection,
+    pub file_request_collection: FileRequestCollection,
+    pub comment_collection: CommentCollection,
+    pub stats_cache: FolderStatsCache,
 }
 
 #[derive(Serialize, Clone, Debug)]
 
-enum Item<A, B> {
+pub enum Item<A, B> {
     File(A),
     Folder(B),
 }
@@ -73,10 +118,25 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         header::ACCESS_CONTROL_ALLOW_CREDENTIALS,
         header::ACCESS_CONTROL_ALLOW_METHODS,
         header::ACCESS_CONTROL_ALLOW_ORIGIN,
-        header::ACCESS_CONTROL_ALLOW_HEADERS
-    ]).allow_methods(AllowMethods::exact(Method::DELETE))
+        header::ACCESS_CONTROL_ALLOW_HEADERS,
+        HeaderName::from_static("tus-resumable"),
+        HeaderName::from_static("upload-length"),
+        HeaderName::from_static("upload-metadata"),
+        HeaderName::from_static("upload-offset"),
+        HeaderName::from_static("x-share-access"),
+    ]).allow_methods(AllowMethods::list(vec![Method::DELETE, Method::PATCH, Method::HEAD]))
         .allow_credentials(true)
-        .expose_headers(vec![header::LOCATION]);
+        .expose_headers(vec![
+        header::LOCATION,
+        HeaderName::from_static("tus-resumable"),
+        HeaderName::from_static("upload-offset"),
+        HeaderName::from_static("upload-length"),
+        HeaderName::from_static("upload-expires"),
+        HeaderName::from_static(NEXT_CURSOR_HEADER),
+        HeaderName::from_static(STORAGE_USED_HEADER),
+        HeaderName::from_static(STORAGE_QUOTA_HEADER),
+        HeaderName::from_static(STORAGE_AVAILABLE_HEADER),
+    ]);
 
 
 
@@ -92,34 +152,201 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     let folder_collection = FolderCollection::init().await?;
     let files_collection = FileCollection::init().await?;
+    let upload_collection = UploadCollection::init().await?;
+    let blob_collection = BlobCollection::init().await?;
+    let version_collection = VersionCollection::init().await?;
+    let trash_collection = TrashCollection::init().await?;
+    let share_collection = ShareCollection::init().await?;
+    let permission_collection = PermissionCollection::init().await?;
+    let organization_collection = OrganizationCollection::init().await?;
+    let drive_collection = DriveCollection::init().await?;
+    let file_request_collection = FileRequestCollection::init().await?;
+    let comment_collection = CommentCollection::init().await?;
+
+    let blob_store: Arc<dyn BlobStore> = match env::var("STORAGE_BACKEND").as_deref() {
+        Ok("s3") => Arc::new(S3BlobStore::init()?),
+        _ => Arc::new(LocalBlobStore::init().await?),
+    };
 
     let state = Arc::new(AppState {
         file_collection: files_collection.clone(),
         folder_collection: folder_collection.clone(),
+        blob_store,
+        upload_collection: upload_collection.clone(),
+        blob_collection: blob_collection.clone(),
+        user_collection: user_collection.clone(),
+        version_collection: version_collection.clone(),
+        trash_collection: trash_collection.clone(),
+        share_collection: share_collection.clone(),
+        permission_collection: permission_collection.clone(),
+        organization_collection: organization_collection.clone(),
+        drive_collection: drive_collection.clone(),
+        file_request_collection: file_request_collection.clone(),
+        comment_collection: comment_collection.clone(),
+        stats_cache: FolderStatsCache::default(),
     });
 
+    state.run_migrations().await?;
+
+    let purge_state = state.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(60 * 60));
+        loop {
+            interval.tick().await;
+            if let Err(err) = purge_state.purge_expired_uploads().await {
+                println!("Failed to purge expired uploads: {}", err);
+            }
+            if let Err(err) = purge_state.purge_expired_trash().await {
+                println!("Failed to purge expired trash: {}", err);
+            }
+        }
+    });
+
+    // released blobs leave their objects to the GC, so it runs daily unless set to 0
+    let gc_interval = env::var("GC_INTERVAL_HOURS").ok().and_then(|hours| hours.parse::<u64>().ok()).unwrap_or(24);
+
+    if gc_interval > 0 {
+        let gc_state = state.clone();
+        let dry_run = env::var("GC_DRY_RUN").map(|dry_run| dry_run == "true").unwrap_or(false);
+
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(std::time::Duration::from_secs(gc_interval * 60 * 60));
+            loop {
+                interval.tick().await;
+                match gc_state.collect_garbage(dry_run, gc_grace_period()).await {
+                    Ok(report) => println!("GC found {} orphaned blobs, deleted {} ({} bytes)", report.orphans.len(), report.deleted, report.reclaimed_bytes),
+                    Err(err) => println!("GC failed: {}", err)
+                }
+            }
+        });
+    }
+
 
     let files_router = Router::new()
         .route("/files", get(get_files))
         .route("/upload", post(upload_file))
+        .route("/upload/multipart", post(upload_multipart_file).layer(DefaultBodyLimit::disable()))
         .route("/delete", delete(delete_file))
+        .route("/files/:id/content", get(get_file_content))
+        .route("/files/:id/download-url", get(presign_download))
+        .route("/files/:id/rename", post(rename_file))
+        .route("/move", post(move_items))
+        .route("/copy", post(copy_items))
+        .route("/search", get(search))
+        .route("/files/:id/versions", get(get_file_versions).delete(prune_file_versions))
+        .route("/files/:id/versions/:version/content", get(get_version_content))
+        .route("/files/:id/versions/:version/restore", post(restore_file_version))
+        .route("/upload/presign", post(presign_upload))
+        .route("/upload/finalize", post(finalize_upload))
 
         .route_layer(axum_middleware::from_fn(verify_token))
         .with_state(state.clone());
 
 
+    let tus_router = Router::new()
+        .route("/tus", post(tus_create).options(tus_options))
+        .route("/tus/:id", head(tus_head).patch(tus_patch).delete(tus_delete))
+        .route_layer(axum_middleware::from_fn(verify_token))
+        .with_state(state.clone());
+
+
     let dashboard_router = Router::new()
         .route("/", get(get_dashboard))
+        .route("/usage", get(get_storage_usage))
+        .route("/shared", get(get_shared_with_me))
+        .route_layer(axum_middleware::from_fn(verify_token))
+        .with_state(state.clone());
+
+
+
+    let storage_router = Router::new()
+        .route("/gc", post(run_gc))
+        .route_layer(axum_middleware::from_fn(verify_token))
+        .with_state(state.clone());
+
+
+    let trash_router = Router::new()
+        .route("/", get(get_trash).delete(empty_trash))
+        .route("/:id", delete(delete_from_trash))
+        .route("/:id/restore", post(restore_from_trash))
+        .route_layer(axum_middleware::from_fn(verify_token))
+        .with_state(state.clone());
+
+
+    let share_router = Router::new()
+        .route("/", get(get_shares).post(create_share))
+        .route("/:id", delete(revoke_share))
+        .route_layer(axum_middleware::from_fn(verify_token))
+        .with_state(state.clone());
+
+
+    let permission_router = Router::new()
+        .route("/", get(get_permissions).post(grant_access))
+        .route("/:id", delete(revoke_permission))
         .route_layer(axum_middleware::from_fn(verify_token))
         .with_state(state.clone());
 
 
+    let org_router = Router::new()
+        .route("/", get(get_organizations).post(create_organization))
+        .route("/:id/members", post(set_member))
+        .route("/:id/members/:user_id", delete(remove_member))
+        .route("/:id/drives", get(get_org_drives).post(create_drive))
+        .route("/:id/drives/:drive_id", patch(update_drive))
+        .route_layer(axum_middleware::from_fn(verify_token))
+        .with_state(state.clone());
+
+
+    let drive_router = Router::new()
+        .route("/", get(get_drives))
+        .route_layer(axum_middleware::from_fn(verify_token))
+        .with_state(state.clone());
+
+
+    let comment_router = Router::new()
+        .route("/", get(get_comments).post(add_comment))
+        .route("/mentions", get(get_mentions))
+        .route("/:id", patch(edit_comment).delete(delete_comment))
+        .route("/:id/resolve", post(resolve_comment).delete(unresolve_comment))
+        .route_layer(axum_middleware::from_fn(verify_token))
+        .with_state(state.clone());
+
+
+    let file_request_router = Router::new()
+        .route("/", get(get_file_requests).post(create_file_request))
+        .route("/:id", delete(revoke_file_request))
+        .route_layer(axum_middleware::from_fn(verify_token))
+        .with_state(state.clone());
+
+
+    // file request links are used without an account too
+    let public_file_request_router = Router::new()
+        .route("/:token", get(open_file_request).post(upload_to_file_request).layer(DefaultBodyLimit::max(file_request_body_limit())))
+        .with_state(state.clone());
+
+
+    // share links are opened without an account, outside of verify_token
+    let public_share_router = Router::new()
+        .route("/:token", get(open_shared_item))
+        .route("/:token/access", post(unlock_shared_item))
+        .route("/:token/files/:file_id/content", get(download_shared_file))
+        .with_state(state.clone());
+
+
+    let fs_router = Router::new()
+        .route("/*path", get(resolve_path))
+        .route_layer(axum_middleware::from_fn(verify_token))
+        .with_state(state.clone());
+
 
     let folder_router = Router::new()
         .route("/create", post(create_folder))
         .route("/folders", get(get_folders))
         .route("/details", get(get_folder_details))
+        .route("/tree", get(get_folder_tree))
         .route("/delete", delete(delete_folder))
+        .route("/:id/rename", post(rename_folder))
+        .route("/:id/breadcrumbs", get(get_breadcrumbs))
         .route_layer(axum_middleware::from_fn(verify_token))
 
         .with_state(state);
@@ -127,6 +354,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     let user_router = Router::new()
         .route("/get-user", get(get_user))
+        .route("/settings", post(update_settings))
         .route_layer(axum_middleware::from_fn(verify_token))
         .with_state(user_collection.clone());
 
@@ -134,10 +362,21 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let app = Router::new()
         .route("/", get(root))
         .nest_service("/auth", auth_router)
-        .nest("/api", files_router)
+        .nest("/api", files_router.merge(tus_router))
         .nest("/user", user_router)
         .nest("/folder", folder_router)
         .nest("/dashboard", dashboard_router)
+        .nest("/storage", storage_router)
+        .nest("/trash", trash_router)
+        .nest("/fs", fs_router)
+        .nest("/shares", share_router)
+        .nest("/permissions", permission_router)
+        .nest("/orgs", org_router)
+        .nest("/drives", drive_router)
+        .nest("/s", public_share_router)
+        .nest("/file-requests", file_request_router)
+        .nest("/comments", comment_router)
+        .nest("/r", public_file_request_router)
         .layer(cors)
         .layer(CookieManagerLayer::new());
 
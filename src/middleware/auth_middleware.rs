@@ -7,7 +7,7 @@ use jsonwebtoken::{decode, DecodingKey, Validation};
 use tower_cookies::Cookies;
 use crate::context::user_context::UserContext;
 use crate::controllers::auth_controller::handle_response;
-use crate::models::token_model::Claims;
+use crate::models::token_model::{Claims, TokenKind};
 
 
 pub async fn verify_token<B>(cookies: Cookies, mut req: Request<B>, next: Next<B>,) -> axum::response::Response {
@@ -27,7 +27,7 @@ pub async fn verify_token<B>(cookies: Cookies, mut req: Request<B>, next: Next<B
 
 
             match token_data {
-                Ok(toke) => {
+                Ok(toke) if toke.claims.kind == TokenKind::Login => {
                     let id = toke.claims.id.unwrap();
                     let email = toke.claims.email;
 
@@ -38,7 +38,7 @@ pub async fn verify_token<B>(cookies: Cookies, mut req: Request<B>, next: Next<B
 
 
                 }
-                Err(_) => {
+                _ => {
                     return handle_response("Token is not valid!".to_string(), StatusCode::FORBIDDEN).into_response()
                 }
             }
@@ -0,0 +1,19 @@
+use serde::{Serialize, Deserialize};
+
+
+// One stored object per unique content, shared by every File that has the same SHA-256
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Blob{
+    // hex encoded SHA-256 of the content
+    #[serde(rename = "_id")]
+    pub hash: String,
+
+    pub key: String,
+
+    pub size: u64,
+
+    pub ref_count: i64,
+
+    #[serde(rename = "createdAt")]
+    pub created_at: bson::DateTime,
+}
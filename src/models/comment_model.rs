@@ -0,0 +1,51 @@
+use mongodb::bson::oid::ObjectId;
+use serde::{Serialize, Deserialize};
+
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Mention{
+    pub user_id: ObjectId,
+
+    pub email: String,
+}
+
+// A comment on a file. Replies point at the first comment of their thread, threads are one level
+// deep and only the first comment can be resolved.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Comment{
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+
+    pub file_id: ObjectId,
+
+    // owner of the file, like `user_id` on File
+    pub owner_id: ObjectId,
+
+    pub parent_id: Option<ObjectId>,
+
+    pub author_id: ObjectId,
+
+    pub author_email: String,
+
+    pub body: String,
+
+    // users with access to the file named as @email in the body
+    pub mentions: Vec<Mention>,
+
+    pub resolved_at: Option<bson::DateTime>,
+
+    pub resolved_by: Option<ObjectId>,
+
+    pub edited_at: Option<bson::DateTime>,
+
+    #[serde(rename = "createdAt")]
+    pub created_at: bson::DateTime,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CommentThread{
+    #[serde(flatten)]
+    pub comment: Comment,
+
+    pub replies: Vec<Comment>,
+}
@@ -1,5 +1,7 @@
 use mongodb::bson::oid::ObjectId;
 use serde::{Serialize, Deserialize};
+use crate::models::blob_model::Blob;
+use crate::models::owner_model::{Owner, OwnerKind};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct File{
@@ -24,7 +26,59 @@ pub struct File{
 
     pub user_id: Option<ObjectId>,
 
+    // whether `user_id` is a user or a shared drive
+    #[serde(default)]
+    pub owner_kind: OwnerKind,
+
     pub folder_id: Option<ObjectId>,
     
-    pub path: Option<String>
+    pub path: Option<String>,
+
+    // SHA-256 of the content, files uploaded through the backend share one Blob per hash
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content_hash: Option<String>,
+
+    // only set once versioning kicked in for this file, older contents live in file_versions
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<i64>,
+
+    // who sent the file through a file request link, files uploaded by users don't have it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub uploaded_by: Option<Uploader>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Uploader{
+    pub name: Option<String>,
+
+    pub email: Option<String>,
+
+    pub file_request_id: ObjectId,
+}
+
+impl File {
+    pub fn from_blob(file_name: String, file_type: String, blob: &Blob, file_location: String) -> Self {
+        Self {
+            id: None,
+            file_name,
+            original_file_name: None,
+            file_type,
+            created_at: None,
+            updated_at: None,
+            aws_file_name: blob.key.clone(),
+            file_location,
+            size: blob.size,
+            user_id: None,
+            owner_kind: OwnerKind::User,
+            folder_id: None,
+            path: None,
+            content_hash: Some(blob.hash.clone()),
+            version: None,
+            uploaded_by: None,
+        }
+    }
+
+    pub fn owner(&self) -> Option<Owner> {
+        self.user_id.map(|id| Owner{ kind: self.owner_kind, id })
+    }
 }
\ No newline at end of file
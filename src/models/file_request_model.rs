@@ -0,0 +1,93 @@
+use mongodb::bson::oid::ObjectId;
+use serde::{Serialize, Deserialize};
+use crate::models::owner_model::{Owner, OwnerKind};
+
+
+// An upload-only link into a folder. People without an account can send files with it but never
+// see what is in the folder.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileRequest{
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+
+    pub token: String,
+
+    // owner of the folder the files go to
+    pub user_id: ObjectId,
+
+    #[serde(default)]
+    pub owner_kind: OwnerKind,
+
+    pub created_by: ObjectId,
+
+    pub folder_id: ObjectId,
+
+    // shown to the uploader
+    pub title: Option<String>,
+
+    // uploads without a name and an email are refused
+    pub require_uploader: bool,
+
+    pub expires_at: Option<bson::DateTime>,
+
+    // in bytes, per file
+    pub max_file_size: Option<i64>,
+
+    // mime types, "image/*" allows a whole family
+    pub allowed_types: Option<Vec<String>>,
+
+    pub max_files: Option<i64>,
+
+    pub files: i64,
+
+    pub revoked_at: Option<bson::DateTime>,
+
+    #[serde(rename = "createdAt")]
+    pub created_at: bson::DateTime,
+}
+
+impl FileRequest {
+    pub fn owner(&self) -> Owner {
+        Owner{ kind: self.owner_kind, id: self.user_id }
+    }
+
+    pub fn allows_type(&self, file_type: &str) -> bool {
+        match &self.allowed_types {
+            Some(allowed_types) => allowed_types.iter().any(|allowed| match allowed.strip_suffix("/*") {
+                Some(family) => file_type.split('/').next() == Some(family),
+                None => allowed.eq_ignore_ascii_case(file_type)
+            }),
+            None => true
+        }
+    }
+}
+
+// What the uploader gets to see of the request, not where the files end up
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileRequestInfo{
+    pub title: Option<String>,
+
+    pub require_uploader: bool,
+
+    pub expires_at: Option<bson::DateTime>,
+
+    pub max_file_size: Option<i64>,
+
+    pub allowed_types: Option<Vec<String>>,
+
+    // files that can still be sent, None when there is no cap
+    pub remaining_files: Option<i64>,
+}
+
+impl From<FileRequest> for FileRequestInfo {
+    fn from(request: FileRequest) -> Self {
+        Self {
+            remaining_files: request.max_files.map(|max_files| (max_files - request.files).max(0)),
+            title: request.title,
+            require_uploader: request.require_uploader,
+            expires_at: request.expires_at,
+            max_file_size: request.max_file_size,
+            allowed_types: request.allowed_types,
+        }
+    }
+}
@@ -2,6 +2,7 @@ use bson::Bson;
 use mongodb::bson::oid::ObjectId;
 use serde::{Serialize, Deserialize};
 use crate::models::file_model::File;
+use crate::models::owner_model::{Owner, OwnerKind};
 use mongodb::{options::ClientOptions, Client};
 
 
@@ -30,8 +31,11 @@ pub struct Folder{
 
     pub folder_type: Option<FolderType>,
 
+    // filled in from File.folder_id / Folder.parent_id when read, not stored
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub files: Option<Vec<ObjectId>>,
 
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub folders: Option<Vec<ObjectId>>,
 
     #[serde(rename = "createdAt")]
@@ -44,7 +48,24 @@ pub struct Folder{
 
     pub user_id: Option<ObjectId>,
 
-    pub path: Option<String>
+    // whether `user_id` is a user or a shared drive
+    #[serde(default)]
+    pub owner_kind: OwnerKind,
+
+    pub path: Option<String>,
+
+    // recursive totals, computed when read and not stored either
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stats: Option<FolderStats>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default)]
+pub struct FolderStats{
+    pub size: u64,
+
+    pub file_count: u64,
+
+    pub folder_count: u64,
 }
 
 
@@ -70,6 +91,15 @@ pub struct FolderJSON{
 
 }
 
+// One step of the trail from the root down to a folder
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Breadcrumb{
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+
+    pub folder_name: String,
+}
+
 impl Folder {
     pub fn new() -> Self {
         Self {
@@ -82,9 +112,15 @@ impl Folder {
             updated_at: None,
             parent_id: None,
             user_id: None,
-            path: None
+            owner_kind: OwnerKind::User,
+            path: None,
+            stats: None,
         }
     }
 
+    pub fn owner(&self) -> Option<Owner> {
+        self.user_id.map(|id| Owner{ kind: self.owner_kind, id })
+    }
+
 }
 
@@ -1,4 +1,14 @@
 pub mod user_model;
 pub mod token_model;
 pub mod file_model;
-pub mod folder_model;
\ No newline at end of file
+pub mod folder_model;
+pub mod upload_model;
+pub mod blob_model;
+pub mod version_model;
+pub mod trash_model;
+pub mod share_model;
+pub mod permission_model;
+pub mod organization_model;
+pub mod file_request_model;
+pub mod comment_model;
+pub mod owner_model;
@@ -0,0 +1,64 @@
+use mongodb::bson::oid::ObjectId;
+use serde::{Serialize, Deserialize};
+
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum OrgRole{
+    // works in the organization's drives
+    Member,
+    // also manages members, drives and their quotas
+    Admin,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OrgMember{
+    pub user_id: ObjectId,
+
+    pub email: String,
+
+    pub role: OrgRole,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Organization{
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+
+    pub name: String,
+
+    pub members: Vec<OrgMember>,
+
+    #[serde(rename = "createdAt")]
+    pub created_at: bson::DateTime,
+}
+
+impl Organization {
+    pub fn member_role(&self, user_id: &ObjectId) -> Option<OrgRole> {
+        self.members.iter().find(|member| member.user_id == *user_id).map(|member| member.role)
+    }
+}
+
+// A drive that belongs to an organization instead of a user. Its id takes the place of a user id
+// as the owner (`user_id`) of the files and folders in it, so everything scoped to an owner works
+// for drives the same way.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Drive{
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+
+    pub org_id: ObjectId,
+
+    pub name: String,
+
+    // storage limit in bytes, DEFAULT_QUOTA_BYTES applies when not set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub quota: Option<u64>,
+
+    // keeps replaced contents as versions, like the setting of the same name users have
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub versioning: Option<bool>,
+
+    #[serde(rename = "createdAt")]
+    pub created_at: bson::DateTime,
+}
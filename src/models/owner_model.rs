@@ -0,0 +1,107 @@
+use bson::Bson;
+use mongodb::bson::oid::ObjectId;
+use serde::{Serialize, Deserialize};
+
+
+// Files and folders belong to a user or to a shared drive of an organization. Either way the
+// owner's id is in their `user_id`, `owner_kind` says which of the two it is. Documents from
+// before drives existed don't have it and belong to users.
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum OwnerKind{
+    #[default]
+    User,
+    Drive,
+}
+
+impl From<OwnerKind> for Bson {
+    fn from(kind: OwnerKind) -> Self {
+        match kind {
+            OwnerKind::User => Bson::String(String::from("user")),
+            OwnerKind::Drive => Bson::String(String::from("drive")),
+        }
+    }
+}
+
+// Who a request works on behalf of once it is authorized. Quota, versioning and organization
+// access are looked up on the user or on the drive depending on `kind`, never by guessing from `id`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Owner{
+    pub kind: OwnerKind,
+    pub id: ObjectId,
+}
+
+impl Owner {
+    pub fn user(id: ObjectId) -> Self {
+        Self { kind: OwnerKind::User, id }
+    }
+
+    pub fn drive(id: ObjectId) -> Self {
+        Self { kind: OwnerKind::Drive, id }
+    }
+
+    pub fn is_user(&self, user_id: &ObjectId) -> bool {
+        self.kind == OwnerKind::User && self.id == *user_id
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use bson::doc;
+    use mongodb::bson::oid::ObjectId;
+    use crate::models::file_model::File;
+    use crate::models::file_request_model::FileRequest;
+    use crate::models::folder_model::Folder;
+    use super::{Owner, OwnerKind};
+
+    fn stored_file(user_id: ObjectId, owner_kind: Option<&str>) -> File {
+        let mut file = doc! {"file_name": "a.txt", "file_type": "text/plain", "aws_file_name": "key", "file_location": "url", "size": 1_i64, "user_id": user_id};
+        if let Some(owner_kind) = owner_kind {
+            file.insert("owner_kind", owner_kind);
+        }
+        bson::from_document(file).unwrap()
+    }
+
+    #[test]
+    fn a_drive_is_never_a_user() {
+        let id = ObjectId::new();
+
+        assert!(Owner::user(id).is_user(&id));
+        assert!(!Owner::drive(id).is_user(&id));
+        assert!(!Owner::user(id).is_user(&ObjectId::new()));
+    }
+
+    #[test]
+    fn stored_as_snake_case() {
+        assert_eq!(bson::to_bson(&OwnerKind::Drive).unwrap(), bson::Bson::from(OwnerKind::Drive));
+        assert_eq!(bson::to_bson(&OwnerKind::User).unwrap(), bson::Bson::from(OwnerKind::User));
+    }
+
+    #[test]
+    fn items_resolve_to_the_owner_stored_on_them() {
+        let id = ObjectId::new();
+
+        assert_eq!(stored_file(id, Some("drive")).owner(), Some(Owner::drive(id)));
+        assert_eq!(stored_file(id, Some("user")).owner(), Some(Owner::user(id)));
+
+        let folder: Folder = bson::from_document(doc! {"folder_name": "docs", "user_id": id, "owner_kind": "drive"}).unwrap();
+        assert_eq!(folder.owner(), Some(Owner::drive(id)));
+    }
+
+    #[test]
+    fn items_from_before_drives_belong_to_users() {
+        let id = ObjectId::new();
+
+        assert_eq!(stored_file(id, None).owner(), Some(Owner::user(id)));
+
+        let folder: Folder = bson::from_document(doc! {"folder_name": "docs", "user_id": id}).unwrap();
+        assert_eq!(folder.owner(), Some(Owner::user(id)));
+
+        let request: FileRequest = bson::from_document(doc! {
+            "token": "t", "user_id": id, "created_by": id, "folder_id": ObjectId::new(),
+            "require_uploader": false, "files": 0_i64, "createdAt": bson::DateTime::now(),
+        }).unwrap();
+        assert_eq!(request.owner(), Owner::user(id));
+    }
+}
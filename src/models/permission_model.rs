@@ -0,0 +1,39 @@
+use mongodb::bson::oid::ObjectId;
+use serde::{Serialize, Deserialize};
+use crate::models::share_model::SharedItemType;
+
+
+// Ordered from the least to the most a grantee may do, a role includes everything below it
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum Role{
+    Viewer,
+    Commenter,
+    Editor,
+    CoOwner,
+}
+
+// Access to a file or folder granted by its owner to another user. A grant on a folder applies
+// to everything below it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Permission{
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+
+    pub owner_id: ObjectId,
+
+    pub grantee_id: ObjectId,
+
+    pub grantee_email: String,
+
+    pub item_type: SharedItemType,
+
+    pub item_id: ObjectId,
+
+    pub role: Role,
+
+    pub granted_by: ObjectId,
+
+    #[serde(rename = "createdAt")]
+    pub created_at: bson::DateTime,
+}
@@ -0,0 +1,93 @@
+use mongodb::bson::oid::ObjectId;
+use serde::{Serialize, Deserialize};
+
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq)]
+pub enum SharedItemType{
+    File,
+    Folder,
+}
+
+// A public link to a file or a folder subtree. Whoever has the token can see it, within the
+// limits set on the link.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Share{
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+
+    pub token: String,
+
+    pub user_id: ObjectId,
+
+    // a co-owner of the item, or the owner
+    #[serde(default)]
+    pub created_by: Option<ObjectId>,
+
+    pub item_type: SharedItemType,
+
+    pub item_id: ObjectId,
+
+    // bcrypt hash, same as user passwords
+    pub password: Option<String>,
+
+    // wrong passwords since the last lockout, the link locks until `locked_until` when too many pile up
+    #[serde(default)]
+    pub failed_attempts: i32,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub locked_until: Option<bson::DateTime>,
+
+    pub expires_at: Option<bson::DateTime>,
+
+    pub max_downloads: Option<i64>,
+
+    pub downloads: i64,
+
+    pub revoked_at: Option<bson::DateTime>,
+
+    #[serde(rename = "createdAt")]
+    pub created_at: bson::DateTime,
+}
+
+// What the owner gets to see of a share, never the password hash
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ShareResponse{
+    #[serde(rename = "_id")]
+    pub id: Option<ObjectId>,
+
+    pub token: String,
+
+    pub item_type: SharedItemType,
+
+    pub item_id: ObjectId,
+
+    pub has_password: bool,
+
+    pub expires_at: Option<bson::DateTime>,
+
+    pub max_downloads: Option<i64>,
+
+    pub downloads: i64,
+
+    pub revoked_at: Option<bson::DateTime>,
+
+    #[serde(rename = "createdAt")]
+    pub created_at: bson::DateTime,
+}
+
+impl From<Share> for ShareResponse {
+    fn from(share: Share) -> Self {
+        Self {
+            id: share.id,
+            token: share.token,
+            item_type: share.item_type,
+            item_id: share.item_id,
+            has_password: share.password.is_some(),
+            expires_at: share.expires_at,
+            max_downloads: share.max_downloads,
+            downloads: share.downloads,
+            revoked_at: share.revoked_at,
+            created_at: share.created_at,
+        }
+    }
+}
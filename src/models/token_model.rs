@@ -1,9 +1,71 @@
 use mongodb::bson::oid::ObjectId;
 use serde::{Serialize, Deserialize};
 
+// Every token is signed with the same secret, the kind keeps one from being accepted as another.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TokenKind {
+    Login,
+    ShareAccess,
+    Upload,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Claims {
+    pub kind: TokenKind,
     pub id: Option<ObjectId>,
     pub email: String,
     pub exp: i64,
-}
\ No newline at end of file
+}
+
+// Handed out with a presigned upload URL and given back on finalize, so the
+// key and expected size the backend chose can't be swapped by the client.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UploadClaims {
+    pub kind: TokenKind,
+    pub user_id: ObjectId,
+    pub key: String,
+    pub file_name: String,
+    pub file_type: String,
+    pub size: u64,
+    pub exp: i64,
+}
+
+// Handed out for the right password of a share link, so later requests carry this instead of the password.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ShareAccessClaims {
+    pub kind: TokenKind,
+    pub share_id: ObjectId,
+    pub exp: i64,
+}
+
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+    use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
+    use mongodb::bson::oid::ObjectId;
+    use super::{Claims, ShareAccessClaims, TokenKind, UploadClaims};
+
+    const SECRET: &[u8] = b"test secret";
+
+    #[test]
+    fn share_access_tokens_are_no_login_tokens() {
+        let claims = ShareAccessClaims{ kind: TokenKind::ShareAccess, share_id: ObjectId::new(), exp: Utc::now().timestamp() + 60 };
+        let token = encode(&Header::default(), &claims, &EncodingKey::from_secret(SECRET)).unwrap();
+
+        assert!(decode::<Claims>(&token, &DecodingKey::from_secret(SECRET), &Validation::default()).is_err());
+    }
+
+    #[test]
+    fn tokens_carry_their_kind() {
+        let claims = Claims{ kind: TokenKind::Login, id: Some(ObjectId::new()), email: "ann@example.com".to_string(), exp: Utc::now().timestamp() + 60 };
+        let token = encode(&Header::default(), &claims, &EncodingKey::from_secret(SECRET)).unwrap();
+
+        // a login token has every field an upload token needs but the kind, which is checked on finalize
+        let decoded = decode::<Claims>(&token, &DecodingKey::from_secret(SECRET), &Validation::default()).unwrap();
+        assert_eq!(decoded.claims.kind, TokenKind::Login);
+        assert!(decode::<UploadClaims>(&token, &DecodingKey::from_secret(SECRET), &Validation::default()).is_err());
+        assert!(decode::<ShareAccessClaims>(&token, &DecodingKey::from_secret(SECRET), &Validation::default()).is_err());
+    }
+}
@@ -0,0 +1,65 @@
+use mongodb::bson::oid::ObjectId;
+use serde::{Serialize, Deserialize};
+use crate::models::file_model::File;
+use crate::models::folder_model::Folder;
+
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq)]
+pub enum TrashItemType{
+    File,
+    Folder,
+}
+
+// A deleted file or folder subtree. The documents are taken out of files/folders as they were
+// and kept one per row in trashed_files/trashed_folders, so restoring puts them back with the
+// same ids and a large subtree never has to fit in a single document.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrashItem{
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+
+    pub user_id: ObjectId,
+
+    pub item_type: TrashItemType,
+
+    pub item_id: ObjectId,
+
+    pub name: String,
+
+    // folder the item was deleted from, None for items at the root
+    pub original_parent: Option<ObjectId>,
+
+    // the folders from the root down to original_parent at the time of the delete,
+    // used to recreate them if they are gone by the time the item is restored
+    pub ancestors: Vec<Folder>,
+
+    pub size: u64,
+
+    #[serde(rename = "deletedAt")]
+    pub deleted_at: bson::DateTime,
+}
+
+// A file of a trashed item, `id` is the id the file had and gets back on restore.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrashedFile{
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+
+    pub trash_id: ObjectId,
+
+    pub user_id: ObjectId,
+
+    pub file: File,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrashedFolder{
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+
+    pub trash_id: ObjectId,
+
+    pub user_id: ObjectId,
+
+    pub folder: Folder,
+}
@@ -0,0 +1,40 @@
+use mongodb::bson::oid::ObjectId;
+use serde::{Serialize, Deserialize};
+
+
+// Resumable (tus) upload in progress, the bytes live in the staging area until the last PATCH
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Upload{
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+
+    pub user_id: ObjectId,
+
+    pub length: u64,
+
+    pub offset: u64,
+
+    pub file_name: String,
+
+    pub file_type: String,
+
+    pub folder_id: Option<ObjectId>,
+
+    // shared drive the file goes to the root of when there is no folder
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub drive_id: Option<ObjectId>,
+
+    // set once the upload has been turned into a regular File
+    pub file_id: Option<ObjectId>,
+
+    // set while a PATCH is writing to the staging file, only one may at a time
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub locked_at: Option<bson::DateTime>,
+
+    // stored as BSON dates (not strings) so expired uploads can be found with a range query
+    #[serde(rename = "createdAt")]
+    pub created_at: bson::DateTime,
+
+    #[serde(rename = "expiresAt")]
+    pub expires_at: bson::DateTime,
+}
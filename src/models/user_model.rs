@@ -13,5 +13,13 @@ pub struct User{
     pub id: Option<ObjectId>,
     pub email: String,
     pub password: String,
+
+    // storage limit in bytes, DEFAULT_QUOTA_BYTES applies when not set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub quota: Option<u64>,
+
+    // re-uploading a name into the same folder adds a version instead of a "name (n).ext" copy
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub versioning: Option<bool>,
 }
 
@@ -0,0 +1,30 @@
+use mongodb::bson::oid::ObjectId;
+use serde::{Serialize, Deserialize};
+
+
+// An older content of a File, kept when the same name is uploaded again into the same folder
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileVersion{
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+
+    pub file_id: ObjectId,
+
+    pub user_id: ObjectId,
+
+    pub version: i64,
+
+    pub file_type: String,
+
+    pub aws_file_name: String,
+
+    pub file_location: String,
+
+    pub size: u64,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content_hash: Option<String>,
+
+    #[serde(rename = "createdAt")]
+    pub created_at: bson::DateTime,
+}
@@ -1,11 +1,11 @@
-use std::env;
 use axum::Json;
-use dotenv::dotenv;
-use mongodb::{Client, Collection};
-use mongodb::options::ClientOptions;
+use bson::doc;
+use mongodb::{Collection, IndexModel};
+use mongodb::error::Error;
+use mongodb::options::{Collation, CollationStrength, FindOneOptions, IndexOptions};
 use mongodb::results::InsertOneResult;
 use crate::models::user_model::{User};
-use crate::services::trait_service::StorageCollection;
+use crate::services::trait_service::{mongo_client, StorageCollection};
 use async_trait::async_trait;
 
 
@@ -14,22 +14,32 @@ pub struct UserCollection{
     pub user_collection: Collection<User>
 }
 
+// Compares emails ignoring case, whatever case they were signed up with
+fn email_collation() -> Collation {
+    Collation::builder().locale("en").strength(CollationStrength::Secondary).build()
+}
+
+impl UserCollection {
+
+    pub async fn find_by_email(&self, email: &str) -> Result<Option<User>, Error> {
+        let options = FindOneOptions::builder().collation(email_collation()).build();
+        self.user_collection.find_one(doc! {"email": email}, options).await
+    }
+}
+
 
 #[async_trait]
 impl StorageCollection for UserCollection{
     type Error = Box<dyn std::error::Error>;
 
     async fn init() -> Result<Self, Self::Error> where Self: Sized {
-        dotenv().ok();
-        let mongo_uri = env::var("MONGODB_URI").expect("MONGODB_URI not found in env");
-
-
-        let client_options = ClientOptions::parse(mongo_uri).await?;
-
-        let client = Client::with_options(client_options)?;
+        let client = mongo_client().await?;
         let db = client.database("cloud_storage");
         let col: Collection<User> = db.collection("users");
 
+        let options = IndexOptions::builder().name("email_ci".to_string()).collation(email_collation()).build();
+        col.create_index(IndexModel::builder().keys(doc! {"email": 1}).options(options).build(), None).await?;
+
         Ok(UserCollection{user_collection: col})
     }
 }
@@ -0,0 +1,338 @@
+use std::io;
+use std::path::Path;
+use async_trait::async_trait;
+use bson::{doc, Document};
+use bson::oid::ObjectId;
+use futures::{StreamExt, TryStreamExt};
+use mongodb::{Collection, IndexModel};
+use mongodb::options::{FindOneAndUpdateOptions, ReturnDocument, UpdateOptions};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::fs;
+use tokio::io::AsyncWriteExt;
+use tokio_util::io::ReaderStream;
+use crate::AppState;
+use crate::models::blob_model::Blob;
+use crate::models::file_model::File;
+use crate::services::trait_service::{mongo_client, StorageCollection};
+use crate::storage::blob_store::ByteStream;
+
+
+// Fails the stream with InvalidInput as soon as more than `limit` bytes came through, so nothing
+// bigger than allowed is ever staged or stored.
+pub fn limited(body: ByteStream<'_>, limit: u64) -> ByteStream<'_> {
+    let mut seen = 0u64;
+
+    body.map(move |chunk| {
+        let chunk = chunk?;
+        seen += chunk.len() as u64;
+
+        if seen > limit {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Body is larger than allowed"));
+        }
+
+        Ok(chunk)
+    }).boxed()
+}
+
+pub fn new_blob_key(user_id: &ObjectId) -> String {
+    format!("{}/{}", user_id, ObjectId::new())
+}
+
+// Keys a client may hand us for content it wrote itself, the ones new_blob_key gives out to that user.
+// Anything else belongs to another user or to the content store.
+pub fn issued_to(key: &str, user_id: &ObjectId) -> bool {
+    match key.strip_prefix(&format!("{}/", user_id)) {
+        Some(rest) => ObjectId::parse_str(rest).is_ok(),
+        None => false
+    }
+}
+
+// What hash_stream produces, a hex encoded SHA-256. Hashes from clients are only trusted in this shape.
+pub fn valid_content_hash(hash: &str) -> bool {
+    hash.len() == 64 && hash.bytes().all(|byte| matches!(byte, b'0'..=b'9' | b'a'..=b'f'))
+}
+
+pub fn content_blob_key(hash: &str) -> String {
+    format!("blobs/{}/{}", &hash[..2], hash)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StorageUsage {
+    // what the user sees: every copy of a file counts
+    pub logical_bytes: u64,
+    // what the user's files actually take up once identical content is stored once
+    pub physical_bytes: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct BlobCollection{
+    pub blob_collection: Collection<Blob>,
+}
+
+impl BlobCollection {
+
+    pub async fn get_blob(&self, hash: &str) -> Result<Option<Blob>, mongodb::error::Error>{
+        self.blob_collection.find_one(doc! {"_id": hash}, None).await
+    }
+
+    pub async fn update_blob(&self, filter: Document, update: Document) -> Result<Option<Blob>, mongodb::error::Error>{
+        let options = FindOneAndUpdateOptions::builder().return_document(ReturnDocument::After).build();
+        self.blob_collection.find_one_and_update(filter, update, options).await
+    }
+}
+
+
+async fn hash_stream(mut body: ByteStream<'_>, spool_to: Option<&Path>) -> io::Result<(String, u64)> {
+    let mut hasher = Sha256::new();
+    let mut size = 0;
+
+    let mut spool = match spool_to {
+        Some(path) => Some(fs::File::create(path).await?),
+        None => None
+    };
+
+    while let Some(chunk) = body.next().await {
+        let chunk = chunk?;
+        hasher.update(&chunk);
+        size += chunk.len() as u64;
+
+        if let Some(spool) = &mut spool {
+            spool.write_all(&chunk).await?;
+        }
+    }
+
+    if let Some(spool) = &mut spool {
+        spool.flush().await?;
+    }
+
+    Ok((hex::encode(hasher.finalize()), size))
+}
+
+impl AppState {
+
+    // The client only tells us which key it wrote to; where the bytes live, how big they are
+    // and which blob they belong to is decided here. Like every other way of adding a file, the
+    // file ends up holding one reference on its blob, release it if the file is not stored.
+    pub async fn locate_blob(&self, file: &mut File, user_id: &ObjectId, owner_id: &ObjectId) -> io::Result<()> {
+        let client_hash = file.content_hash.take();
+        file.uploaded_by = None;
+        file.version = None;
+
+        // another copy of content the user can already see, e.g. a tree exported with /folder/tree
+        if let Some(hash) = client_hash.filter(|hash| valid_content_hash(hash) && file.aws_file_name == content_blob_key(hash)) {
+            let filter = doc! {"content_hash": &hash, "user_id": {"$in": [user_id, owner_id]}};
+            let known = self.file_collection.file_collection.find_one(filter, None).await.map_err(io::Error::other)?.is_some();
+
+            if known {
+                if let Some(blob) = self.blob_collection.update_blob(doc! {"_id": &hash}, doc! {"$inc": {"ref_count": 1}}).await.map_err(io::Error::other)? {
+                    self.describe_blob(file, blob);
+                    return Ok(());
+                }
+            }
+        }
+
+        if !issued_to(&file.aws_file_name, user_id) {
+            return Err(io::Error::new(io::ErrorKind::PermissionDenied, "Key was not issued to this user"));
+        }
+
+        let blob = self.adopt_blob(&file.aws_file_name).await?;
+        self.describe_blob(file, blob);
+
+        Ok(())
+    }
+
+    fn describe_blob(&self, file: &mut File, blob: Blob) {
+        file.file_location = self.blob_store.location(&blob.key);
+        file.aws_file_name = blob.key;
+        file.size = blob.size;
+        file.content_hash = Some(blob.hash);
+    }
+
+    // Takes one reference on the blob for `hash`, writing the staged content to the store
+    // only when nobody has uploaded the same bytes before.
+    async fn store_staged_blob(&self, staged: &Path, hash: String, size: u64) -> io::Result<Blob> {
+        if let Some(blob) = self.blob_collection.update_blob(doc! {"_id": &hash}, doc! {"$inc": {"ref_count": 1}}).await.map_err(io::Error::other)? {
+            return Ok(blob);
+        }
+
+        let key = content_blob_key(&hash);
+        let staged_file = fs::File::open(staged).await?;
+        self.blob_store.put(&key, ReaderStream::new(staged_file).boxed()).await?;
+
+        self.register_blob(hash, key, size).await
+    }
+
+    async fn register_blob(&self, hash: String, key: String, size: u64) -> io::Result<Blob> {
+        let update = doc! {
+            "$inc": {"ref_count": 1},
+            "$setOnInsert": {"key": &key, "size": size as i64, "createdAt": bson::DateTime::now()},
+        };
+
+        let options = UpdateOptions::builder().upsert(true).build();
+        self.blob_collection.blob_collection.update_one(doc! {"_id": &hash}, update, options).await.map_err(io::Error::other)?;
+
+        match self.blob_collection.get_blob(&hash).await.map_err(io::Error::other)? {
+            Some(blob) => Ok(blob),
+            None => Err(io::Error::new(io::ErrorKind::NotFound, format!("Blob {} disappeared", hash)))
+        }
+    }
+
+    // Hashes the body on the way in and stores it once per unique content.
+    pub async fn ingest_blob(&self, body: ByteStream<'_>) -> io::Result<Blob> {
+        let staged = self.upload_collection.staging_path.join(format!("ingest-{}", ObjectId::new()));
+
+        let stored = async {
+            let (hash, size) = hash_stream(body, Some(&staged)).await?;
+            self.store_staged_blob(&staged, hash, size).await
+        }.await;
+
+        let _ = fs::remove_file(&staged).await;
+        stored
+    }
+
+    pub async fn ingest_staged_file(&self, staged: &Path) -> io::Result<Blob> {
+        let staged_file = fs::File::open(staged).await?;
+        let (hash, size) = hash_stream(ReaderStream::new(staged_file).boxed(), None).await?;
+
+        self.store_staged_blob(staged, hash, size).await
+    }
+
+    // For objects a client wrote straight into the store: if the content is already known the
+    // new copy is dropped, otherwise the object becomes the blob for its hash where it is.
+    pub async fn adopt_blob(&self, key: &str) -> io::Result<Blob> {
+        let body = self.blob_store.stream(key, None).await?;
+        let (hash, size) = hash_stream(body, None).await?;
+
+        if let Some(blob) = self.blob_collection.update_blob(doc! {"_id": &hash}, doc! {"$inc": {"ref_count": 1}}).await.map_err(io::Error::other)? {
+            if blob.key != key {
+                self.blob_store.delete(key).await?;
+            }
+            return Ok(blob);
+        }
+
+        self.register_blob(hash, key.to_string(), size).await
+    }
+
+    pub async fn retain_blob(&self, hash: &str) -> io::Result<()> {
+        self.blob_collection.update_blob(doc! {"_id": hash}, doc! {"$inc": {"ref_count": 1}}).await.map_err(io::Error::other)?;
+        Ok(())
+    }
+
+    // Drops one reference, the blob record goes away with the last one. The stored object is
+    // left to the GC: the same content may be ingested again right after and written to the same
+    // key, deleting it here could take it from under the new record.
+    pub async fn release_blob(&self, hash: &str) -> io::Result<()> {
+        let blob = self.blob_collection.update_blob(doc! {"_id": hash}, doc! {"$inc": {"ref_count": -1}}).await.map_err(io::Error::other)?;
+
+        if blob.map(|blob| blob.ref_count <= 0).unwrap_or(false) {
+            let filter = doc! {"_id": hash, "ref_count": {"$lte": 0}};
+            self.blob_collection.blob_collection.delete_one(filter, None).await.map_err(io::Error::other)?;
+        }
+
+        Ok(())
+    }
+
+    // Releases the content of deleted files, including every version they had.
+    pub async fn release_files(&self, files: &[File]) {
+        let file_ids = files.iter().filter_map(|file| file.id).collect::<Vec<_>>();
+
+        if let Err(err) = self.delete_file_versions(&file_ids).await {
+            println!("Failed to delete file versions: {}", err);
+        }
+
+        for file in files {
+            if let Some(hash) = &file.content_hash {
+                if let Err(err) = self.release_blob(hash).await {
+                    println!("Failed to release blob {}: {}", hash, err);
+                }
+            }
+        }
+    }
+
+    pub async fn get_storage_usage(&self, user_id: &ObjectId) -> Result<StorageUsage, mongodb::error::Error> {
+        let pipeline = vec![
+            doc! {"$match": {"user_id": user_id}},
+            // older versions take up space too
+            doc! {"$unionWith": {"coll": "file_versions", "pipeline": [{"$match": {"user_id": user_id}}]}},
+            // and so do files sitting in the trash
+            doc! {"$unionWith": {"coll": "trashed_files", "pipeline": [
+                {"$match": {"user_id": user_id}},
+                {"$replaceRoot": {"newRoot": "$file"}},
+            ]}},
+            doc! {"$facet": {
+                "logical": [
+                    {"$group": {"_id": null, "total": {"$sum": "$size"}}},
+                ],
+                "physical": [
+                    {"$group": {"_id": {"$ifNull": ["$content_hash", "$_id"]}, "size": {"$first": "$size"}}},
+                    {"$group": {"_id": null, "total": {"$sum": "$size"}}},
+                ],
+            }},
+        ];
+
+        let result = self.file_collection.file_collection.aggregate(pipeline, None).await?.try_next().await?;
+
+        let total = |facet: &str| -> u64 {
+            result.as_ref()
+                .and_then(|result| result.get_array(facet).ok())
+                .and_then(|facet| facet.first())
+                .and_then(|group| group.as_document())
+                .and_then(|group| group.get("total"))
+                .and_then(|total| total.as_i64().or(total.as_i32().map(i64::from)))
+                .unwrap_or(0) as u64
+        };
+
+        Ok(StorageUsage{
+            logical_bytes: total("logical"),
+            physical_bytes: total("physical"),
+        })
+    }
+}
+
+
+#[async_trait]
+impl StorageCollection for BlobCollection{
+    type Error = Box<dyn std::error::Error>;
+
+    async fn init() -> Result<Self, Self::Error> where Self: Sized {
+        let client = mongo_client().await?;
+        let db = client.database("cloud_storage");
+        let col: Collection<Blob> = db.collection("blobs");
+
+        // the GC checks by key whether an object has a record again before deleting it
+        col.create_index(IndexModel::builder().keys(doc! {"key": 1}).build(), None).await?;
+
+        Ok(Self{ blob_collection: col })
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use bson::oid::ObjectId;
+    use super::{content_blob_key, issued_to, valid_content_hash};
+
+    #[test]
+    fn accepts_only_sha256_hex_hashes() {
+        let hash = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+
+        assert!(valid_content_hash(hash));
+        assert_eq!(content_blob_key(hash), format!("blobs/e3/{}", hash));
+
+        assert!(!valid_content_hash(&hash.to_uppercase()));
+        assert!(!valid_content_hash(&hash[..63]));
+        assert!(!valid_content_hash("aé"));
+        assert!(!valid_content_hash(&format!("é{}", &hash[2..])));
+    }
+
+    #[test]
+    fn keys_are_issued_to_one_user() {
+        let (user_id, other_id) = (ObjectId::new(), ObjectId::new());
+        let key = format!("{}/{}", user_id, ObjectId::new());
+
+        assert!(issued_to(&key, &user_id));
+        assert!(!issued_to(&key, &other_id));
+        assert!(!issued_to(&format!("{}/../blobs/e3/x", user_id), &user_id));
+    }
+}
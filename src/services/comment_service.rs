@@ -0,0 +1,302 @@
+use std::collections::HashMap;
+use async_trait::async_trait;
+use axum::http::StatusCode;
+use bson::{doc, Document};
+use bson::oid::ObjectId;
+use futures::TryStreamExt;
+use mongodb::{Collection, IndexModel};
+use mongodb::error::Error;
+use mongodb::options::{FindOneAndUpdateOptions, FindOptions, ReturnDocument};
+use crate::AppState;
+use crate::error::storage_error::StorageError;
+use crate::models::comment_model::{Comment, CommentThread, Mention};
+use crate::models::permission_model::Role;
+use crate::models::share_model::SharedItemType;
+use crate::services::trait_service::{mongo_client, StorageCollection};
+
+
+#[derive(Debug, Clone)]
+pub struct CommentCollection{
+    pub comment_collection: Collection<Comment>,
+}
+
+impl CommentCollection {
+
+    pub async fn get_comments(&self, filter: Document) -> Result<Vec<Comment>, Error>{
+        let options = FindOptions::builder().sort(doc! {"createdAt": 1}).build();
+        self.comment_collection.find(filter, options).await?.try_collect::<Vec<Comment>>().await
+    }
+
+    pub async fn delete_comments(&self, filter: Document) -> Result<u64, Error>{
+        Ok(self.comment_collection.delete_many(filter, None).await?.deleted_count)
+    }
+}
+
+// Every distinct "@someone@example.com" in the text, without the leading @ and trailing punctuation.
+fn mentioned_emails(body: &str) -> Vec<String> {
+    let mut emails: Vec<String> = vec![];
+
+    for word in body.split_whitespace() {
+        let email = match word.strip_prefix('@') {
+            Some(email) => email.trim_end_matches(|c: char| ",.;:!?)".contains(c)).to_lowercase(),
+            None => continue
+        };
+
+        if email.contains('@') && !emails.contains(&email) {
+            emails.push(email);
+        }
+    }
+
+    emails
+}
+
+// Groups comments sorted by creation into threads, replies under the comment they answer.
+fn threads(comments: Vec<Comment>) -> Vec<CommentThread> {
+    let mut replies: HashMap<ObjectId, Vec<Comment>> = HashMap::new();
+    let mut roots = vec![];
+
+    for comment in comments {
+        match comment.parent_id {
+            Some(parent_id) => replies.entry(parent_id).or_default().push(comment),
+            None => roots.push(comment)
+        }
+    }
+
+    roots.into_iter().map(|comment| {
+        let replies = comment.id.and_then(|id| replies.remove(&id)).unwrap_or_default();
+        CommentThread{ comment, replies }
+    }).collect()
+}
+
+impl AppState {
+
+    // Mentions only reach users who can see the file, anyone else stays plain text.
+    async fn mentions(&self, file_id: &ObjectId, body: &str) -> Result<Vec<Mention>, Error> {
+        let mut mentions = vec![];
+
+        for email in mentioned_emails(body) {
+            let user_id = match self.user_collection.find_by_email(&email).await?.and_then(|user| user.id) {
+                Some(user_id) => user_id,
+                None => continue
+            };
+
+            if self.role_on(&user_id, SharedItemType::File, file_id).await?.is_some() {
+                mentions.push(Mention{ user_id, email });
+            }
+        }
+
+        Ok(mentions)
+    }
+
+    async fn find_comment(&self, comment_id: &ObjectId) -> Result<Comment, StorageError> {
+        self.comment_collection.comment_collection.find_one(doc! {"_id": comment_id}, None).await?
+            .ok_or(StorageError::Status(StatusCode::NOT_FOUND))
+    }
+
+    pub async fn add_comment(&self, user_id: &ObjectId, email: &str, file_id: &ObjectId, parent_id: Option<ObjectId>, body: &str) -> Result<Comment, StorageError> {
+        let owner_id = self.authorize(user_id, SharedItemType::File, file_id, Role::Commenter).await?.id;
+
+        // replying to a reply continues the same thread
+        let parent_id = match parent_id {
+            Some(parent_id) => {
+                let parent = self.find_comment(&parent_id).await?;
+
+                if parent.file_id != *file_id {
+                    return Err(StatusCode::BAD_REQUEST.into());
+                }
+
+                Some(parent.parent_id.unwrap_or(parent_id))
+            },
+            None => None
+        };
+
+        let mut comment = Comment{
+            id: None,
+            file_id: *file_id,
+            owner_id,
+            parent_id,
+            author_id: *user_id,
+            author_email: email.to_string(),
+            body: body.to_string(),
+            mentions: self.mentions(file_id, body).await?,
+            resolved_at: None,
+            resolved_by: None,
+            edited_at: None,
+            created_at: bson::DateTime::now(),
+        };
+
+        let inserted = self.comment_collection.comment_collection.insert_one(&comment, None).await?;
+        comment.id = inserted.inserted_id.as_object_id();
+
+        Ok(comment)
+    }
+
+    // Only the author changes what a comment says, and only while they can still comment.
+    pub async fn edit_comment(&self, user_id: &ObjectId, comment_id: &ObjectId, body: &str) -> Result<Comment, StorageError> {
+        let comment = self.find_comment(comment_id).await?;
+
+        if comment.author_id != *user_id {
+            return Err(StatusCode::FORBIDDEN.into());
+        }
+
+        self.authorize(user_id, SharedItemType::File, &comment.file_id, Role::Commenter).await?;
+
+        let mentions = bson::to_bson(&self.mentions(&comment.file_id, body).await?).unwrap_or_default();
+        let update = doc! {"$set": {"body": body, "mentions": mentions, "edited_at": bson::DateTime::now()}};
+        let options = FindOneAndUpdateOptions::builder().return_document(ReturnDocument::After).build();
+
+        self.comment_collection.comment_collection.find_one_and_update(doc! {"_id": comment_id}, update, options).await?
+            .ok_or(StorageError::Status(StatusCode::NOT_FOUND))
+    }
+
+    // Deleting the first comment of a thread takes the replies with it.
+    pub async fn delete_comment(&self, user_id: &ObjectId, comment_id: &ObjectId) -> Result<u64, StorageError> {
+        let comment = self.find_comment(comment_id).await?;
+
+        if comment.author_id != *user_id {
+            return Err(StatusCode::FORBIDDEN.into());
+        }
+
+        let deleted = self.comment_collection.delete_comments(doc! {"$or": [{"_id": comment_id}, {"parent_id": comment_id}]}).await?;
+        Ok(deleted)
+    }
+
+    pub async fn set_resolved(&self, user_id: &ObjectId, comment_id: &ObjectId, resolved: bool) -> Result<Comment, StorageError> {
+        let comment = self.find_comment(comment_id).await?;
+
+        if comment.parent_id.is_some() {
+            return Err(StatusCode::BAD_REQUEST.into());
+        }
+
+        self.authorize(user_id, SharedItemType::File, &comment.file_id, Role::Commenter).await?;
+
+        let update = match resolved {
+            true => doc! {"$set": {"resolved_at": bson::DateTime::now(), "resolved_by": user_id}},
+            false => doc! {"$set": {"resolved_at": null, "resolved_by": null}},
+        };
+        let options = FindOneAndUpdateOptions::builder().return_document(ReturnDocument::After).build();
+
+        self.comment_collection.comment_collection.find_one_and_update(doc! {"_id": comment_id}, update, options).await?
+            .ok_or(StorageError::Status(StatusCode::NOT_FOUND))
+    }
+
+    // Comments mentioning the user on files they can still see, access may have gone since.
+    pub async fn mentions_of(&self, user_id: &ObjectId) -> Result<Vec<Comment>, Error> {
+        let comments = self.comment_collection.get_comments(doc! {"mentions.user_id": user_id}).await?;
+        let mut visible: HashMap<ObjectId, bool> = HashMap::new();
+        let mut mentions = vec![];
+
+        for comment in comments {
+            let can_see = match visible.get(&comment.file_id) {
+                Some(can_see) => *can_see,
+                None => {
+                    let can_see = self.role_on(user_id, SharedItemType::File, &comment.file_id).await?.is_some();
+                    visible.insert(comment.file_id, can_see);
+                    can_see
+                }
+            };
+
+            if can_see {
+                mentions.push(comment);
+            }
+        }
+
+        Ok(mentions)
+    }
+
+    pub async fn file_comments(&self, user_id: &ObjectId, file_id: &ObjectId) -> Result<Vec<CommentThread>, StorageError> {
+        self.authorize(user_id, SharedItemType::File, file_id, Role::Viewer).await?;
+
+        let comments = self.comment_collection.get_comments(doc! {"file_id": file_id}).await?;
+        Ok(threads(comments))
+    }
+
+    // Comments on the files directly inside the folder.
+    pub async fn folder_comments(&self, user_id: &ObjectId, folder_id: &ObjectId) -> Result<Vec<CommentThread>, StorageError> {
+        let owner_id = self.authorize(user_id, SharedItemType::Folder, folder_id, Role::Viewer).await?.id;
+
+        let file_ids = self.file_collection.get_file(doc! {"folder_id": folder_id, "user_id": owner_id}).await?
+            .into_iter()
+            .filter_map(|file| file.id)
+            .collect::<Vec<_>>();
+
+        let comments = self.comment_collection.get_comments(doc! {"file_id": {"$in": file_ids}}).await?;
+        Ok(threads(comments))
+    }
+}
+
+
+#[async_trait]
+impl StorageCollection for CommentCollection{
+    type Error = Box<dyn std::error::Error>;
+
+    async fn init() -> Result<Self, Self::Error> where Self: Sized {
+        let client = mongo_client().await?;
+        let db = client.database("cloud_storage");
+        let col: Collection<Comment> = db.collection("comments");
+
+        col.create_index(IndexModel::builder().keys(doc! {"file_id": 1, "createdAt": 1}).build(), None).await?;
+        col.create_index(IndexModel::builder().keys(doc! {"mentions.user_id": 1}).build(), None).await?;
+
+        Ok(Self{ comment_collection: col })
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use bson::oid::ObjectId;
+    use crate::models::comment_model::Comment;
+    use super::{mentioned_emails, threads};
+
+    fn comment(id: ObjectId, parent_id: Option<ObjectId>, body: &str) -> Comment {
+        Comment{
+            id: Some(id),
+            file_id: ObjectId::new(),
+            owner_id: ObjectId::new(),
+            parent_id,
+            author_id: ObjectId::new(),
+            author_email: "author@example.com".to_string(),
+            body: body.to_string(),
+            mentions: vec![],
+            resolved_at: None,
+            resolved_by: None,
+            edited_at: None,
+            created_at: bson::DateTime::now(),
+        }
+    }
+
+    #[test]
+    fn finds_each_mentioned_email_once() {
+        let emails = mentioned_emails("@Ann@Example.com, can you and (@bob@example.com) check? cc @ann@example.com.");
+        assert_eq!(emails, vec!["ann@example.com".to_string()]);
+
+        let emails = mentioned_emails("thanks @ann@example.com! @bob@example.com: see above");
+        assert_eq!(emails, vec!["ann@example.com".to_string(), "bob@example.com".to_string()]);
+    }
+
+    #[test]
+    fn ignores_handles_and_plain_addresses() {
+        assert!(mentioned_emails("@ann and ann@example.com and @ alone").is_empty());
+    }
+
+    #[test]
+    fn groups_replies_under_their_thread() {
+        let (first, second) = (ObjectId::new(), ObjectId::new());
+
+        let comments = vec![
+            comment(first, None, "first"),
+            comment(ObjectId::new(), Some(first), "reply to first"),
+            comment(second, None, "second"),
+            comment(ObjectId::new(), Some(first), "another reply to first"),
+        ];
+
+        let threads = threads(comments);
+
+        assert_eq!(threads.len(), 2);
+        assert_eq!(threads[0].comment.body, "first");
+        assert_eq!(threads[0].replies.iter().map(|reply| reply.body.as_str()).collect::<Vec<_>>(), vec!["reply to first", "another reply to first"]);
+        assert_eq!(threads[1].comment.body, "second");
+        assert!(threads[1].replies.is_empty());
+    }
+}
@@ -0,0 +1,157 @@
+use async_trait::async_trait;
+use axum::http::StatusCode;
+use bson::{doc, Document};
+use bson::oid::ObjectId;
+use futures::TryStreamExt;
+use mongodb::{Collection, IndexModel};
+use mongodb::error::Error;
+use mongodb::options::{FindOneAndUpdateOptions, ReturnDocument};
+use crate::AppState;
+use crate::error::storage_error::StorageError;
+use crate::models::organization_model::{Drive, OrgRole};
+use crate::models::owner_model::{Owner, OwnerKind};
+use crate::models::permission_model::Role;
+use crate::services::trait_service::{mongo_client, StorageCollection};
+
+
+#[derive(Debug, Clone)]
+pub struct DriveCollection{
+    pub drive_collection: Collection<Drive>,
+}
+
+impl DriveCollection {
+
+    pub async fn get_drives(&self, filter: Document) -> Result<Vec<Drive>, Error>{
+        self.drive_collection.find(filter, None).await?.try_collect::<Vec<Drive>>().await
+    }
+}
+
+impl AppState {
+
+    // What a member of the drive's organization may do in it. None for everyone else, and when
+    // there is no such drive.
+    pub async fn drive_role(&self, user_id: &ObjectId, drive_id: &ObjectId) -> Result<Option<Role>, Error> {
+        let drive = match self.drive_collection.drive_collection.find_one(doc! {"_id": drive_id}, None).await? {
+            Some(drive) => drive,
+            None => return Ok(None)
+        };
+
+        let organization = self.organization_collection.get_organizations(doc! {"_id": drive.org_id, "members.user_id": user_id}).await?.into_iter().next();
+
+        Ok(organization.and_then(|organization| organization.member_role(user_id)).map(|role| match role {
+            OrgRole::Member => Role::Editor,
+            OrgRole::Admin => Role::CoOwner,
+        }))
+    }
+
+    // Owner for the root of the selected drive, the user's own drive when none is selected.
+    pub async fn authorize_root(&self, user_id: &ObjectId, drive_id: Option<ObjectId>, required: Role) -> Result<Owner, StorageError> {
+        match drive_id {
+            Some(drive_id) => match self.drive_role(user_id, &drive_id).await? {
+                Some(role) if role >= required => Ok(Owner::drive(drive_id)),
+                Some(_) => Err(StatusCode::FORBIDDEN.into()),
+                None => Err(StatusCode::NOT_FOUND.into())
+            },
+            None => Ok(Owner::user(*user_id))
+        }
+    }
+
+    // Owner for a place something is read from or put into: a folder, or else the root of a drive.
+    pub async fn authorize_location(&self, user_id: &ObjectId, folder_id: Option<ObjectId>, drive_id: Option<ObjectId>, required: Role) -> Result<Owner, StorageError> {
+        match folder_id {
+            Some(_) => self.authorize_folder(user_id, folder_id, required).await,
+            None => self.authorize_root(user_id, drive_id, required).await
+        }
+    }
+
+    // Drives of every organization the user is a member of.
+    pub async fn user_drives(&self, user_id: &ObjectId) -> Result<Vec<Drive>, Error> {
+        let org_ids = self.organization_collection.get_organizations(doc! {"members.user_id": user_id}).await?
+            .into_iter()
+            .filter_map(|organization| organization.id)
+            .collect::<Vec<_>>();
+
+        self.drive_collection.get_drives(doc! {"org_id": {"$in": org_ids}}).await
+    }
+
+    pub async fn create_drive(&self, admin_id: &ObjectId, org_id: &ObjectId, name: &str, quota: Option<u64>) -> Result<Drive, StorageError> {
+        self.authorize_org(admin_id, org_id, OrgRole::Admin).await?;
+
+        let mut drive = Drive{
+            id: None,
+            org_id: *org_id,
+            name: name.to_string(),
+            quota,
+            versioning: None,
+            created_at: bson::DateTime::now(),
+        };
+
+        let inserted = self.drive_collection.drive_collection.insert_one(&drive, None).await?;
+        drive.id = inserted.inserted_id.as_object_id();
+
+        Ok(drive)
+    }
+
+    // Items of shared drives used to be told apart from a user's only by their user_id being the id
+    // of a drive. Marks them, their trash and their file requests as owned by a drive.
+    pub async fn migrate_owner_kinds(&self) -> Result<(), Error> {
+        let drive_ids = self.drive_collection.get_drives(doc! {}).await?.into_iter().filter_map(|drive| drive.id).collect::<Vec<_>>();
+
+        if drive_ids.is_empty() {
+            return Ok(());
+        }
+
+        let owned = doc! {"user_id": {"$in": &drive_ids}};
+
+        self.file_collection.file_collection.update_many(owned.clone(), doc! {"$set": {"owner_kind": OwnerKind::Drive}}, None).await?;
+        self.folder_collection.folder_collection.update_many(owned.clone(), doc! {"$set": {"owner_kind": OwnerKind::Drive}}, None).await?;
+        self.trash_collection.trashed_file_collection.update_many(owned.clone(), doc! {"$set": {"file.owner_kind": OwnerKind::Drive}}, None).await?;
+        self.trash_collection.trashed_folder_collection.update_many(owned.clone(), doc! {"$set": {"folder.owner_kind": OwnerKind::Drive}}, None).await?;
+        self.file_request_collection.file_request_collection.update_many(owned, doc! {"$set": {"owner_kind": OwnerKind::Drive}}, None).await?;
+
+        Ok(())
+    }
+
+    pub async fn update_drive(&self, admin_id: &ObjectId, org_id: &ObjectId, drive_id: &ObjectId, name: Option<&str>, quota: Option<u64>, versioning: Option<bool>) -> Result<Drive, StorageError> {
+        self.authorize_org(admin_id, org_id, OrgRole::Admin).await?;
+
+        let drive = self.drive_collection.drive_collection.find_one(doc! {"_id": drive_id, "org_id": org_id}, None).await?
+            .ok_or(StorageError::Status(StatusCode::NOT_FOUND))?;
+
+        let mut update = doc! {};
+        if let Some(name) = name {
+            update.insert("name", name);
+        }
+        if let Some(quota) = quota {
+            update.insert("quota", quota as i64);
+        }
+        if let Some(versioning) = versioning {
+            update.insert("versioning", versioning);
+        }
+
+        if update.is_empty() {
+            return Ok(drive);
+        }
+
+        let options = FindOneAndUpdateOptions::builder().return_document(ReturnDocument::After).build();
+
+        self.drive_collection.drive_collection.find_one_and_update(doc! {"_id": drive_id}, doc! {"$set": update}, options).await?
+            .ok_or(StorageError::Status(StatusCode::NOT_FOUND))
+    }
+}
+
+
+#[async_trait]
+impl StorageCollection for DriveCollection{
+    type Error = Box<dyn std::error::Error>;
+
+    async fn init() -> Result<Self, Self::Error> where Self: Sized {
+        let client = mongo_client().await?;
+        let db = client.database("cloud_storage");
+        let col: Collection<Drive> = db.collection("drives");
+
+        col.create_index(IndexModel::builder().keys(doc! {"org_id": 1}).build(), None).await?;
+
+        Ok(Self{ drive_collection: col })
+    }
+}
@@ -0,0 +1,189 @@
+use async_trait::async_trait;
+use axum::http::StatusCode;
+use axum::Json;
+use bson::{doc, Document};
+use bson::oid::ObjectId;
+use chrono::Utc;
+use futures::TryStreamExt;
+use mongodb::{Collection, IndexModel};
+use mongodb::error::Error;
+use mongodb::options::{FindOptions, IndexOptions};
+use mongodb::results::UpdateResult;
+use crate::AppState;
+use crate::error::storage_error::StorageError;
+use crate::models::file_model::File;
+use crate::models::file_request_model::FileRequest;
+use crate::models::permission_model::Role;
+use crate::models::share_model::SharedItemType;
+use crate::services::share_service::new_share_token;
+use crate::services::trait_service::{mongo_client, StorageCollection};
+
+
+#[derive(Debug, Clone)]
+pub struct FileRequestCollection{
+    pub file_request_collection: Collection<FileRequest>,
+}
+
+impl FileRequestCollection {
+
+    pub async fn get_file_requests(&self, filter: Document) -> Result<Vec<FileRequest>, Error>{
+        let options = FindOptions::builder().sort(doc! {"createdAt": -1}).build();
+        self.file_request_collection.find(filter, options).await?.try_collect::<Vec<FileRequest>>().await
+    }
+
+    pub async fn update_file_request(&self, filter: Document, update: Document) -> Result<UpdateResult, Error>{
+        self.file_request_collection.update_one(filter, update, None).await
+    }
+}
+
+// Magic numbers of the formats people usually send, enough to tell whether a declared type is true.
+const SIGNATURES: &[(&[u8], &str)] = &[
+    (b"\x89PNG\r\n\x1a\n", "image/png"),
+    (b"\xff\xd8\xff", "image/jpeg"),
+    (b"GIF87a", "image/gif"),
+    (b"GIF89a", "image/gif"),
+    (b"%PDF-", "application/pdf"),
+    (b"PK\x03\x04", "application/zip"),
+    (b"\x1f\x8b", "application/gzip"),
+    (b"OggS", "audio/ogg"),
+    (b"ID3", "audio/mpeg"),
+];
+
+// The type a file really is, judging by its first bytes. Declared types are only kept when the
+// content agrees with them, e.g. a .docx is a zip and a .mov is an ISO media file.
+pub fn sniffed_type(declared: &str, head: &[u8]) -> String {
+    let declared = declared.to_ascii_lowercase();
+
+    let signature = SIGNATURES.iter().find(|(magic, _)| head.starts_with(magic)).map(|(_, mime)| *mime)
+        .or_else(|| (head.len() >= 12 && &head[..4] == b"RIFF" && &head[8..12] == b"WEBP").then_some("image/webp"))
+        .or_else(|| (head.len() >= 8 && &head[4..8] == b"ftyp").then_some("video/mp4"));
+
+    match signature {
+        Some("application/zip") if declared.starts_with("application/vnd.openxmlformats-") || declared.starts_with("application/vnd.oasis.opendocument.") || declared == "application/epub+zip" => declared,
+        Some("video/mp4") if declared.starts_with("video/") || declared.starts_with("audio/") => declared,
+        Some(mime) => mime.to_string(),
+        None => {
+            // text may be cut in the middle of a character at the end of `head`
+            let text = match std::str::from_utf8(head) {
+                Ok(_) => !head.contains(&0),
+                Err(err) => err.error_len().is_none() && !head.contains(&0)
+            };
+
+            if !text {
+                return "application/octet-stream".to_string();
+            }
+
+            let start = String::from_utf8_lossy(head).trim_start().to_ascii_lowercase();
+
+            if start.starts_with("<svg") || (start.starts_with("<?xml") && start.contains("<svg")) {
+                "image/svg+xml".to_string()
+            } else if start.starts_with("<!doctype html") || start.starts_with("<html") || start.contains("<script") {
+                "text/html".to_string()
+            } else if (declared.starts_with("text/") && declared != "text/html") || declared == "application/json" {
+                declared
+            } else {
+                "text/plain".to_string()
+            }
+        }
+    }
+}
+
+impl AppState {
+
+    // Stores `request` with a fresh token. Files can be requested into any folder the user may
+    // upload into, they end up belonging to the folder's owner.
+    pub async fn create_file_request(&self, user_id: &ObjectId, mut request: FileRequest) -> Result<FileRequest, StorageError> {
+        let owner = self.authorize(user_id, SharedItemType::Folder, &request.folder_id, Role::Editor).await?;
+
+        request.id = None;
+        request.token = new_share_token();
+        request.user_id = owner.id;
+        request.owner_kind = owner.kind;
+        request.created_by = *user_id;
+        request.files = 0;
+        request.revoked_at = None;
+        request.created_at = bson::DateTime::now();
+
+        let inserted = self.file_request_collection.file_request_collection.insert_one(&request, None).await?;
+        request.id = inserted.inserted_id.as_object_id();
+
+        Ok(request)
+    }
+
+    // The request behind `token` if files can still be sent with it.
+    pub async fn open_file_request(&self, token: &str) -> Result<FileRequest, StorageError> {
+        let request = match self.file_request_collection.file_request_collection.find_one(doc! {"token": token}, None).await? {
+            Some(request) if request.revoked_at.is_none() => request,
+            _ => return Err(StatusCode::NOT_FOUND.into())
+        };
+
+        if request.expires_at.map(|expires_at| expires_at.to_chrono() < Utc::now()).unwrap_or(false) {
+            return Err(StatusCode::GONE.into());
+        }
+
+        if request.max_files.map(|max_files| request.files >= max_files).unwrap_or(false) {
+            return Err(StatusCode::GONE.into());
+        }
+
+        // the folder may have been deleted, or the user who asked for the files lost access to it
+        let owner = self.authorize(&request.created_by, SharedItemType::Folder, &request.folder_id, Role::Editor).await?;
+
+        if owner != request.owner() {
+            return Err(StatusCode::NOT_FOUND.into());
+        }
+
+        Ok(request)
+    }
+
+    // Reserves room for one more file, failing once the cap is reached.
+    pub async fn take_file_slot(&self, request: &FileRequest) -> Result<(), StorageError> {
+        let filter = doc! {
+            "_id": request.id,
+            "$or": [{"max_files": null}, {"$expr": {"$lt": ["$files", "$max_files"]}}],
+        };
+
+        let updated = self.file_request_collection.update_file_request(filter, doc! {"$inc": {"files": 1}}).await?;
+
+        if updated.modified_count == 0 {
+            return Err(StatusCode::GONE.into());
+        }
+
+        Ok(())
+    }
+
+    // Gives back a slot taken for a file that was not stored after all.
+    pub async fn release_file_slot(&self, request: &FileRequest) -> Result<(), Error> {
+        self.file_request_collection.update_file_request(doc! {"_id": request.id, "files": {"$gt": 0}}, doc! {"$inc": {"files": -1}}).await?;
+        Ok(())
+    }
+
+    // Puts a received file into the requested folder. Files with the same name are numbered rather
+    // than turned into versions, uploaders never replace what is already there.
+    pub async fn store_requested_file(&self, request: &FileRequest, mut file: File) -> Result<ObjectId, Error> {
+        file.folder_id = Some(request.folder_id);
+        file.owner_kind = request.owner_kind;
+
+        let parent_path = self.folder_path(file.folder_id, &request.user_id).await?;
+        let inserted = self.file_collection.create_file(Json(file), request.user_id, parent_path.as_deref()).await?;
+        self.invalidate_stats(&request.user_id);
+
+        Ok(inserted.inserted_id.as_object_id().unwrap())
+    }
+}
+
+
+#[async_trait]
+impl StorageCollection for FileRequestCollection{
+    type Error = Box<dyn std::error::Error>;
+
+    async fn init() -> Result<Self, Self::Error> where Self: Sized {
+        let client = mongo_client().await?;
+        let db = client.database("cloud_storage");
+        let col: Collection<FileRequest> = db.collection("file_requests");
+
+        let unique = IndexOptions::builder().unique(true).build();
+        col.create_index(IndexModel::builder().keys(doc! {"token": 1}).options(unique).build(), None).await?;
+
+        Ok(Self{ file_request_collection: col })
+    }
+}
@@ -1,20 +1,27 @@
-use std::env;
-use mongodb::{Client, Collection, Cursor};
+use mongodb::{ClientSession, Collection, Cursor, IndexModel};
 use crate::models::file_model::File;
 use async_trait::async_trait;
 use axum::extract::Query;
 use axum::Json;
 use bson::oid::ObjectId;
 use chrono::Utc;
-use dotenv::dotenv;
 use futures::TryStreamExt;
 use mongodb::bson::{doc, Document};
-use mongodb::options::{ClientOptions, FindOptions};
+use mongodb::options::{FindOptions};
 use crate::services::auth_services::UserCollection;
-use crate::services::trait_service::StorageCollection;
+use crate::services::trait_service::{mongo_client, StorageCollection};
+use crate::services::tree_service::child_path;
 use mongodb::error::{Error, Result as MongoResult};
 use mongodb::results::{DeleteResult, InsertOneResult, UpdateResult};
 
+// "report.final.pdf" -> "report.final (2).pdf", names without an extension (or dot files) just get the suffix
+pub fn numbered_file_name(file_name: &str, number: usize) -> String {
+    match file_name.rsplit_once('.') {
+        Some((stem, extension)) if !stem.is_empty() => format!("{} ({}).{}", stem, number, extension),
+        _ => format!("{} ({})", file_name, number)
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct FileCollection{
     pub file_collection: Collection<File>
@@ -29,13 +36,18 @@ impl FileCollection {
 
     }
 
+    pub async fn get_file_with_session(&self, filter: Document, session: &mut ClientSession) -> Result<Vec<File>, Error> {
+        self.file_collection.find_with_session(filter, None, session).await?.stream(session).try_collect::<Vec<File>>().await
+    }
+
     pub async fn get_many_files(&self, params: &Query<Vec<(String, ObjectId)>>, user_id: &ObjectId) -> Result<Vec<File>, Error>{
         let ids = params.0.to_vec().iter().filter(|obj| obj.0 == "ids").map(|obj| doc! {"_id": obj.1, "user_id": user_id}).collect::<Vec<_>>();
         self.file_collection.find(doc! {"$or": ids, "user_id": user_id}, None).await?.try_collect::<Vec<File>>().await
 
     }
 
-    pub async fn create_file(&self, mut new_file: Json<File>, user_id: ObjectId) -> Result<InsertOneResult, Error>{
+    // `parent_path` is the path of the folder the file goes into, None at the root
+    pub async fn create_file(&self, mut new_file: Json<File>, user_id: ObjectId, parent_path: Option<&str>) -> Result<InsertOneResult, Error>{
 
         let now = Utc::now();
         new_file.user_id = Some(user_id.clone());
@@ -49,14 +61,11 @@ impl FileCollection {
         let mut count_duplicates = self.get_file(filter).await.unwrap_or(vec![]);
 
         if count_duplicates.len() > 0 {
-            let mut split_file_name = new_file.file_name.split(".").collect::<Vec<_>>();
-            let file_name = split_file_name[0];
-            let file_duplicate = count_duplicates.len();
-            let file_format = split_file_name.pop().unwrap();
-
-            new_file.file_name = format!("{} ({}).{}", file_name, file_duplicate, file_format)
+            new_file.file_name = numbered_file_name(&new_file.file_name, count_duplicates.len());
         }
 
+        new_file.path = Some(child_path(parent_path, &new_file.file_name));
+
 
         return self.file_collection.insert_one(&*new_file, None).await;
     }
@@ -84,16 +93,33 @@ impl StorageCollection for FileCollection{
     type Error = Box<dyn std::error::Error>;
 
     async fn init() -> Result<Self, Self::Error> where Self: Sized {
-        dotenv().ok();
-        let mongo_uri = env::var("MONGODB_URI").expect("MONGODB_URI not found in env");
-
-        let client_options = ClientOptions::parse(mongo_uri).await?;
-
-        let client = Client::with_options(client_options)?;
+        let client = mongo_client().await?;
         let db = client.database("cloud_storage");
         let col: Collection<File> = db.collection("files");
 
+        col.create_index(IndexModel::builder().keys(doc! {"user_id": 1, "folder_id": 1}).build(), None).await?;
+        col.create_index(IndexModel::builder().keys(doc! {"folder_id": 1}).build(), None).await?;
+        col.create_index(IndexModel::builder().keys(doc! {"user_id": 1, "path": 1}).build(), None).await?;
+
         Ok(Self{ file_collection: col })
     }
 
 }
+
+
+#[cfg(test)]
+mod tests {
+    use super::numbered_file_name;
+
+    #[test]
+    fn numbers_before_the_last_extension() {
+        assert_eq!(numbered_file_name("report.pdf", 1), "report (1).pdf");
+        assert_eq!(numbered_file_name("report.final.pdf", 2), "report.final (2).pdf");
+    }
+
+    #[test]
+    fn appends_to_names_without_an_extension() {
+        assert_eq!(numbered_file_name("README", 3), "README (3)");
+        assert_eq!(numbered_file_name(".env", 1), ".env (1)");
+    }
+}
@@ -1,18 +1,14 @@
-use mongodb::Collection;
-use crate::models::folder_model::Folder;
-use crate::services::trait_service::StorageCollection;
+use mongodb::{ClientSession, Collection, IndexModel};
+use crate::models::folder_model::{Breadcrumb, Folder};
+use crate::services::trait_service::{mongo_client, StorageCollection};
 use async_trait::async_trait;
-use std::env;
 use axum::extract::Query;
 use axum::Json;
 use bson::{doc, Document};
 use bson::oid::ObjectId;
 use chrono::Utc;
-use dotenv::dotenv;
 use futures::TryStreamExt;
-use mongodb::options::{ClientOptions};
 
-use mongodb::{Client};
 use mongodb::results::{DeleteResult, InsertOneResult, UpdateResult};
 use crate::services::file_services::FileCollection;
 
@@ -21,10 +17,80 @@ pub struct FolderCollection{
     pub folder_collection: Collection<Folder>,
 }
 
+// The tree is only stored as File.folder_id and Folder.parent_id. A folder's `files` and `folders`
+// are looked up from those when it is read and never written. Recursive `stats` are left to
+// with_stats, only the endpoints that show them pay for them.
+fn with_children(filter: Document) -> Vec<Document> {
+    vec![
+        doc! {"$match": filter},
+        doc! {"$lookup": {"from": "files", "localField": "_id", "foreignField": "folder_id", "as": "files"}},
+        doc! {"$lookup": {"from": "folders", "localField": "_id", "foreignField": "parent_id", "as": "folders"}},
+        doc! {"$set": {"files": "$files._id", "folders": "$folders._id"}},
+    ]
+}
+
 impl FolderCollection {
 
     pub async fn get_folder(&self, filter: Document) -> Result<Vec<Folder>, mongodb::error::Error>{
-        self.folder_collection.find(filter, None).await?.try_collect::<Vec<Folder>>().await
+        self.folder_collection.aggregate(with_children(filter), None).await?
+            .and_then(|folder| async move { Ok(bson::from_document::<Folder>(folder)?) })
+            .try_collect::<Vec<Folder>>().await
+    }
+
+    // Plain read for use inside a transaction, `files` and `folders` are left empty.
+    pub async fn get_folder_with_session(&self, filter: Document, session: &mut ClientSession) -> Result<Vec<Folder>, mongodb::error::Error>{
+        self.folder_collection.find_with_session(filter, None, session).await?.stream(session).try_collect::<Vec<Folder>>().await
+    }
+
+    // The folder and all of its ancestors, root first, looked up in a single aggregation.
+    // Empty when the folder doesn't exist or belongs to someone else.
+    pub async fn get_breadcrumbs(&self, folder_id: &ObjectId, user_id: &ObjectId) -> Result<Vec<Breadcrumb>, mongodb::error::Error>{
+        let pipeline = vec![
+            doc! {"$match": {"_id": folder_id, "user_id": user_id}},
+            doc! {"$graphLookup": {
+                "from": "folders",
+                "startWith": "$parent_id",
+                "connectFromField": "parent_id",
+                "connectToField": "_id",
+                "as": "ancestors",
+                "depthField": "depth",
+                "restrictSearchWithMatch": {"user_id": user_id},
+            }},
+            // the folder itself goes last, below its parent at depth 0
+            doc! {"$project": {
+                "trail": {"$concatArrays": ["$ancestors", [{"_id": "$_id", "folder_name": "$folder_name", "depth": -1}]]},
+            }},
+            doc! {"$unwind": "$trail"},
+            doc! {"$replaceRoot": {"newRoot": "$trail"}},
+            doc! {"$sort": {"depth": -1}},
+            doc! {"$project": {"_id": 1, "folder_name": 1}},
+        ];
+
+        self.folder_collection.aggregate(pipeline, None).await?
+            .and_then(|crumb| async move { Ok(bson::from_document::<Breadcrumb>(crumb)?) })
+            .try_collect::<Vec<Breadcrumb>>().await
+    }
+
+    // Ids of every folder below the folder, at any depth, following the parent pointers down.
+    pub async fn get_descendant_ids(&self, folder_id: &ObjectId, user_id: &ObjectId) -> Result<Vec<ObjectId>, mongodb::error::Error>{
+        let pipeline = vec![
+            doc! {"$match": {"_id": folder_id, "user_id": user_id}},
+            doc! {"$graphLookup": {
+                "from": "folders",
+                "startWith": "$_id",
+                "connectFromField": "_id",
+                "connectToField": "parent_id",
+                "as": "descendants",
+                "restrictSearchWithMatch": {"user_id": user_id},
+            }},
+            doc! {"$unwind": "$descendants"},
+            doc! {"$replaceRoot": {"newRoot": "$descendants"}},
+            doc! {"$project": {"_id": 1, "folder_name": 1}},
+        ];
+
+        self.folder_collection.aggregate(pipeline, None).await?
+            .and_then(|descendant| async move { Ok(bson::from_document::<Breadcrumb>(descendant)?.id) })
+            .try_collect::<Vec<ObjectId>>().await
     }
 
     pub async fn create_folder(&self, mut new_folder: &mut Json<Folder>, user_id: &ObjectId) -> Result<InsertOneResult, mongodb::error::Error>{
@@ -38,7 +104,7 @@ impl FolderCollection {
     pub async fn get_folder_by_id(&self, params: &Query<Vec<(String, ObjectId)>>, user_id: &ObjectId) -> Result<Vec<Folder>, mongodb::error::Error>{
         let ids = params.0.to_vec().iter().map(|obj| doc! {"_id": obj.1, "user_id": user_id}).collect::<Vec<_>>();
 
-        self.folder_collection.find(doc! {"$or": ids, "user_id": user_id}, None).await?.try_collect::<Vec<Folder>>().await
+        self.get_folder(doc! {"$or": ids, "user_id": user_id}).await
     }
 
 
@@ -64,15 +130,15 @@ impl StorageCollection for FolderCollection{
     type Error = Box<dyn std::error::Error>;
 
     async fn init() -> Result<Self, Self::Error> where Self: Sized {
-        dotenv().ok();
-        let mongo_uri = env::var("MONGODB_URI").expect("MONGODB_URI not found in env");
-
-        let client_options = ClientOptions::parse(mongo_uri).await?;
-
-        let client = Client::with_options(client_options)?;
+        let client = mongo_client().await?;
         let db = client.database("cloud_storage");
         let folder_col: Collection<Folder> = db.collection("folders");
 
+        folder_col.create_index(IndexModel::builder().keys(doc! {"user_id": 1, "parent_id": 1}).build(), None).await?;
+        // for looking up the children of folders by id alone
+        folder_col.create_index(IndexModel::builder().keys(doc! {"parent_id": 1}).build(), None).await?;
+        folder_col.create_index(IndexModel::builder().keys(doc! {"user_id": 1, "path": 1}).build(), None).await?;
+
         Ok(Self{ folder_collection: folder_col })
     }
 }
\ No newline at end of file
@@ -0,0 +1,99 @@
+use std::collections::HashSet;
+use std::io;
+use bson::{doc, Document};
+use chrono::{DateTime, Duration, Utc};
+use futures::TryStreamExt;
+use serde::{Deserialize, Serialize};
+use crate::AppState;
+use crate::storage::blob_store::BlobMeta;
+
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GcReport {
+    pub dry_run: bool,
+    pub scanned: usize,
+    pub orphans: Vec<BlobMeta>,
+    pub deleted: usize,
+    pub reclaimed_bytes: u64,
+}
+
+impl AppState {
+
+    // Every key that some document still points at. Objects not in here are orphans. The keys are
+    // streamed, a single `distinct` result would be capped at 16MB on a large deployment.
+    async fn referenced_blob_keys(&self) -> Result<HashSet<String>, mongodb::error::Error> {
+        let mut keys = HashSet::new();
+
+        let files = self.file_collection.file_collection.clone_with_type::<Document>();
+        let versions = self.version_collection.version_collection.clone_with_type::<Document>();
+        let blobs = self.blob_collection.blob_collection.clone_with_type::<Document>();
+        // trashed files keep their content until the trash is purged
+        let trashed_files = self.trash_collection.trashed_file_collection.clone_with_type::<Document>();
+
+        for (collection, field) in [(files, "$aws_file_name"), (versions, "$aws_file_name"), (blobs, "$key"), (trashed_files, "$file.aws_file_name")] {
+            let pipeline = vec![doc! {"$project": {"_id": 0, "key": field}}];
+            let mut cursor = collection.aggregate(pipeline, None).await?;
+
+            while let Some(document) = cursor.try_next().await? {
+                if let Ok(key) = document.get_str("key") {
+                    keys.insert(key.to_string());
+                }
+            }
+        }
+
+        Ok(keys)
+    }
+
+    async fn still_orphaned(&self, key: &str, cutoff: DateTime<Utc>) -> io::Result<bool> {
+        if self.blob_collection.blob_collection.find_one(doc! {"key": key}, None).await.map_err(io::Error::other)?.is_some() {
+            return Ok(false);
+        }
+
+        Ok(match self.blob_store.head(key).await? {
+            Some(blob) => blob.last_modified.map(|modified| modified < cutoff).unwrap_or(false),
+            None => false
+        })
+    }
+
+    // Compares the blob store against the files/blobs documents. Objects younger than the grace
+    // period are skipped, they may belong to an upload that has not been registered yet.
+    pub async fn collect_garbage(&self, dry_run: bool, grace: Duration) -> io::Result<GcReport> {
+        let stored = self.blob_store.list().await?;
+        let referenced = self.referenced_blob_keys().await.map_err(io::Error::other)?;
+        let cutoff = Utc::now() - grace;
+
+        let orphans = stored.iter()
+            .filter(|blob| !referenced.contains(&blob.key))
+            .filter(|blob| blob.last_modified.map(|modified| modified < cutoff).unwrap_or(false))
+            .cloned()
+            .collect::<Vec<_>>();
+
+        let mut deleted = 0;
+        let mut reclaimed_bytes = 0;
+
+        if !dry_run {
+            for orphan in orphans.iter() {
+                // the content may have been uploaded again since the listing, which writes the same key
+                if !self.still_orphaned(&orphan.key, cutoff).await? {
+                    continue;
+                }
+
+                match self.blob_store.delete(&orphan.key).await {
+                    Ok(_) => {
+                        deleted += 1;
+                        reclaimed_bytes += orphan.size;
+                    },
+                    Err(err) => println!("Failed to delete orphaned blob {}: {}", orphan.key, err)
+                }
+            }
+        }
+
+        Ok(GcReport{
+            dry_run,
+            scanned: stored.len(),
+            orphans,
+            deleted,
+            reclaimed_bytes,
+        })
+    }
+}
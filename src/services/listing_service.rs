@@ -0,0 +1,277 @@
+use std::collections::HashMap;
+use axum::http::{HeaderMap, HeaderValue, StatusCode};
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use bson::{doc, Bson, Document};
+use bson::oid::ObjectId;
+use futures::TryStreamExt;
+use serde::{Deserialize, Serialize};
+use crate::{AppState, Item};
+use crate::error::storage_error::StorageError;
+use crate::models::file_model::File;
+use crate::models::folder_model::Folder;
+
+
+const DEFAULT_PAGE_SIZE: u64 = 100;
+const MAX_PAGE_SIZE: u64 = 500;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SortField {
+    Name,
+    Size,
+    Type,
+    #[default]
+    Created,
+    Updated,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SortOrder {
+    #[default]
+    Asc,
+    Desc,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ListParams {
+    pub sort: Option<SortField>,
+    pub order: Option<SortOrder>,
+    pub folders_first: Option<bool>,
+    // next_cursor of the previous page, as handed out
+    pub cursor: Option<String>,
+    pub limit: Option<u64>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Listing {
+    pub items: Vec<Item<File, Folder>>,
+    pub next_cursor: Option<String>,
+}
+
+// Plain array listings hand the cursor for the next page out in this header.
+pub const NEXT_CURSOR_HEADER: &str = "x-next-cursor";
+
+pub fn next_cursor_headers(next_cursor: &Option<String>) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+
+    if let Some(value) = next_cursor.as_deref().and_then(|cursor| HeaderValue::from_str(cursor).ok()) {
+        headers.insert(NEXT_CURSOR_HEADER, value);
+    }
+
+    headers
+}
+
+// Where the previous page stopped: the sort key of its last item and the sort it was taken with.
+#[derive(Debug, Serialize, Deserialize)]
+struct Cursor {
+    sort: SortField,
+    order: SortOrder,
+    folders_first: bool,
+    rank: i32,
+    key: Bson,
+    id: ObjectId,
+}
+
+impl Cursor {
+    fn encode(&self) -> Option<String> {
+        let mut bytes = vec![];
+        bson::to_document(self).ok()?.to_writer(&mut bytes).ok()?;
+        Some(URL_SAFE_NO_PAD.encode(bytes))
+    }
+
+    fn decode(cursor: &str) -> Option<Self> {
+        let bytes = URL_SAFE_NO_PAD.decode(cursor).ok()?;
+        let document = Document::from_reader(bytes.as_slice()).ok()?;
+        bson::from_document(document).ok()
+    }
+}
+
+// Folders have no size or type, they sort as empty for those.
+fn sort_key(sort: SortField, name_field: &str, is_folder: bool) -> Bson {
+    let key = match (sort, is_folder) {
+        (SortField::Name, _) => doc! {"$toLower": format!("${}", name_field)},
+        (SortField::Size, true) => doc! {"$literal": 0_i64},
+        (SortField::Size, false) => doc! {"$toLong": {"$ifNull": ["$size", 0]}},
+        (SortField::Type, true) => doc! {"$literal": ""},
+        (SortField::Type, false) => doc! {"$toLower": {"$ifNull": ["$file_type", ""]}},
+        (SortField::Created, _) => doc! {"$ifNull": ["$createdAt", ""]},
+        (SortField::Updated, _) => doc! {"$ifNull": ["$updatedAt", ""]},
+    };
+
+    Bson::Document(key)
+}
+
+impl ListParams {
+
+    fn sort(&self) -> SortField {
+        self.sort.unwrap_or_default()
+    }
+
+    fn order(&self) -> SortOrder {
+        self.order.unwrap_or_default()
+    }
+
+    fn folders_first(&self) -> bool {
+        self.folders_first.unwrap_or(false)
+    }
+
+    fn limit(&self) -> u64 {
+        self.limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE)
+    }
+
+    fn tagged(&self, filter: Document, kind: &str, name_field: &str) -> Vec<Document> {
+        let is_folder = kind == "folder";
+        let rank = if is_folder || !self.folders_first() { 0 } else { 1 };
+
+        vec![
+            doc! {"$match": filter},
+            doc! {"$set": {"kind": kind, "rank": rank, "sort_key": sort_key(self.sort(), name_field, is_folder)}},
+        ]
+    }
+
+    // Everything strictly after the cursor in the listing order.
+    fn after(&self, cursor: &Cursor) -> Document {
+        let op = match self.order() {
+            SortOrder::Asc => "$gt",
+            SortOrder::Desc => "$lt",
+        };
+
+        doc! {"$or": [
+            {"rank": {"$gt": cursor.rank}},
+            {"rank": cursor.rank, "sort_key": {op: &cursor.key}},
+            {"rank": cursor.rank, "sort_key": &cursor.key, "_id": {op: cursor.id}},
+        ]}
+    }
+
+    fn paging(&self) -> Result<Vec<Document>, StorageError> {
+        let direction = match self.order() {
+            SortOrder::Asc => 1,
+            SortOrder::Desc => -1,
+        };
+
+        let mut stages = vec![];
+
+        if let Some(cursor) = &self.cursor {
+            let cursor = Cursor::decode(cursor).ok_or(StorageError::Status(StatusCode::BAD_REQUEST))?;
+
+            // a cursor only makes sense with the sort it was taken with
+            if cursor.sort != self.sort() || cursor.order != self.order() || cursor.folders_first != self.folders_first() {
+                return Err(StatusCode::BAD_REQUEST.into());
+            }
+
+            stages.push(doc! {"$match": self.after(&cursor)});
+        }
+
+        stages.push(doc! {"$sort": {"rank": 1, "sort_key": direction, "_id": direction}});
+        // one extra to know whether there is another page
+        stages.push(doc! {"$limit": (self.limit() + 1) as i64});
+
+        Ok(stages)
+    }
+
+    fn cursor_after(&self, last: &Document) -> Option<String> {
+        Cursor{
+            sort: self.sort(),
+            order: self.order(),
+            folders_first: self.folders_first(),
+            rank: last.get_i32("rank").ok()?,
+            key: last.get("sort_key")?.clone(),
+            id: last.get_object_id("_id").ok()?,
+        }.encode()
+    }
+}
+
+impl AppState {
+
+    // One page of the files matching `file_filter` and the folders matching `folder_filter`, sorted
+    // and cut in MongoDB. A None filter leaves that collection out.
+    pub async fn list_items(&self, file_filter: Option<Document>, folder_filter: Option<Document>, params: &ListParams) -> Result<Listing, StorageError> {
+        let paging = params.paging()?;
+
+        let cursor = match (file_filter, folder_filter) {
+            (Some(file_filter), Some(folder_filter)) => {
+                let mut pipeline = params.tagged(file_filter, "file", "file_name");
+                pipeline.push(doc! {"$unionWith": {"coll": "folders", "pipeline": params.tagged(folder_filter, "folder", "folder_name")}});
+                pipeline.extend(paging);
+                self.file_collection.file_collection.aggregate(pipeline, None).await?
+            },
+            (Some(file_filter), None) => {
+                let pipeline = [params.tagged(file_filter, "file", "file_name"), paging].concat();
+                self.file_collection.file_collection.aggregate(pipeline, None).await?
+            },
+            (None, Some(folder_filter)) => {
+                let pipeline = [params.tagged(folder_filter, "folder", "folder_name"), paging].concat();
+                self.folder_collection.folder_collection.aggregate(pipeline, None).await?
+            },
+            (None, None) => return Ok(Listing{ items: vec![], next_cursor: None })
+        };
+
+        let mut found = cursor.try_collect::<Vec<Document>>().await?;
+
+        let next_cursor = if found.len() as u64 > params.limit() {
+            found.truncate(params.limit() as usize);
+            found.last().and_then(|last| params.cursor_after(last))
+        } else {
+            None
+        };
+
+        // folders are read again so they come with their children and totals
+        let folder_ids = found.iter()
+            .filter(|item| item.get_str("kind") == Ok("folder"))
+            .filter_map(|item| item.get_object_id("_id").ok())
+            .collect::<Vec<_>>();
+
+        let mut folders = HashMap::new();
+        if !folder_ids.is_empty() {
+            for folder in self.folder_collection.get_folder(doc! {"_id": {"$in": folder_ids}}).await? {
+                folders.insert(folder.id.unwrap(), folder);
+            }
+        }
+
+        let items = found.into_iter().filter_map(|item| {
+            if item.get_str("kind") == Ok("folder") {
+                item.get_object_id("_id").ok().and_then(|folder_id| folders.remove(&folder_id)).map(Item::Folder)
+            } else {
+                bson::from_document::<File>(item).ok().map(Item::File)
+            }
+        }).collect::<Vec<_>>();
+
+        Ok(Listing{ items, next_cursor })
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use bson::Bson;
+    use bson::oid::ObjectId;
+    use super::{Cursor, SortField, SortOrder};
+
+    #[test]
+    fn cursors_round_trip() {
+        let id = ObjectId::new();
+        let cursor = Cursor{ sort: SortField::Name, order: SortOrder::Desc, folders_first: true, rank: 1, key: Bson::String("report.pdf".to_string()), id };
+
+        let encoded = cursor.encode().unwrap();
+        // it goes into query strings and headers as it is
+        assert!(encoded.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_'));
+
+        let decoded = Cursor::decode(&encoded).unwrap();
+
+        assert_eq!(decoded.sort, SortField::Name);
+        assert_eq!(decoded.order, SortOrder::Desc);
+        assert!(decoded.folders_first);
+        assert_eq!(decoded.rank, 1);
+        assert_eq!(decoded.key, Bson::String("report.pdf".to_string()));
+        assert_eq!(decoded.id, id);
+    }
+
+    #[test]
+    fn rejects_malformed_cursors() {
+        assert!(Cursor::decode("not a cursor").is_none());
+        assert!(Cursor::decode("").is_none());
+        assert!(Cursor::decode("AAAA").is_none());
+    }
+}
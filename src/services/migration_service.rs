@@ -0,0 +1,50 @@
+use bson::{doc, Document};
+use mongodb::Collection;
+use mongodb::error::Error;
+use mongodb::options::UpdateOptions;
+use crate::AppState;
+use crate::services::trait_service::mongo_client;
+
+
+// One-off data migrations. Each one is recorded in `migrations` once it went through, later boots
+// skip it instead of scanning the collections again. Both are safe to run twice, so two instances
+// booting at the same time just do the work twice.
+const FOLDER_ARRAYS: &str = "folder_arrays";
+const PATHS: &str = "paths";
+const OWNER_KINDS: &str = "owner_kinds";
+
+async fn migration_applied(migrations: &Collection<Document>, name: &str) -> Result<bool, Error> {
+    Ok(migrations.find_one(doc! {"_id": name}, None).await?.is_some())
+}
+
+async fn record_migration(migrations: &Collection<Document>, name: &str) -> Result<(), Error> {
+    let options = UpdateOptions::builder().upsert(true).build();
+    migrations.update_one(doc! {"_id": name}, doc! {"$set": {"applied_at": bson::DateTime::now()}}, options).await?;
+    println!("Applied migration {}", name);
+    Ok(())
+}
+
+impl AppState {
+
+    pub async fn run_migrations(&self) -> Result<(), Error> {
+        let migrations = mongo_client().await?.database("cloud_storage").collection::<Document>("migrations");
+
+        if !migration_applied(&migrations, FOLDER_ARRAYS).await? {
+            self.migrate_folder_arrays().await?;
+            record_migration(&migrations, FOLDER_ARRAYS).await?;
+        }
+
+        // paths are built from the parent pointers the first migration fills in
+        if !migration_applied(&migrations, PATHS).await? {
+            self.migrate_paths().await?;
+            record_migration(&migrations, PATHS).await?;
+        }
+
+        if !migration_applied(&migrations, OWNER_KINDS).await? {
+            self.migrate_owner_kinds().await?;
+            record_migration(&migrations, OWNER_KINDS).await?;
+        }
+
+        Ok(())
+    }
+}
@@ -2,4 +2,22 @@ pub mod auth_services;
 pub mod file_services;
 pub mod trait_service;
 pub mod folder_service;
-pub mod dashboard_services;
\ No newline at end of file
+pub mod dashboard_services;
+pub mod blob_services;
+pub mod upload_service;
+pub mod gc_service;
+pub mod quota_service;
+pub mod version_service;
+pub mod trash_service;
+pub mod naming_service;
+pub mod tree_service;
+pub mod search_service;
+pub mod listing_service;
+pub mod share_service;
+pub mod permission_service;
+pub mod organization_service;
+pub mod drive_service;
+pub mod file_request_service;
+pub mod comment_service;
+pub mod stats_service;
+pub mod migration_service;
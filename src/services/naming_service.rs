@@ -0,0 +1,211 @@
+use axum::http::StatusCode;
+use bson::{doc, Document};
+use bson::oid::ObjectId;
+use chrono::Utc;
+use mongodb::ClientSession;
+use serde::Deserialize;
+use crate::AppState;
+use crate::error::storage_error::StorageError;
+use crate::models::file_model::File;
+use crate::models::folder_model::Folder;
+use crate::services::file_services::numbered_file_name;
+use crate::services::trait_service::{finish_transaction, start_transaction};
+use crate::services::tree_service::child_path;
+
+
+// What to do when the target name is already taken by a sibling of the same kind
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ConflictPolicy {
+    #[default]
+    Reject,
+    AutoSuffix,
+    Overwrite,
+}
+
+pub fn numbered_folder_name(folder_name: &str, number: usize) -> String {
+    format!("{} ({})", folder_name, number)
+}
+
+// Rewrites the `old_path` prefix of `path` to `new_path`, leaving other paths untouched.
+fn path_prefix_update(old_path: &str, new_path: &str) -> Vec<Document> {
+    let prefix = format!("{}/", old_path);
+    let prefix_len = old_path.chars().count() as i64;
+
+    vec![doc! {
+        "$set": {
+            "path": {
+                "$cond": [
+                    {"$eq": [{"$substrCP": ["$path", 0, prefix_len + 1]}, prefix]},
+                    {"$concat": [new_path, {"$substrCP": ["$path", prefix_len, {"$strLenCP": "$path"}]}]},
+                    "$path"
+                ]
+            }
+        }
+    }]
+}
+
+// Longest name a file or folder can have, in characters
+const MAX_NAME_LENGTH: usize = 255;
+
+pub fn valid_name(name: &str) -> bool {
+    let name = name.trim();
+    !name.is_empty() && name != "." && name != ".." && !name.contains('/') && !name.contains(char::is_control) && name.chars().count() <= MAX_NAME_LENGTH
+}
+
+impl AppState {
+
+    async fn sibling_file(&self, user_id: &ObjectId, folder_id: Option<ObjectId>, name: &str, except: Option<ObjectId>, session: &mut ClientSession) -> Result<Option<File>, mongodb::error::Error> {
+        let filter = doc! {"user_id": user_id, "folder_id": folder_id, "file_name": name, "_id": {"$ne": except}};
+        Ok(self.file_collection.get_file_with_session(filter, session).await?.into_iter().next())
+    }
+
+    async fn sibling_folder(&self, user_id: &ObjectId, parent_id: Option<ObjectId>, name: &str, except: Option<ObjectId>, session: &mut ClientSession) -> Result<Option<Folder>, mongodb::error::Error> {
+        let filter = doc! {"user_id": user_id, "parent_id": parent_id, "folder_name": name, "_id": {"$ne": except}};
+        Ok(self.folder_collection.get_folder_with_session(filter, session).await?.into_iter().next())
+    }
+
+    // Picks the name a file ends up with inside `folder_id`. `except` is the file being renamed or
+    // moved, so it never conflicts with itself. Overwritten files go to the trash.
+    pub async fn resolve_file_name(&self, user_id: &ObjectId, folder_id: Option<ObjectId>, name: &str, except: Option<ObjectId>, policy: ConflictPolicy, session: &mut ClientSession) -> Result<String, StorageError> {
+        let existing = match self.sibling_file(user_id, folder_id, name, except, session).await? {
+            Some(existing) => existing,
+            None => return Ok(name.to_string())
+        };
+
+        match policy {
+            ConflictPolicy::Reject => Err(StorageError::NameConflict{ name: name.to_string() }),
+            ConflictPolicy::AutoSuffix => {
+                let mut number = 1;
+                while self.sibling_file(user_id, folder_id, &numbered_file_name(name, number), except, session).await?.is_some() {
+                    number += 1;
+                }
+                Ok(numbered_file_name(name, number))
+            },
+            ConflictPolicy::Overwrite => {
+                self.trash_file_in(existing, user_id, session).await?;
+                Ok(name.to_string())
+            }
+        }
+    }
+
+    pub async fn resolve_folder_name(&self, user_id: &ObjectId, parent_id: Option<ObjectId>, name: &str, except: Option<ObjectId>, policy: ConflictPolicy, session: &mut ClientSession) -> Result<String, StorageError> {
+        let existing = match self.sibling_folder(user_id, parent_id, name, except, session).await? {
+            Some(existing) => existing,
+            None => return Ok(name.to_string())
+        };
+
+        match policy {
+            ConflictPolicy::Reject => Err(StorageError::NameConflict{ name: name.to_string() }),
+            ConflictPolicy::AutoSuffix => {
+                let mut number = 1;
+                while self.sibling_folder(user_id, parent_id, &numbered_folder_name(name, number), except, session).await?.is_some() {
+                    number += 1;
+                }
+                Ok(numbered_folder_name(name, number))
+            },
+            ConflictPolicy::Overwrite => {
+                self.trash_folder_in(existing, user_id, session).await?;
+                Ok(name.to_string())
+            }
+        }
+    }
+
+    async fn rename_file_in(&self, user_id: &ObjectId, filter: Document, name: &str, policy: ConflictPolicy, session: &mut ClientSession) -> Result<(), StorageError> {
+        let file = match self.file_collection.get_file_with_session(filter.clone(), session).await?.into_iter().next() {
+            Some(file) => file,
+            None => return Err(StorageError::Status(StatusCode::NOT_FOUND))
+        };
+
+        let name = self.resolve_file_name(user_id, file.folder_id, name.trim(), file.id, policy, session).await?;
+        let path = child_path(self.folder_path_in(file.folder_id, user_id, session).await?.as_deref(), &name);
+
+        // uploads under the new name should land on this file when versioning is on
+        let update = doc! {
+            "$set": {
+                "file_name": &name,
+                "original_file_name": &name,
+                "path": path,
+                "updatedAt": bson::to_bson(&Utc::now()).unwrap_or_default(),
+            }
+        };
+
+        self.file_collection.file_collection.update_one_with_session(filter, update, None, session).await?;
+
+        Ok(())
+    }
+
+    pub async fn rename_file(&self, user_id: &ObjectId, file_id: &ObjectId, name: &str, policy: ConflictPolicy) -> Result<File, StorageError> {
+        let filter = doc! {"_id": file_id, "user_id": user_id};
+
+        let mut session = start_transaction().await?;
+        let result = self.rename_file_in(user_id, filter.clone(), name, policy, &mut session).await;
+        finish_transaction(session, result).await?;
+        // overwriting sends the other item to the trash
+        self.invalidate_stats(user_id);
+
+        match self.file_collection.get_file(filter).await?.into_iter().next() {
+            Some(file) => Ok(file),
+            None => Err(StorageError::Status(StatusCode::NOT_FOUND))
+        }
+    }
+
+    async fn rename_folder_in(&self, user_id: &ObjectId, filter: Document, name: &str, policy: ConflictPolicy, session: &mut ClientSession) -> Result<(), StorageError> {
+        let folder = match self.folder_collection.get_folder_with_session(filter.clone(), session).await?.into_iter().next() {
+            Some(folder) => folder,
+            None => return Err(StorageError::Status(StatusCode::NOT_FOUND))
+        };
+
+        let name = self.resolve_folder_name(user_id, folder.parent_id, name.trim(), folder.id, policy, session).await?;
+        let old_path = folder.path.clone();
+        let new_path = Some(child_path(self.folder_path_in(folder.parent_id, user_id, session).await?.as_deref(), &name));
+
+        let update = doc! {
+            "$set": {
+                "folder_name": &name,
+                "path": &new_path,
+                "updatedAt": bson::to_bson(&Utc::now()).unwrap_or_default(),
+            }
+        };
+
+        self.folder_collection.folder_collection.update_one_with_session(filter, update, None, session).await?;
+
+        if let (Some(old_path), Some(new_path)) = (old_path, new_path) {
+            self.rewrite_descendant_paths(folder, &old_path, &new_path, user_id, session).await?;
+        }
+
+        Ok(())
+    }
+
+    pub async fn rename_folder(&self, user_id: &ObjectId, folder_id: &ObjectId, name: &str, policy: ConflictPolicy) -> Result<Folder, StorageError> {
+        let filter = doc! {"_id": folder_id, "user_id": user_id};
+
+        let mut session = start_transaction().await?;
+        let result = self.rename_folder_in(user_id, filter.clone(), name, policy, &mut session).await;
+        finish_transaction(session, result).await?;
+        // overwriting sends the other item to the trash
+        self.invalidate_stats(user_id);
+
+        match self.folder_collection.get_folder(filter).await?.into_iter().next() {
+            Some(folder) => Ok(folder),
+            None => Err(StorageError::Status(StatusCode::NOT_FOUND))
+        }
+    }
+
+    pub async fn rewrite_descendant_paths(&self, folder: Folder, old_path: &str, new_path: &str, user_id: &ObjectId, session: &mut ClientSession) -> Result<(), mongodb::error::Error> {
+        if old_path == new_path {
+            return Ok(());
+        }
+
+        let root_id = folder.id;
+        let (folders, files) = self.folder_subtree(folder, user_id, session).await?;
+
+        let folder_ids = folders.iter().filter_map(|folder| folder.id).filter(|id| Some(*id) != root_id).collect::<Vec<_>>();
+        let file_ids = files.iter().filter_map(|file| file.id).collect::<Vec<_>>();
+
+        self.folder_collection.folder_collection.update_many_with_session(doc! {"_id": {"$in": folder_ids}, "user_id": user_id}, path_prefix_update(old_path, new_path), None, session).await?;
+        self.file_collection.file_collection.update_many_with_session(doc! {"_id": {"$in": file_ids}, "user_id": user_id}, path_prefix_update(old_path, new_path), None, session).await?;
+
+        Ok(())
+    }
+}
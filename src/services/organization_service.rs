@@ -0,0 +1,119 @@
+use async_trait::async_trait;
+use axum::http::StatusCode;
+use bson::{doc, Document};
+use bson::oid::ObjectId;
+use futures::TryStreamExt;
+use mongodb::{Collection, IndexModel};
+use mongodb::error::Error;
+use mongodb::results::UpdateResult;
+use crate::AppState;
+use crate::error::storage_error::StorageError;
+use crate::models::organization_model::{OrgMember, OrgRole, Organization};
+use crate::services::trait_service::{mongo_client, StorageCollection};
+
+
+#[derive(Debug, Clone)]
+pub struct OrganizationCollection{
+    pub organization_collection: Collection<Organization>,
+}
+
+impl OrganizationCollection {
+
+    pub async fn get_organizations(&self, filter: Document) -> Result<Vec<Organization>, Error>{
+        self.organization_collection.find(filter, None).await?.try_collect::<Vec<Organization>>().await
+    }
+
+    pub async fn update_organization(&self, filter: Document, update: Document) -> Result<UpdateResult, Error>{
+        self.organization_collection.update_one(filter, update, None).await
+    }
+}
+
+fn admin_count(organization: &Organization) -> usize {
+    organization.members.iter().filter(|member| member.role == OrgRole::Admin).count()
+}
+
+impl AppState {
+
+    pub async fn create_organization(&self, user_id: &ObjectId, email: &str, name: &str) -> Result<Organization, Error> {
+        let mut organization = Organization{
+            id: None,
+            name: name.to_string(),
+            members: vec![OrgMember{ user_id: *user_id, email: email.to_string(), role: OrgRole::Admin }],
+            created_at: bson::DateTime::now(),
+        };
+
+        let inserted = self.organization_collection.organization_collection.insert_one(&organization, None).await?;
+        organization.id = inserted.inserted_id.as_object_id();
+
+        Ok(organization)
+    }
+
+    // The organization if `user_id` is a member with at least `required`. Non-members get a 404.
+    pub async fn authorize_org(&self, user_id: &ObjectId, org_id: &ObjectId, required: OrgRole) -> Result<Organization, StorageError> {
+        let organization = self.organization_collection.get_organizations(doc! {"_id": org_id, "members.user_id": user_id}).await?
+            .into_iter().next()
+            .ok_or(StorageError::Status(StatusCode::NOT_FOUND))?;
+
+        match organization.member_role(user_id) {
+            Some(role) if role >= required => Ok(organization),
+            _ => Err(StatusCode::FORBIDDEN.into())
+        }
+    }
+
+    // Adds the user with `email` to the organization, or changes their role if they already are a member.
+    pub async fn set_org_member(&self, admin_id: &ObjectId, org_id: &ObjectId, email: &str, role: OrgRole) -> Result<(), StorageError> {
+        let organization = self.authorize_org(admin_id, org_id, OrgRole::Admin).await?;
+
+        let user_id = self.user_collection.find_by_email(email).await?
+            .and_then(|user| user.id)
+            .ok_or(StorageError::Status(StatusCode::NOT_FOUND))?;
+
+        match organization.member_role(&user_id) {
+            // an organization is never left without an admin
+            Some(OrgRole::Admin) if role != OrgRole::Admin && admin_count(&organization) == 1 => {
+                return Err(StatusCode::CONFLICT.into());
+            },
+            Some(_) => {
+                let update = doc! {"$set": {"members.$.role": bson::to_bson(&role).unwrap_or_default()}};
+                self.organization_collection.update_organization(doc! {"_id": org_id, "members.user_id": user_id}, update).await?;
+            },
+            None => {
+                let member = bson::to_bson(&OrgMember{ user_id, email: email.to_string(), role }).unwrap_or_default();
+                self.organization_collection.update_organization(doc! {"_id": org_id}, doc! {"$push": {"members": member}}).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    // Admins can remove anyone, members can only leave.
+    pub async fn remove_org_member(&self, user_id: &ObjectId, org_id: &ObjectId, member_id: &ObjectId) -> Result<(), StorageError> {
+        let required = if user_id == member_id { OrgRole::Member } else { OrgRole::Admin };
+        let organization = self.authorize_org(user_id, org_id, required).await?;
+
+        match organization.member_role(member_id) {
+            Some(OrgRole::Admin) if admin_count(&organization) == 1 => Err(StatusCode::CONFLICT.into()),
+            Some(_) => {
+                self.organization_collection.update_organization(doc! {"_id": org_id}, doc! {"$pull": {"members": {"user_id": member_id}}}).await?;
+                Ok(())
+            },
+            None => Err(StatusCode::NOT_FOUND.into())
+        }
+    }
+}
+
+
+#[async_trait]
+impl StorageCollection for OrganizationCollection{
+    type Error = Box<dyn std::error::Error>;
+
+    async fn init() -> Result<Self, Self::Error> where Self: Sized {
+        let client = mongo_client().await?;
+        let db = client.database("cloud_storage");
+        let col: Collection<Organization> = db.collection("organizations");
+
+        col.create_index(IndexModel::builder().keys(doc! {"members.user_id": 1}).build(), None).await?;
+
+        Ok(Self{ organization_collection: col })
+    }
+}
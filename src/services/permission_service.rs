@@ -0,0 +1,211 @@
+use async_trait::async_trait;
+use axum::http::StatusCode;
+use bson::{doc, Document};
+use bson::oid::ObjectId;
+use futures::TryStreamExt;
+use mongodb::{Collection, IndexModel};
+use mongodb::error::Error;
+use mongodb::options::{FindOneAndUpdateOptions, FindOptions, IndexOptions, ReturnDocument};
+use mongodb::results::DeleteResult;
+use serde::Serialize;
+use crate::{AppState, Item};
+use crate::error::storage_error::StorageError;
+use crate::models::file_model::File;
+use crate::models::folder_model::Folder;
+use crate::models::owner_model::{Owner, OwnerKind};
+use crate::models::permission_model::{Permission, Role};
+use crate::models::share_model::SharedItemType;
+use crate::services::trait_service::{mongo_client, StorageCollection};
+
+
+#[derive(Debug, Clone)]
+pub struct PermissionCollection{
+    pub permission_collection: Collection<Permission>,
+}
+
+impl PermissionCollection {
+
+    pub async fn get_permissions(&self, filter: Document) -> Result<Vec<Permission>, Error>{
+        let options = FindOptions::builder().sort(doc! {"createdAt": -1}).build();
+        self.permission_collection.find(filter, options).await?.try_collect::<Vec<Permission>>().await
+    }
+
+    pub async fn delete_permissions(&self, filter: Document) -> Result<DeleteResult, Error>{
+        self.permission_collection.delete_many(filter, None).await
+    }
+}
+
+// An entry of "Shared with me"
+#[derive(Debug, Clone, Serialize)]
+pub struct SharedItem {
+    pub permission_id: Option<ObjectId>,
+    pub owner_id: ObjectId,
+    pub role: Role,
+    pub item: Item<File, Folder>,
+}
+
+impl AppState {
+
+    // Owner of the item, as stored on it, and the ids a grant for it can be on: the item and every
+    // folder above it.
+    async fn item_lineage(&self, item_type: SharedItemType, item_id: &ObjectId) -> Result<Option<(Owner, Vec<ObjectId>)>, Error> {
+        let (owner, folder_id) = match item_type {
+            SharedItemType::File => match self.file_collection.file_collection.find_one(doc! {"_id": item_id}, None).await? {
+                Some(file) => match file.owner() {
+                    Some(owner) => (owner, file.folder_id),
+                    None => return Ok(None)
+                },
+                None => return Ok(None)
+            },
+            SharedItemType::Folder => match self.folder_collection.folder_collection.find_one(doc! {"_id": item_id}, None).await?.and_then(|folder| folder.owner()) {
+                Some(owner) => (owner, Some(*item_id)),
+                None => return Ok(None)
+            }
+        };
+
+        let mut lineage = vec![*item_id];
+
+        if let Some(folder_id) = folder_id {
+            let crumbs = self.folder_collection.get_breadcrumbs(&folder_id, &owner.id).await?;
+            lineage.extend(crumbs.into_iter().map(|crumb| crumb.id));
+        }
+
+        Ok(Some((owner, lineage)))
+    }
+
+    // The owner of the item and what `user_id` may do with it. A user owning the item counts as
+    // co-owner, members of the organization of a drive owning it get their drive role, anyone
+    // else only has what was granted on the item or a folder above it.
+    // None when the item doesn't exist or the user has no access at all.
+    pub async fn role_on(&self, user_id: &ObjectId, item_type: SharedItemType, item_id: &ObjectId) -> Result<Option<(Owner, Role)>, Error> {
+        let (owner, lineage) = match self.item_lineage(item_type, item_id).await? {
+            Some(lineage) => lineage,
+            None => return Ok(None)
+        };
+
+        if owner.is_user(user_id) {
+            return Ok(Some((owner, Role::CoOwner)));
+        }
+
+        let drive_role = match owner.kind {
+            OwnerKind::Drive => self.drive_role(user_id, &owner.id).await?,
+            OwnerKind::User => None
+        };
+
+        let grants = self.permission_collection.get_permissions(doc! {"grantee_id": user_id, "item_id": {"$in": lineage}}).await?;
+
+        Ok(grants.into_iter().map(|grant| grant.role).chain(drive_role).max().map(|role| (owner, role)))
+    }
+
+    // Checks that `user_id` has at least `required` on the item and returns its owner, whose
+    // user_id the rest of the request runs with. Items without any access look like they don't exist.
+    pub async fn authorize(&self, user_id: &ObjectId, item_type: SharedItemType, item_id: &ObjectId, required: Role) -> Result<Owner, StorageError> {
+        match self.role_on(user_id, item_type, item_id).await? {
+            Some((owner, role)) if role >= required => Ok(owner),
+            Some(_) => Err(StatusCode::FORBIDDEN.into()),
+            None => Err(StatusCode::NOT_FOUND.into())
+        }
+    }
+
+    // Same for a folder something goes into, None being the user's own root.
+    pub async fn authorize_folder(&self, user_id: &ObjectId, folder_id: Option<ObjectId>, required: Role) -> Result<Owner, StorageError> {
+        match folder_id {
+            Some(folder_id) => self.authorize(user_id, SharedItemType::Folder, &folder_id, required).await,
+            None => Ok(Owner::user(*user_id))
+        }
+    }
+
+    // Moves and copies stay within one drive: the items and the destination must have the same
+    // owner, which is returned.
+    pub async fn authorize_transfer(&self, user_id: &ObjectId, file_ids: &[ObjectId], folder_ids: &[ObjectId], destination: Option<ObjectId>, drive_id: Option<ObjectId>, item_role: Role) -> Result<Owner, StorageError> {
+        let owner = self.authorize_location(user_id, destination, drive_id, Role::Editor).await?;
+
+        let items = file_ids.iter().map(|id| (SharedItemType::File, id)).chain(folder_ids.iter().map(|id| (SharedItemType::Folder, id)));
+
+        for (item_type, item_id) in items {
+            if self.authorize(user_id, item_type, item_id, item_role).await? != owner {
+                return Err(StatusCode::BAD_REQUEST.into());
+            }
+        }
+
+        Ok(owner)
+    }
+
+    // Gives the user with `email` `role` on the item, replacing what they had on it before.
+    pub async fn grant_access(&self, granted_by: &ObjectId, item_type: SharedItemType, item_id: &ObjectId, email: &str, role: Role) -> Result<Permission, StorageError> {
+        let owner = self.authorize(granted_by, item_type, item_id, Role::CoOwner).await?;
+
+        let grantee = self.user_collection.find_by_email(email).await?
+            .and_then(|user| user.id)
+            .ok_or(StorageError::Status(StatusCode::NOT_FOUND))?;
+
+        if owner.is_user(&grantee) {
+            return Err(StatusCode::BAD_REQUEST.into());
+        }
+
+        let update = doc! {
+            "$set": {
+                "owner_id": owner.id,
+                "grantee_email": email,
+                "item_type": bson::to_bson(&item_type).unwrap_or_default(),
+                "role": bson::to_bson(&role).unwrap_or_default(),
+                "granted_by": granted_by,
+            },
+            "$setOnInsert": {"createdAt": bson::DateTime::now()},
+        };
+
+        let options = FindOneAndUpdateOptions::builder().upsert(true).return_document(ReturnDocument::After).build();
+
+        self.permission_collection.permission_collection.find_one_and_update(doc! {"item_id": item_id, "grantee_id": grantee}, update, options).await?
+            .ok_or(StorageError::Status(StatusCode::INTERNAL_SERVER_ERROR))
+    }
+
+    // Everything other users granted `user_id` access to, the items themselves and not what's inside.
+    pub async fn shared_with_me(&self, user_id: &ObjectId) -> Result<Vec<SharedItem>, Error> {
+        let grants = self.permission_collection.get_permissions(doc! {"grantee_id": user_id}).await?;
+
+        let file_ids = grants.iter().filter(|grant| grant.item_type == SharedItemType::File).map(|grant| grant.item_id).collect::<Vec<_>>();
+        let folder_ids = grants.iter().filter(|grant| grant.item_type == SharedItemType::Folder).map(|grant| grant.item_id).collect::<Vec<_>>();
+
+        let mut files = self.file_collection.get_file(doc! {"_id": {"$in": file_ids}}).await?;
+        let mut folders = self.folder_collection.get_folder(doc! {"_id": {"$in": folder_ids}}).await?;
+
+        let shared = grants.into_iter().filter_map(|grant| {
+            let item = match grant.item_type {
+                SharedItemType::File => files.iter().position(|file| file.id == Some(grant.item_id)).map(|index| Item::File(files.swap_remove(index))),
+                SharedItemType::Folder => folders.iter().position(|folder| folder.id == Some(grant.item_id)).map(|index| Item::Folder(folders.swap_remove(index))),
+            }?;
+
+            Some(SharedItem{ permission_id: grant.id, owner_id: grant.owner_id, role: grant.role, item })
+        }).collect();
+
+        Ok(shared)
+    }
+
+    // Grants, share links, file requests and comments die with the items they are for.
+    pub async fn forget_items(&self, item_ids: &[ObjectId]) -> Result<(), Error> {
+        self.permission_collection.delete_permissions(doc! {"item_id": {"$in": item_ids}}).await?;
+        self.share_collection.share_collection.delete_many(doc! {"item_id": {"$in": item_ids}}, None).await?;
+        self.file_request_collection.file_request_collection.delete_many(doc! {"folder_id": {"$in": item_ids}}, None).await?;
+        self.comment_collection.delete_comments(doc! {"file_id": {"$in": item_ids}}).await?;
+        Ok(())
+    }
+}
+
+
+#[async_trait]
+impl StorageCollection for PermissionCollection{
+    type Error = Box<dyn std::error::Error>;
+
+    async fn init() -> Result<Self, Self::Error> where Self: Sized {
+        let client = mongo_client().await?;
+        let db = client.database("cloud_storage");
+        let col: Collection<Permission> = db.collection("permissions");
+
+        let unique = IndexOptions::builder().unique(true).build();
+        col.create_index(IndexModel::builder().keys(doc! {"item_id": 1, "grantee_id": 1}).options(unique).build(), None).await?;
+        col.create_index(IndexModel::builder().keys(doc! {"grantee_id": 1}).build(), None).await?;
+
+        Ok(Self{ permission_collection: col })
+    }
+}
@@ -0,0 +1,70 @@
+use std::env;
+use bson::doc;
+use dotenv::dotenv;
+use serde::{Deserialize, Serialize};
+use crate::AppState;
+use crate::error::storage_error::StorageError;
+use crate::models::owner_model::{Owner, OwnerKind};
+use axum::http::{HeaderMap, HeaderValue, StatusCode};
+
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QuotaUsage {
+    pub used_bytes: u64,
+    pub quota_bytes: u64,
+    pub available_bytes: u64,
+}
+
+// The dashboard reports the usage of the drive it shows in these headers, next to its items.
+pub const STORAGE_USED_HEADER: &str = "x-storage-used";
+pub const STORAGE_QUOTA_HEADER: &str = "x-storage-quota";
+pub const STORAGE_AVAILABLE_HEADER: &str = "x-storage-available";
+
+impl QuotaUsage {
+    pub fn headers(&self) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(STORAGE_USED_HEADER, HeaderValue::from(self.used_bytes));
+        headers.insert(STORAGE_QUOTA_HEADER, HeaderValue::from(self.quota_bytes));
+        headers.insert(STORAGE_AVAILABLE_HEADER, HeaderValue::from(self.available_bytes));
+        headers
+    }
+}
+
+pub fn default_quota() -> u64 {
+    dotenv().ok();
+    env::var("DEFAULT_QUOTA_BYTES").ok().and_then(|quota| quota.parse::<u64>().ok()).unwrap_or(5 * 1024 * 1024 * 1024)
+}
+
+impl AppState {
+
+    // Users and shared drives each have a quota of their own.
+    pub async fn get_quota_usage(&self, owner: &Owner) -> Result<QuotaUsage, mongodb::error::Error> {
+        let quota = match owner.kind {
+            OwnerKind::User => self.user_collection.user_collection.find_one(doc! {"_id": owner.id}, None).await?.and_then(|user| user.quota),
+            OwnerKind::Drive => self.drive_collection.drive_collection.find_one(doc! {"_id": owner.id}, None).await?.and_then(|drive| drive.quota)
+        };
+        let quota_bytes = quota.unwrap_or(default_quota());
+
+        let used_bytes = self.get_storage_usage(&owner.id).await?.logical_bytes;
+
+        Ok(QuotaUsage{
+            used_bytes,
+            quota_bytes,
+            available_bytes: quota_bytes.saturating_sub(used_bytes),
+        })
+    }
+
+    // Call before anything new is stored for the owner, `incoming` being the bytes about to be added.
+    pub async fn check_quota(&self, owner: &Owner, incoming: u64) -> Result<(), StorageError> {
+        let usage = match self.get_quota_usage(owner).await {
+            Ok(usage) => usage,
+            Err(_) => return Err(StatusCode::INTERNAL_SERVER_ERROR.into())
+        };
+
+        if incoming > usage.available_bytes {
+            return Err(StorageError::QuotaExceeded{ used: usage.used_bytes, quota: usage.quota_bytes, requested: incoming });
+        }
+
+        Ok(())
+    }
+}
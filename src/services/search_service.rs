@@ -0,0 +1,258 @@
+use std::collections::HashMap;
+use axum::http::StatusCode;
+use bson::{doc, Document};
+use bson::oid::ObjectId;
+use chrono::{DateTime, Utc};
+use futures::TryStreamExt;
+use serde::{Deserialize, Serialize};
+use crate::{AppState, Item};
+use crate::error::storage_error::StorageError;
+use crate::models::file_model::File;
+use crate::models::folder_model::Folder;
+
+
+const DEFAULT_PAGE_SIZE: u64 = 50;
+const MAX_PAGE_SIZE: u64 = 200;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SearchKind {
+    File,
+    Folder,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct SearchQuery {
+    // matched case-insensitively anywhere in the name, or only at its start with `prefix`
+    pub q: Option<String>,
+    pub prefix: Option<bool>,
+    pub kind: Option<SearchKind>,
+    pub file_type: Option<String>,
+    pub min_size: Option<u64>,
+    pub max_size: Option<u64>,
+    pub created_after: Option<DateTime<Utc>>,
+    pub created_before: Option<DateTime<Utc>>,
+    pub updated_after: Option<DateTime<Utc>>,
+    pub updated_before: Option<DateTime<Utc>>,
+    pub folder_id: Option<ObjectId>,
+    // also look inside the subfolders of `folder_id`
+    pub recursive: Option<bool>,
+    // a shared drive to search instead of the user's own one
+    pub drive_id: Option<ObjectId>,
+    // starts at 1
+    pub page: Option<u64>,
+    pub per_page: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SearchResults {
+    pub items: Vec<Item<File, Folder>>,
+    pub page: u64,
+    pub per_page: u64,
+    pub has_more: bool,
+}
+
+fn escape_regex(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+
+    for c in text.chars() {
+        if "\\^$.|?*+()[]{}".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+
+    escaped
+}
+
+// Dates are stored the way chrono serializes them, so the bounds are too.
+fn date_range(after: &Option<DateTime<Utc>>, before: &Option<DateTime<Utc>>) -> Option<Document> {
+    let mut range = doc! {};
+
+    if let Some(after) = after {
+        range.insert("$gte", bson::to_bson(after).unwrap_or_default());
+    }
+    if let Some(before) = before {
+        range.insert("$lte", bson::to_bson(before).unwrap_or_default());
+    }
+
+    Some(range).filter(|range| !range.is_empty())
+}
+
+impl SearchQuery {
+
+    // size and type only exist on files, asking for them leaves folders out
+    fn includes_folders(&self) -> bool {
+        !matches!(self.kind, Some(SearchKind::File)) && self.file_type.is_none() && self.min_size.is_none() && self.max_size.is_none()
+    }
+
+    fn includes_files(&self) -> bool {
+        !matches!(self.kind, Some(SearchKind::Folder))
+    }
+
+    fn common_filter(&self, user_id: &ObjectId, name_field: &str) -> Document {
+        let mut filter = doc! {"user_id": user_id};
+
+        if let Some(q) = self.q.as_deref().map(str::trim).filter(|q| !q.is_empty()) {
+            let pattern = if self.prefix.unwrap_or(false) { format!("^{}", escape_regex(q)) } else { escape_regex(q) };
+            filter.insert(name_field, doc! {"$regex": pattern, "$options": "i"});
+        }
+
+        if let Some(created) = date_range(&self.created_after, &self.created_before) {
+            filter.insert("createdAt", created);
+        }
+        if let Some(updated) = date_range(&self.updated_after, &self.updated_before) {
+            filter.insert("updatedAt", updated);
+        }
+
+        filter
+    }
+
+    fn file_filter(&self, user_id: &ObjectId, scope: &Option<Vec<ObjectId>>) -> Document {
+        let mut filter = self.common_filter(user_id, "file_name");
+
+        if let Some(file_type) = &self.file_type {
+            filter.insert("file_type", file_type);
+        }
+
+        let mut size = doc! {};
+        if let Some(min_size) = self.min_size {
+            size.insert("$gte", min_size as i64);
+        }
+        if let Some(max_size) = self.max_size {
+            size.insert("$lte", max_size as i64);
+        }
+        if !size.is_empty() {
+            filter.insert("size", size);
+        }
+
+        self.scoped(filter, "folder_id", scope)
+    }
+
+    fn folder_filter(&self, user_id: &ObjectId, scope: &Option<Vec<ObjectId>>) -> Document {
+        let filter = self.common_filter(user_id, "folder_name");
+        self.scoped(filter, "parent_id", scope)
+    }
+
+    // `scope` holds the folders whose direct children are searched: the folder asked for, and with
+    // `recursive` also every folder below it.
+    fn scoped(&self, mut filter: Document, parent_field: &str, scope: &Option<Vec<ObjectId>>) -> Document {
+        if let Some(folder_ids) = scope {
+            filter.insert(parent_field, doc! {"$in": folder_ids});
+        }
+
+        filter
+    }
+}
+
+fn tagged(filter: Document, kind: &str, name_field: &str) -> Vec<Document> {
+    vec![
+        doc! {"$match": filter},
+        doc! {"$set": {"kind": kind, "sort_name": {"$toLower": format!("${}", name_field)}}},
+    ]
+}
+
+impl AppState {
+
+    // Files and folders matching `query`, folders first and then by name, one page at a time.
+    pub async fn search(&self, user_id: &ObjectId, query: &SearchQuery) -> Result<SearchResults, StorageError> {
+        let page = query.page.unwrap_or(1).max(1);
+        let per_page = query.per_page.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE);
+
+        let scope = match query.folder_id {
+            Some(folder_id) => {
+                if self.folder_collection.folder_collection.find_one(doc! {"_id": folder_id, "user_id": user_id}, None).await?.is_none() {
+                    return Err(StatusCode::NOT_FOUND.into());
+                }
+
+                let mut folder_ids = vec![folder_id];
+                if query.recursive.unwrap_or(false) {
+                    folder_ids.extend(self.folder_collection.get_descendant_ids(&folder_id, user_id).await?);
+                }
+                Some(folder_ids)
+            },
+            None => None
+        };
+
+        let files = tagged(query.file_filter(user_id, &scope), "file", "file_name");
+        let folders = tagged(query.folder_filter(user_id, &scope), "folder", "folder_name");
+
+        let paging = vec![
+            doc! {"$sort": {"kind": -1, "sort_name": 1, "_id": 1}},
+            doc! {"$skip": (page - 1).saturating_mul(per_page).min(i64::MAX as u64) as i64},
+            // one extra to know whether there is another page
+            doc! {"$limit": (per_page + 1) as i64},
+        ];
+
+        let cursor = match (query.includes_files(), query.includes_folders()) {
+            (true, true) => {
+                let mut pipeline = files;
+                pipeline.push(doc! {"$unionWith": {"coll": "folders", "pipeline": folders}});
+                pipeline.extend(paging);
+                self.file_collection.file_collection.aggregate(pipeline, None).await?
+            },
+            (true, false) => self.file_collection.file_collection.aggregate([files, paging].concat(), None).await?,
+            (false, true) => self.folder_collection.folder_collection.aggregate([folders, paging].concat(), None).await?,
+            (false, false) => return Ok(SearchResults{ items: vec![], page, per_page, has_more: false })
+        };
+
+        let mut found = cursor.try_collect::<Vec<Document>>().await?;
+
+        let has_more = found.len() as u64 > per_page;
+        found.truncate(per_page as usize);
+
+        // folders are read again so they come with their children and totals like everywhere else
+        let folder_ids = found.iter()
+            .filter(|item| item.get_str("kind") == Ok("folder"))
+            .filter_map(|item| item.get_object_id("_id").ok())
+            .collect::<Vec<_>>();
+
+        let mut folders = HashMap::new();
+        if !folder_ids.is_empty() {
+            for folder in self.folder_collection.get_folder(doc! {"_id": {"$in": folder_ids}, "user_id": user_id}).await? {
+                folders.insert(folder.id.unwrap(), folder);
+            }
+        }
+
+        let items = found.into_iter().filter_map(|item| {
+            if item.get_str("kind") == Ok("folder") {
+                item.get_object_id("_id").ok().and_then(|folder_id| folders.remove(&folder_id)).map(Item::Folder)
+            } else {
+                bson::from_document::<File>(item).ok().map(Item::File)
+            }
+        }).collect::<Vec<_>>();
+
+        Ok(SearchResults{ items, page, per_page, has_more })
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use bson::doc;
+    use bson::oid::ObjectId;
+    use super::{escape_regex, SearchQuery};
+
+    #[test]
+    fn escapes_regex_syntax() {
+        assert_eq!(escape_regex("report (final).pdf"), "report \\(final\\)\\.pdf");
+        assert_eq!(escape_regex("a+b*c?^$|[x]{2}\\"), "a\\+b\\*c\\?\\^\\$\\|\\[x\\]\\{2\\}\\\\");
+    }
+
+    #[test]
+    fn keeps_plain_text() {
+        assert_eq!(escape_regex("holiday photos 2024"), "holiday photos 2024");
+        assert_eq!(escape_regex("überblick-ä_ö"), "überblick-ä_ö");
+    }
+
+    #[test]
+    fn scopes_by_parent_pointers() {
+        let query = SearchQuery{ recursive: Some(true), ..SearchQuery::default() };
+        let (folder_id, child_id) = (ObjectId::new(), ObjectId::new());
+        let scope = Some(vec![folder_id, child_id]);
+
+        assert_eq!(query.scoped(doc! {}, "folder_id", &scope), doc! {"folder_id": {"$in": [folder_id, child_id]}});
+        assert_eq!(query.scoped(doc! {}, "parent_id", &scope), doc! {"parent_id": {"$in": [folder_id, child_id]}});
+        assert_eq!(query.scoped(doc! {"user_id": folder_id}, "folder_id", &None), doc! {"user_id": folder_id});
+    }
+}
@@ -0,0 +1,265 @@
+use std::env;
+use async_trait::async_trait;
+use axum::http::StatusCode;
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use bcrypt::{DEFAULT_COST, hash, verify};
+use bson::{doc, Document};
+use bson::oid::ObjectId;
+use chrono::{DateTime, Duration, Utc};
+use dotenv::dotenv;
+use futures::TryStreamExt;
+use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
+use mongodb::{Collection, IndexModel};
+use mongodb::error::Error;
+use mongodb::options::{FindOneAndUpdateOptions, FindOptions, IndexOptions, ReturnDocument};
+use mongodb::results::{InsertOneResult, UpdateResult};
+use rand::RngCore;
+use crate::AppState;
+use crate::error::storage_error::StorageError;
+use crate::models::file_model::File;
+use crate::models::folder_model::Folder;
+use crate::models::permission_model::Role;
+use crate::models::share_model::{Share, SharedItemType};
+use crate::models::token_model::{ShareAccessClaims, TokenKind};
+use crate::services::trait_service::{mongo_client, StorageCollection};
+
+
+#[derive(Debug, Clone)]
+pub struct ShareCollection{
+    pub share_collection: Collection<Share>,
+}
+
+impl ShareCollection {
+
+    pub async fn get_shares(&self, filter: Document) -> Result<Vec<Share>, Error>{
+        let options = FindOptions::builder().sort(doc! {"createdAt": -1}).build();
+        self.share_collection.find(filter, options).await?.try_collect::<Vec<Share>>().await
+    }
+
+    pub async fn get_share_by_token(&self, token: &str) -> Result<Option<Share>, Error>{
+        self.share_collection.find_one(doc! {"token": token}, None).await
+    }
+
+    pub async fn create_share(&self, share: &Share) -> Result<InsertOneResult, Error>{
+        self.share_collection.insert_one(share, None).await
+    }
+
+    pub async fn update_share(&self, filter: Document, update: Document) -> Result<UpdateResult, Error>{
+        self.share_collection.update_one(filter, update, None).await
+    }
+}
+
+// 256 random bits, URL safe
+pub fn new_share_token() -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+// Wrong passwords a link takes before it locks for `share_lockout`
+const MAX_FAILED_ATTEMPTS: i32 = 5;
+
+fn share_lockout() -> Duration {
+    Duration::minutes(15)
+}
+
+fn share_access_ttl() -> Duration {
+    dotenv().ok();
+    let seconds = env::var("SHARE_ACCESS_TTL_SECS").ok().and_then(|ttl| ttl.parse::<i64>().ok()).unwrap_or(60 * 60);
+    Duration::seconds(seconds)
+}
+
+fn jwt_secret() -> String {
+    dotenv().ok();
+    env::var("JWT_TOKEN").expect("JWT_TOKEN not found in env")
+}
+
+impl AppState {
+
+    pub async fn create_share(&self, user_id: &ObjectId, item_type: SharedItemType, item_id: ObjectId, password: Option<&str>, expires_at: Option<DateTime<Utc>>, max_downloads: Option<i64>) -> Result<Share, StorageError> {
+        // links are public, handing them out is up to the owner and co-owners
+        let owner_id = self.authorize(user_id, item_type, &item_id, Role::CoOwner).await?.id;
+
+        let password = match password.filter(|password| !password.is_empty()) {
+            Some(password) => Some(hash(password, DEFAULT_COST).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?),
+            None => None
+        };
+
+        let mut share = Share{
+            id: None,
+            token: new_share_token(),
+            user_id: owner_id,
+            created_by: Some(*user_id),
+            item_type,
+            item_id,
+            password,
+            failed_attempts: 0,
+            locked_until: None,
+            expires_at: expires_at.map(bson::DateTime::from_chrono),
+            max_downloads,
+            downloads: 0,
+            revoked_at: None,
+            created_at: bson::DateTime::now(),
+        };
+
+        let inserted = self.share_collection.create_share(&share).await?;
+        share.id = inserted.inserted_id.as_object_id();
+
+        Ok(share)
+    }
+
+    // The share behind `token` if it has not been revoked, expired or used up.
+    async fn live_share(&self, token: &str) -> Result<Share, StorageError> {
+        let share = match self.share_collection.get_share_by_token(token).await? {
+            Some(share) if share.revoked_at.is_none() => share,
+            _ => return Err(StatusCode::NOT_FOUND.into())
+        };
+
+        if share.expires_at.map(|expires_at| expires_at.to_chrono() < Utc::now()).unwrap_or(false) {
+            return Err(StatusCode::GONE.into());
+        }
+
+        if share.max_downloads.map(|max_downloads| share.downloads >= max_downloads).unwrap_or(false) {
+            return Err(StatusCode::GONE.into());
+        }
+
+        Ok(share)
+    }
+
+    // Checks the password of a link once and exchanges it for an access token that expires after
+    // `share_access_ttl`. Returns the token and its lifetime in seconds.
+    pub async fn unlock_share(&self, token: &str, password: &str) -> Result<(String, i64), StorageError> {
+        let share = self.live_share(token).await?;
+        let share_id = share.id.unwrap();
+
+        if share.locked_until.map(|locked_until| locked_until.to_chrono() > Utc::now()).unwrap_or(false) {
+            return Err(StatusCode::TOO_MANY_REQUESTS.into());
+        }
+
+        if let Some(hashed) = &share.password {
+            if !verify(password, hashed).unwrap_or(false) {
+                self.record_failed_attempt(&share_id).await?;
+                return Err(StatusCode::UNAUTHORIZED.into());
+            }
+        }
+
+        if share.failed_attempts > 0 {
+            self.share_collection.update_share(doc! {"_id": share_id}, doc! {"$set": {"failed_attempts": 0}}).await?;
+        }
+
+        let ttl = share_access_ttl();
+        let claims = ShareAccessClaims{ kind: TokenKind::ShareAccess, share_id, exp: (Utc::now() + ttl).timestamp() };
+
+        match encode(&Header::default(), &claims, &EncodingKey::from_secret(jwt_secret().as_ref())) {
+            Ok(access_token) => Ok((access_token, ttl.num_seconds())),
+            Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR.into())
+        }
+    }
+
+    async fn record_failed_attempt(&self, share_id: &ObjectId) -> Result<(), Error> {
+        let options = FindOneAndUpdateOptions::builder().return_document(ReturnDocument::After).build();
+        let share = self.share_collection.share_collection.find_one_and_update(doc! {"_id": share_id}, doc! {"$inc": {"failed_attempts": 1}}, options).await?;
+
+        if share.map(|share| share.failed_attempts >= MAX_FAILED_ATTEMPTS).unwrap_or(false) {
+            let locked_until = bson::DateTime::from_chrono(Utc::now() + share_lockout());
+            self.share_collection.update_share(doc! {"_id": share_id}, doc! {"$set": {"failed_attempts": 0, "locked_until": locked_until}}).await?;
+        }
+
+        Ok(())
+    }
+
+    // The share behind `token` if it can still be used, password protected links also need an
+    // access token from `unlock_share`.
+    pub async fn open_share(&self, token: &str, access_token: Option<&str>) -> Result<Share, StorageError> {
+        let share = self.live_share(token).await?;
+
+        if share.password.is_some() {
+            let claims = access_token
+                .and_then(|access_token| decode::<ShareAccessClaims>(access_token, &DecodingKey::from_secret(jwt_secret().as_ref()), &Validation::default()).ok())
+                .map(|token_data| token_data.claims);
+
+            if !claims.map(|claims| claims.kind == TokenKind::ShareAccess && Some(claims.share_id) == share.id).unwrap_or(false) {
+                return Err(StatusCode::UNAUTHORIZED.into());
+            }
+        }
+
+        Ok(share)
+    }
+
+    // Whether the folder is the shared folder or lies below it, going up the parent pointers
+    // like the breadcrumbs do rather than trusting the stored paths.
+    async fn within_shared_folder(&self, share: &Share, folder_id: &ObjectId) -> Result<bool, Error> {
+        let crumbs = self.folder_collection.get_breadcrumbs(folder_id, &share.user_id).await?;
+        Ok(crumbs.iter().any(|crumb| crumb.id == share.item_id))
+    }
+
+    // The shared folder itself, or one of the folders below it.
+    pub async fn shared_folder(&self, share: &Share, folder_id: Option<ObjectId>) -> Result<Folder, StorageError> {
+        if share.item_type != SharedItemType::Folder {
+            return Err(StatusCode::NOT_FOUND.into());
+        }
+
+        let folder_id = folder_id.unwrap_or(share.item_id);
+
+        if !self.within_shared_folder(share, &folder_id).await? {
+            return Err(StatusCode::NOT_FOUND.into());
+        }
+
+        self.folder_collection.folder_collection.find_one(doc! {"_id": folder_id, "user_id": share.user_id}, None).await?
+            .ok_or(StorageError::Status(StatusCode::NOT_FOUND))
+    }
+
+    // The shared file, or any file inside the shared folder.
+    pub async fn shared_file(&self, share: &Share, file_id: &ObjectId) -> Result<File, StorageError> {
+        let file = self.file_collection.file_collection.find_one(doc! {"_id": file_id, "user_id": share.user_id}, None).await?
+            .ok_or(StorageError::Status(StatusCode::NOT_FOUND))?;
+
+        let shared = match share.item_type {
+            SharedItemType::File => file.id == Some(share.item_id),
+            SharedItemType::Folder => match file.folder_id {
+                Some(folder_id) => self.within_shared_folder(share, &folder_id).await?,
+                None => false
+            }
+        };
+
+        if !shared {
+            return Err(StatusCode::NOT_FOUND.into());
+        }
+
+        Ok(file)
+    }
+
+    // Takes one download off the share, failing once the limit is used up.
+    pub async fn count_share_download(&self, share: &Share) -> Result<(), StorageError> {
+        let filter = doc! {
+            "_id": share.id,
+            "$or": [{"max_downloads": null}, {"$expr": {"$lt": ["$downloads", "$max_downloads"]}}],
+        };
+
+        let updated = self.share_collection.update_share(filter, doc! {"$inc": {"downloads": 1}}).await?;
+
+        if updated.modified_count == 0 {
+            return Err(StatusCode::GONE.into());
+        }
+
+        Ok(())
+    }
+}
+
+
+#[async_trait]
+impl StorageCollection for ShareCollection{
+    type Error = Box<dyn std::error::Error>;
+
+    async fn init() -> Result<Self, Self::Error> where Self: Sized {
+        let client = mongo_client().await?;
+        let db = client.database("cloud_storage");
+        let col: Collection<Share> = db.collection("shares");
+
+        let unique = IndexOptions::builder().unique(true).build();
+        col.create_index(IndexModel::builder().keys(doc! {"token": 1}).options(unique).build(), None).await?;
+
+        Ok(Self{ share_collection: col })
+    }
+}
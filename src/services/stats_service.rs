@@ -0,0 +1,132 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use bson::doc;
+use bson::oid::ObjectId;
+use futures::TryStreamExt;
+use mongodb::error::Error;
+use mongodb::options::FindOptions;
+use serde::Deserialize;
+use crate::{AppState, Item};
+use crate::models::file_model::File;
+use crate::models::folder_model::{Folder, FolderStats};
+
+
+// Writes drop the owner's entry right away, this only bounds how stale a missed write can leave it.
+const STATS_TTL: Duration = Duration::from_secs(30);
+
+type OwnerStats = Arc<HashMap<ObjectId, FolderStats>>;
+
+// Recursive folder totals per owner, computed for all of the owner's folders at once and kept
+// until the owner's files or folders change.
+#[derive(Debug, Clone, Default)]
+pub struct FolderStatsCache{
+    entries: Arc<Mutex<HashMap<ObjectId, (Instant, OwnerStats)>>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct FolderTotals {
+    #[serde(rename = "_id")]
+    folder_id: Option<ObjectId>,
+    size: i64,
+    file_count: i64,
+}
+
+#[derive(Debug, Deserialize)]
+struct FolderLink {
+    #[serde(rename = "_id")]
+    id: ObjectId,
+    parent_id: Option<ObjectId>,
+}
+
+// Adds every folder's own totals to all of its ancestors.
+fn roll_up(links: &[FolderLink], totals: &[FolderTotals]) -> HashMap<ObjectId, FolderStats> {
+    let parents = links.iter().map(|link| (link.id, link.parent_id)).collect::<HashMap<_, _>>();
+    let mut stats = links.iter().map(|link| (link.id, FolderStats::default())).collect::<HashMap<_, _>>();
+
+    let mut add = |start: Option<ObjectId>, change: FolderStats| {
+        let mut next = start;
+        // a broken parent chain can't loop forever
+        let mut steps = 0;
+
+        while let Some(folder_id) = next {
+            if steps > parents.len() {
+                break;
+            }
+            if let Some(folder) = stats.get_mut(&folder_id) {
+                folder.size += change.size;
+                folder.file_count += change.file_count;
+                folder.folder_count += change.folder_count;
+            }
+            next = parents.get(&folder_id).cloned().flatten();
+            steps += 1;
+        }
+    };
+
+    for totals in totals {
+        add(totals.folder_id, FolderStats{ size: totals.size.max(0) as u64, file_count: totals.file_count.max(0) as u64, folder_count: 0 });
+    }
+
+    for link in links {
+        add(link.parent_id, FolderStats{ size: 0, file_count: 0, folder_count: 1 });
+    }
+
+    stats
+}
+
+impl AppState {
+
+    async fn owner_stats(&self, user_id: &ObjectId) -> Result<OwnerStats, Error> {
+        if let Some((computed_at, stats)) = self.stats_cache.entries.lock().unwrap().get(user_id) {
+            if computed_at.elapsed() < STATS_TTL {
+                return Ok(stats.clone());
+            }
+        }
+
+        // both only touch the owner's documents through the user_id indexes
+        let options = FindOptions::builder().projection(doc! {"_id": 1, "parent_id": 1}).build();
+        let links = self.folder_collection.folder_collection.clone_with_type::<FolderLink>()
+            .find(doc! {"user_id": user_id}, options).await?
+            .try_collect::<Vec<_>>().await?;
+
+        let pipeline = vec![
+            doc! {"$match": {"user_id": user_id}},
+            doc! {"$group": {"_id": "$folder_id", "size": {"$sum": "$size"}, "file_count": {"$sum": 1}}},
+            doc! {"$set": {"size": {"$toLong": "$size"}, "file_count": {"$toLong": "$file_count"}}},
+        ];
+        let totals = self.file_collection.file_collection
+            .aggregate(pipeline, None).await?
+            .and_then(|totals| async move { Ok(bson::from_document::<FolderTotals>(totals)?) })
+            .try_collect::<Vec<_>>().await?;
+
+        let stats = Arc::new(roll_up(&links, &totals));
+        self.stats_cache.entries.lock().unwrap().insert(*user_id, (Instant::now(), stats.clone()));
+
+        Ok(stats)
+    }
+
+    // Call after anything that adds, removes, moves or resizes the owner's files or folders.
+    pub fn invalidate_stats(&self, user_id: &ObjectId) {
+        self.stats_cache.entries.lock().unwrap().remove(user_id);
+    }
+
+    // Fills in `stats` of the owner's folders.
+    pub async fn with_stats<'a>(&self, user_id: &ObjectId, folders: impl IntoIterator<Item = &'a mut Folder>) -> Result<(), Error> {
+        let stats = self.owner_stats(user_id).await?;
+
+        for folder in folders {
+            folder.stats = Some(folder.id.and_then(|id| stats.get(&id).cloned()).unwrap_or_default());
+        }
+
+        Ok(())
+    }
+
+    pub async fn with_item_stats(&self, user_id: &ObjectId, items: &mut [Item<File, Folder>]) -> Result<(), Error> {
+        let folders = items.iter_mut().filter_map(|item| match item {
+            Item::Folder(folder) => Some(folder),
+            Item::File(_) => None
+        });
+
+        self.with_stats(user_id, folders).await
+    }
+}
@@ -1,8 +1,55 @@
+use std::env;
 use async_trait::async_trait;
+use dotenv::dotenv;
+use mongodb::{Client, ClientSession};
+use mongodb::error::{Error, UNKNOWN_TRANSACTION_COMMIT_RESULT};
+use mongodb::options::ClientOptions;
+use tokio::sync::OnceCell;
 
 #[async_trait]
 pub trait StorageCollection{
     type Error;
 
     async fn init() -> Result<Self, Self::Error> where Self: Sized;
-}
\ No newline at end of file
+}
+
+
+static CLIENT: OnceCell<Client> = OnceCell::const_new();
+
+// Every collection is opened from this one client, a session can only span collections of the same client.
+pub async fn mongo_client() -> Result<Client, Error> {
+    CLIENT.get_or_try_init(|| async {
+        dotenv().ok();
+        let mongo_uri = env::var("MONGODB_URI").expect("MONGODB_URI not found in env");
+
+        let client_options = ClientOptions::parse(mongo_uri).await?;
+        Client::with_options(client_options)
+    }).await.cloned()
+}
+
+pub async fn start_transaction() -> Result<ClientSession, Error> {
+    let mut session = mongo_client().await?.start_session(None).await?;
+    session.start_transaction(None).await?;
+    Ok(session)
+}
+
+// Commits when `result` is Ok and aborts otherwise, so the caller's error is what comes back.
+pub async fn finish_transaction<T, E: From<Error>>(mut session: ClientSession, result: Result<T, E>) -> Result<T, E> {
+    let value = match result {
+        Ok(value) => value,
+        Err(err) => {
+            let _ = session.abort_transaction().await;
+            return Err(err);
+        }
+    };
+
+    let mut attempts = 0;
+
+    loop {
+        match session.commit_transaction().await {
+            Ok(_) => return Ok(value),
+            Err(err) if err.contains_label(UNKNOWN_TRANSACTION_COMMIT_RESULT) && attempts < 3 => attempts += 1,
+            Err(err) => return Err(err.into())
+        }
+    }
+}
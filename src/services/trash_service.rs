@@ -0,0 +1,339 @@
+use std::env;
+use async_trait::async_trait;
+use bson::{doc, Document};
+use bson::oid::ObjectId;
+use chrono::{Duration, Utc};
+use dotenv::dotenv;
+use futures::TryStreamExt;
+use mongodb::{ClientSession, Collection, IndexModel};
+use mongodb::error::Error;
+use mongodb::options::FindOptions;
+use mongodb::results::{DeleteResult, InsertOneResult};
+use crate::AppState;
+use crate::models::file_model::File;
+use crate::models::folder_model::Folder;
+use crate::error::storage_error::StorageError;
+use crate::models::trash_model::{TrashedFile, TrashedFolder, TrashItem, TrashItemType};
+use crate::services::naming_service::ConflictPolicy;
+use crate::services::trait_service::{finish_transaction, mongo_client, start_transaction, StorageCollection};
+use crate::services::tree_service::{child_path, folder_type_for, rebased_path};
+
+
+pub fn trash_retention() -> Duration {
+    dotenv().ok();
+    let days = env::var("TRASH_RETENTION_DAYS").ok().and_then(|days| days.parse::<i64>().ok()).unwrap_or(30);
+    Duration::days(days)
+}
+
+#[derive(Debug, Clone)]
+pub struct TrashCollection{
+    pub trash_collection: Collection<TrashItem>,
+    pub trashed_file_collection: Collection<TrashedFile>,
+    pub trashed_folder_collection: Collection<TrashedFolder>,
+}
+
+impl TrashCollection {
+
+    pub async fn get_trash(&self, filter: Document) -> Result<Vec<TrashItem>, Error>{
+        let options = FindOptions::builder().sort(doc! {"deletedAt": -1}).build();
+        self.trash_collection.find(filter, options).await?.try_collect::<Vec<TrashItem>>().await
+    }
+
+    pub async fn create_trash_item(&self, item: &TrashItem) -> Result<InsertOneResult, Error>{
+        self.trash_collection.insert_one(item, None).await
+    }
+
+    pub async fn delete_trash_items(&self, filter: Document) -> Result<DeleteResult, Error>{
+        self.trash_collection.delete_many(filter, None).await
+    }
+
+    // Everything the item took out of the tree.
+    pub async fn get_trashed_contents(&self, item: &TrashItem) -> Result<(Vec<Folder>, Vec<File>), Error> {
+        let filter = doc! {"trash_id": item.id};
+        let folders = self.trashed_folder_collection.find(filter.clone(), None).await?.map_ok(|row| row.folder).try_collect::<Vec<_>>().await?;
+        let files = self.trashed_file_collection.find(filter, None).await?.map_ok(|row| row.file).try_collect::<Vec<_>>().await?;
+
+        Ok((folders, files))
+    }
+
+    pub async fn get_trashed_contents_with_session(&self, item: &TrashItem, session: &mut ClientSession) -> Result<(Vec<Folder>, Vec<File>), Error> {
+        let filter = doc! {"trash_id": item.id};
+        let folders = self.trashed_folder_collection.find_with_session(filter.clone(), None, session).await?.stream(session).map_ok(|row| row.folder).try_collect::<Vec<_>>().await?;
+        let files = self.trashed_file_collection.find_with_session(filter, None, session).await?.stream(session).map_ok(|row| row.file).try_collect::<Vec<_>>().await?;
+
+        Ok((folders, files))
+    }
+
+    pub async fn delete_trashed_contents(&self, trash_id: Option<ObjectId>) -> Result<(), Error> {
+        self.trashed_file_collection.delete_many(doc! {"trash_id": trash_id}, None).await?;
+        self.trashed_folder_collection.delete_many(doc! {"trash_id": trash_id}, None).await?;
+        Ok(())
+    }
+
+    pub async fn delete_trashed_contents_with_session(&self, trash_id: Option<ObjectId>, session: &mut ClientSession) -> Result<(), Error> {
+        self.trashed_file_collection.delete_many_with_session(doc! {"trash_id": trash_id}, None, session).await?;
+        self.trashed_folder_collection.delete_many_with_session(doc! {"trash_id": trash_id}, None, session).await?;
+        Ok(())
+    }
+}
+
+
+impl AppState {
+
+    pub async fn trash_file_in(&self, file: File, user_id: &ObjectId, session: &mut ClientSession) -> Result<(), Error> {
+        let file_id = file.id.unwrap();
+        let trash_id = ObjectId::new();
+
+        let item = TrashItem{
+            id: Some(trash_id),
+            user_id: *user_id,
+            item_type: TrashItemType::File,
+            item_id: file_id,
+            name: file.file_name.clone(),
+            original_parent: file.folder_id,
+            ancestors: self.folder_ancestors(file.folder_id, user_id, session).await?,
+            size: file.size,
+            deleted_at: bson::DateTime::now(),
+        };
+
+        let row = TrashedFile{ id: file_id, trash_id, user_id: *user_id, file };
+
+        self.trash_collection.trash_collection.insert_one_with_session(&item, None, session).await?;
+        self.trash_collection.trashed_file_collection.insert_one_with_session(&row, None, session).await?;
+        self.file_collection.file_collection.delete_one_with_session(doc! {"_id": file_id, "user_id": user_id}, None, session).await?;
+
+        Ok(())
+    }
+
+    pub async fn trash_folder_in(&self, folder: Folder, user_id: &ObjectId, session: &mut ClientSession) -> Result<(), Error> {
+        let folder_id = folder.id.unwrap();
+        let parent_id = folder.parent_id;
+        let name = folder.folder_name.clone();
+
+        let ancestors = self.folder_ancestors(parent_id, user_id, session).await?;
+        let (folders, files) = self.folder_subtree(folder, user_id, session).await?;
+
+        let folder_ids = folders.iter().filter_map(|folder| folder.id).collect::<Vec<_>>();
+        let file_ids = files.iter().filter_map(|file| file.id).collect::<Vec<_>>();
+
+        let trash_id = ObjectId::new();
+
+        let item = TrashItem{
+            id: Some(trash_id),
+            user_id: *user_id,
+            item_type: TrashItemType::Folder,
+            item_id: folder_id,
+            name,
+            original_parent: parent_id,
+            ancestors,
+            size: files.iter().map(|file| file.size).sum(),
+            deleted_at: bson::DateTime::now(),
+        };
+
+        let folder_rows = folders.into_iter().map(|folder| TrashedFolder{ id: folder.id.unwrap(), trash_id, user_id: *user_id, folder }).collect::<Vec<_>>();
+        let file_rows = files.into_iter().map(|file| TrashedFile{ id: file.id.unwrap(), trash_id, user_id: *user_id, file }).collect::<Vec<_>>();
+
+        self.trash_collection.trash_collection.insert_one_with_session(&item, None, session).await?;
+        self.trash_collection.trashed_folder_collection.insert_many_with_session(&folder_rows, None, session).await?;
+
+        if !file_rows.is_empty() {
+            self.trash_collection.trashed_file_collection.insert_many_with_session(&file_rows, None, session).await?;
+        }
+
+        self.file_collection.file_collection.delete_many_with_session(doc! {"_id": {"$in": file_ids}, "user_id": user_id}, None, session).await?;
+        self.folder_collection.folder_collection.delete_many_with_session(doc! {"_id": {"$in": folder_ids}, "user_id": user_id}, None, session).await?;
+
+        Ok(())
+    }
+
+    pub async fn trash_files(&self, files: Vec<File>, user_id: &ObjectId) -> Result<(), Error> {
+        let mut session = start_transaction().await?;
+
+        let result = async {
+            for file in files {
+                self.trash_file_in(file, user_id, &mut session).await?;
+            }
+            Ok(())
+        }.await;
+
+        let result = finish_transaction(session, result).await;
+        self.invalidate_stats(user_id);
+        result
+    }
+
+    pub async fn trash_folder(&self, folder: Folder, user_id: &ObjectId) -> Result<(), Error> {
+        let mut session = start_transaction().await?;
+        let result = self.trash_folder_in(folder, user_id, &mut session).await;
+        let result = finish_transaction(session, result).await;
+        self.invalidate_stats(user_id);
+        result
+    }
+
+    // Puts a folder document back. It may already exist as an ancestor recreated for an earlier
+    // restore, then only its place in the tree is updated.
+    async fn put_back_folder(&self, folder: &Folder, user_id: &ObjectId, session: &mut ClientSession) -> Result<(), Error> {
+        let folder_id = folder.id.unwrap();
+
+        let mut folder = folder.clone();
+        folder.files = None;
+        folder.folders = None;
+        folder.stats = None;
+
+        if self.folder_exists(&folder_id, user_id, session).await? {
+            let update = doc! {"$set": {"folder_name": &folder.folder_name, "parent_id": folder.parent_id, "folder_type": folder.folder_type, "path": &folder.path}};
+            self.folder_collection.folder_collection.update_one_with_session(doc! {"_id": folder_id, "user_id": user_id}, update, None, session).await?;
+        } else {
+            self.folder_collection.folder_collection.insert_one_with_session(&folder, None, session).await?;
+        }
+
+        Ok(())
+    }
+
+    // Recreates whatever part of the recorded ancestor chain no longer exists, empty.
+    async fn recreate_ancestors(&self, ancestors: &[Folder], user_id: &ObjectId, session: &mut ClientSession) -> Result<(), Error> {
+        for ancestor in ancestors {
+            let folder_id = ancestor.id.unwrap();
+
+            if self.folder_exists(&folder_id, user_id, session).await? {
+                continue;
+            }
+
+            let mut folder = ancestor.clone();
+
+            let parent_id = match folder.parent_id {
+                Some(parent_id) if self.folder_exists(&parent_id, user_id, session).await? => Some(parent_id),
+                _ => None
+            };
+
+            folder.parent_id = parent_id;
+            folder.folder_type = Some(folder_type_for(parent_id));
+            folder.path = Some(child_path(self.folder_path_in(parent_id, user_id, session).await?.as_deref(), &folder.folder_name));
+            self.put_back_folder(&folder, user_id, session).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn restore_trash_item_in(&self, item: TrashItem, to_root: bool, policy: ConflictPolicy, session: &mut ClientSession) -> Result<(), StorageError> {
+        let user_id = item.user_id;
+
+        let parent_id = match item.original_parent {
+            Some(_) if to_root => None,
+            Some(parent_id) => {
+                self.recreate_ancestors(&item.ancestors, &user_id, session).await?;
+                Some(parent_id).filter(|_| !item.ancestors.is_empty())
+            },
+            None => None
+        };
+
+        let (mut folders, mut files) = self.trash_collection.get_trashed_contents_with_session(&item, session).await?;
+
+        // the name may have been taken since the delete, the item itself is excluded as it can
+        // already be back as an ancestor recreated for an earlier restore
+        let name = match item.item_type {
+            TrashItemType::File => self.resolve_file_name(&user_id, parent_id, &item.name, Some(item.item_id), policy, session).await?,
+            TrashItemType::Folder => self.resolve_folder_name(&user_id, parent_id, &item.name, Some(item.item_id), policy, session).await?,
+        };
+
+        // the restored item may end up somewhere else than where it was deleted from
+        let new_root_path = Some(child_path(self.folder_path_in(parent_id, &user_id, session).await?.as_deref(), &name));
+
+        match item.item_type {
+            TrashItemType::File => {
+                for file in files.iter_mut() {
+                    file.folder_id = parent_id;
+                    file.file_name = name.clone();
+                    file.original_file_name = Some(name.clone());
+                    file.path = new_root_path.clone();
+                }
+            },
+            TrashItemType::Folder => {
+                let old_root_path = folders.iter().find(|folder| folder.id == Some(item.item_id)).and_then(|root| root.path.clone());
+
+                for folder in folders.iter_mut() {
+                    if folder.id == Some(item.item_id) {
+                        folder.folder_name = name.clone();
+                        folder.parent_id = parent_id;
+                        folder.folder_type = Some(folder_type_for(parent_id));
+                        folder.path = new_root_path.clone();
+                    } else {
+                        folder.path = rebased_path(&folder.path, &old_root_path, &new_root_path);
+                    }
+                }
+
+                for file in files.iter_mut() {
+                    file.path = rebased_path(&file.path, &old_root_path, &new_root_path);
+                }
+            }
+        }
+
+        for folder in folders.iter() {
+            self.put_back_folder(folder, &user_id, session).await?;
+        }
+
+        if !files.is_empty() {
+            self.file_collection.file_collection.insert_many_with_session(&files, None, session).await?;
+        }
+
+        self.trash_collection.delete_trashed_contents_with_session(item.id, session).await?;
+        self.trash_collection.trash_collection.delete_one_with_session(doc! {"_id": item.id}, None, session).await?;
+
+        Ok(())
+    }
+
+    // Restores into the original parent, recreating it if needed, or at the root when `to_root` is set.
+    pub async fn restore_trash_item(&self, item: TrashItem, to_root: bool, policy: ConflictPolicy) -> Result<(), StorageError> {
+        let mut session = start_transaction().await?;
+        let user_id = item.user_id;
+        let result = self.restore_trash_item_in(item, to_root, policy, &mut session).await;
+        let result = finish_transaction(session, result).await;
+        self.invalidate_stats(&user_id);
+        result
+    }
+
+    // Permanently deletes trashed items, their content goes the same way as a regular delete used to.
+    pub async fn purge_trash_items(&self, items: &[TrashItem]) -> Result<u64, Error> {
+        let mut purged = 0;
+
+        for item in items {
+            let (folders, files) = self.trash_collection.get_trashed_contents(item).await?;
+
+            purged += self.trash_collection.delete_trash_items(doc! {"_id": item.id}).await?.deleted_count;
+            self.trash_collection.delete_trashed_contents(item.id).await?;
+            self.release_files(&files).await;
+
+            let item_ids = files.iter().filter_map(|file| file.id).chain(folders.iter().filter_map(|folder| folder.id)).collect::<Vec<_>>();
+            self.forget_items(&item_ids).await?;
+        }
+
+        Ok(purged)
+    }
+
+    pub async fn purge_expired_trash(&self) -> Result<u64, Error> {
+        let cutoff = bson::DateTime::from_chrono(Utc::now() - trash_retention());
+        let expired = self.trash_collection.get_trash(doc! {"deletedAt": {"$lt": cutoff}}).await?;
+
+        self.purge_trash_items(&expired).await
+    }
+}
+
+
+#[async_trait]
+impl StorageCollection for TrashCollection{
+    type Error = Box<dyn std::error::Error>;
+
+    async fn init() -> Result<Self, Self::Error> where Self: Sized {
+        let client = mongo_client().await?;
+        let db = client.database("cloud_storage");
+        let col: Collection<TrashItem> = db.collection("trash");
+        let file_col: Collection<TrashedFile> = db.collection("trashed_files");
+        let folder_col: Collection<TrashedFolder> = db.collection("trashed_folders");
+
+        file_col.create_index(IndexModel::builder().keys(doc! {"trash_id": 1}).build(), None).await?;
+        // trashed files count towards the quota of their owner
+        file_col.create_index(IndexModel::builder().keys(doc! {"user_id": 1}).build(), None).await?;
+        folder_col.create_index(IndexModel::builder().keys(doc! {"trash_id": 1}).build(), None).await?;
+
+        Ok(Self{ trash_collection: col, trashed_file_collection: file_col, trashed_folder_collection: folder_col })
+    }
+}
@@ -0,0 +1,614 @@
+use std::collections::HashMap;
+use axum::http::StatusCode;
+use bson::{doc, Document};
+use bson::oid::ObjectId;
+use chrono::Utc;
+use futures::TryStreamExt;
+use mongodb::ClientSession;
+use mongodb::error::Error;
+use serde::{Deserialize, Serialize};
+use crate::AppState;
+use crate::error::storage_error::StorageError;
+use crate::models::file_model::File;
+use crate::models::folder_model::{Folder, FolderJSON, FolderType};
+use crate::models::owner_model::Owner;
+use crate::services::naming_service::ConflictPolicy;
+use crate::services::trait_service::{finish_transaction, start_transaction};
+
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MovedItems {
+    pub files: Vec<File>,
+    pub folders: Vec<Folder>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CopiedItems {
+    pub files: Vec<File>,
+    pub folders: Vec<Folder>,
+    // hex id of every copied file and folder -> id of its copy
+    pub id_map: HashMap<String, ObjectId>,
+}
+
+// Paths are owned by the backend: "/" followed by the names from the root down, e.g.
+// "/Photos/2024/beach.jpg". `parent_path` is None for items at the root.
+pub fn child_path(parent_path: Option<&str>, name: &str) -> String {
+    match parent_path {
+        Some(parent_path) => format!("{}/{}", parent_path.trim_end_matches('/'), name),
+        None => format!("/{}", name)
+    }
+}
+
+pub fn folder_type_for(parent_id: Option<ObjectId>) -> FolderType {
+    match parent_id {
+        Some(_) => FolderType::Subfolder,
+        None => FolderType::Folder
+    }
+}
+
+fn moved_path(destination: &Option<Folder>, name: &str) -> String {
+    child_path(destination.as_ref().and_then(|folder| folder.path.as_deref()), name)
+}
+
+// Moves `path` from below `old_root` to below `new_root`, paths outside of old_root are kept.
+pub fn rebased_path(path: &Option<String>, old_root: &Option<String>, new_root: &Option<String>) -> Option<String> {
+    match (path, old_root, new_root) {
+        (Some(path), Some(old_root), Some(new_root)) => match path.strip_prefix(old_root.as_str()) {
+            Some(rest) if rest.starts_with('/') => Some(format!("{}{}", new_root, rest)),
+            _ => Some(path.clone())
+        },
+        _ => path.clone()
+    }
+}
+
+// Builds the FolderJSON for `folder` out of the children collected by parent id. A folder that
+// has no entry in `children` wasn't expanded.
+fn nest_folder(folder: Folder, folder_id: Option<ObjectId>, children: &mut HashMap<Option<ObjectId>, Vec<Folder>>, files: &mut HashMap<Option<ObjectId>, Vec<File>>) -> FolderJSON {
+    let subfolders = children.remove(&folder_id).map(|subfolders| {
+        subfolders.into_iter().map(|subfolder| {
+            let subfolder_id = subfolder.id;
+            nest_folder(subfolder, subfolder_id, children, files)
+        }).collect::<Vec<_>>()
+    });
+
+    FolderJSON{
+        id: folder.id,
+        folder_name: folder.folder_name,
+        folder_type: folder.folder_type,
+        files: Some(files.remove(&folder_id).unwrap_or_default()),
+        folders: subfolders,
+        parent_id: folder.parent_id,
+        path: folder.path,
+    }
+}
+
+// The hierarchy lives in File.folder_id and Folder.parent_id only. Everything that changes more
+// than one document of the tree takes a session and runs inside a transaction.
+impl AppState {
+
+    pub async fn folder_exists(&self, folder_id: &ObjectId, user_id: &ObjectId, session: &mut ClientSession) -> Result<bool, Error> {
+        let folders = self.folder_collection.get_folder_with_session(doc! {"_id": folder_id, "user_id": user_id}, session).await?;
+        Ok(!folders.is_empty())
+    }
+
+    // Path of the folder `folder_id`, None for the root.
+    pub async fn folder_path_in(&self, folder_id: Option<ObjectId>, user_id: &ObjectId, session: &mut ClientSession) -> Result<Option<String>, Error> {
+        match folder_id {
+            Some(folder_id) => {
+                let folders = self.folder_collection.get_folder_with_session(doc! {"_id": folder_id, "user_id": user_id}, session).await?;
+                Ok(folders.into_iter().next().and_then(|folder| folder.path))
+            },
+            None => Ok(None)
+        }
+    }
+
+    pub async fn folder_path(&self, folder_id: Option<ObjectId>, user_id: &ObjectId) -> Result<Option<String>, Error> {
+        match folder_id {
+            Some(folder_id) => {
+                let folders = self.folder_collection.folder_collection.find_one(doc! {"_id": folder_id, "user_id": user_id}, None).await?;
+                Ok(folders.and_then(|folder| folder.path))
+            },
+            None => Ok(None)
+        }
+    }
+
+    // The chain of folders above `parent_id`, root first.
+    pub async fn folder_ancestors(&self, parent_id: Option<ObjectId>, user_id: &ObjectId, session: &mut ClientSession) -> Result<Vec<Folder>, Error> {
+        let mut ancestors: Vec<Folder> = vec![];
+        let mut next = parent_id;
+
+        while let Some(folder_id) = next {
+            if ancestors.iter().any(|ancestor| ancestor.id == Some(folder_id)) {
+                break;
+            }
+
+            match self.folder_collection.get_folder_with_session(doc! {"_id": folder_id, "user_id": user_id}, session).await?.into_iter().next() {
+                Some(folder) => {
+                    next = folder.parent_id;
+                    ancestors.push(folder);
+                },
+                None => break
+            }
+        }
+
+        ancestors.reverse();
+        Ok(ancestors)
+    }
+
+    // Every folder and file below `folder`, one query per level. The folder itself comes first.
+    pub async fn folder_subtree(&self, folder: Folder, user_id: &ObjectId, session: &mut ClientSession) -> Result<(Vec<Folder>, Vec<File>), Error> {
+        let mut level = folder.id.into_iter().collect::<Vec<_>>();
+        let mut folders = vec![folder];
+        let mut files: Vec<File> = vec![];
+
+        while !level.is_empty() {
+            let file_filter = doc! {"user_id": user_id, "folder_id": {"$in": level.clone()}};
+            files.extend(self.file_collection.get_file_with_session(file_filter, session).await?);
+
+            let folder_filter = doc! {"user_id": user_id, "parent_id": {"$in": level.clone()}};
+            let children = self.folder_collection.get_folder_with_session(folder_filter, session).await?
+                .into_iter()
+                .filter(|child| !folders.iter().any(|seen| seen.id == child.id))
+                .collect::<Vec<_>>();
+
+            level = children.iter().filter_map(|child| child.id).collect();
+            folders.extend(children);
+        }
+
+        Ok((folders, files))
+    }
+
+    // The folder `folder_id`, or the whole drive for None, as a nested FolderJSON with its files.
+    // Folders `max_depth` levels down come without their subfolders (`folders` is None).
+    pub async fn folder_tree(&self, folder_id: Option<ObjectId>, user_id: &ObjectId, max_depth: Option<usize>) -> Result<Option<FolderJSON>, Error> {
+        let root = match folder_id {
+            Some(folder_id) => match self.folder_collection.folder_collection.find_one(doc! {"_id": folder_id, "user_id": user_id}, None).await? {
+                Some(folder) => folder,
+                None => return Ok(None)
+            },
+            None => Folder{ folder_name: "/".to_string(), path: Some("/".to_string()), ..Folder::new() }
+        };
+
+        let mut children: HashMap<Option<ObjectId>, Vec<Folder>> = HashMap::new();
+        let mut files: HashMap<Option<ObjectId>, Vec<File>> = HashMap::new();
+
+        let mut level = vec![folder_id];
+        let mut depth = 0;
+
+        while !level.is_empty() {
+            let file_filter = doc! {"user_id": user_id, "folder_id": {"$in": level.clone()}};
+            for file in self.file_collection.get_file(file_filter).await? {
+                files.entry(file.folder_id).or_default().push(file);
+            }
+
+            if max_depth.map(|max_depth| depth >= max_depth).unwrap_or(false) {
+                break;
+            }
+
+            for folder in level.iter() {
+                children.insert(*folder, vec![]);
+            }
+
+            let folder_filter = doc! {"user_id": user_id, "parent_id": {"$in": level.clone()}};
+            let subfolders = self.folder_collection.folder_collection.find(folder_filter, None).await?.try_collect::<Vec<Folder>>().await?;
+
+            level = subfolders.iter().map(|folder| folder.id).filter(|id| !children.contains_key(id)).collect();
+
+            for folder in subfolders {
+                children.entry(folder.parent_id).or_default().push(folder);
+            }
+
+            depth += 1;
+        }
+
+        Ok(Some(nest_folder(root, folder_id, &mut children, &mut files)))
+    }
+
+    // Inserts a whole new subtree, e.g. an uploaded FolderJSON, in one go. The first folder is its
+    // root, which can't take the name of a folder already next to it.
+    pub async fn create_subtree(&self, folders: Vec<Folder>, files: Vec<File>) -> Result<(), StorageError> {
+        let mut session = start_transaction().await?;
+
+        let result = async {
+            if let Some(root) = folders.first() {
+                let owner_id = root.user_id.unwrap();
+                self.resolve_folder_name(&owner_id, root.parent_id, &root.folder_name, root.id, ConflictPolicy::Reject, &mut session).await?;
+            }
+            if !folders.is_empty() {
+                self.folder_collection.folder_collection.insert_many_with_session(&folders, None, &mut session).await?;
+            }
+            if !files.is_empty() {
+                self.file_collection.file_collection.insert_many_with_session(&files, None, &mut session).await?;
+            }
+            Ok(())
+        }.await;
+
+        let owner_id = folders.first().and_then(|folder| folder.user_id).or(files.first().and_then(|file| file.user_id));
+        let result = finish_transaction(session, result).await;
+
+        if let Some(owner_id) = owner_id {
+            self.invalidate_stats(&owner_id);
+        }
+        result
+    }
+
+    // The folder an item is moved into. Ok(None) is the root.
+    async fn destination_folder(&self, destination: Option<ObjectId>, user_id: &ObjectId, session: &mut ClientSession) -> Result<Option<Folder>, StorageError> {
+        match destination {
+            Some(folder_id) => match self.folder_collection.get_folder_with_session(doc! {"_id": folder_id, "user_id": user_id}, session).await?.into_iter().next() {
+                Some(folder) => Ok(Some(folder)),
+                None => Err(StatusCode::NOT_FOUND.into())
+            },
+            None => Ok(None)
+        }
+    }
+
+    async fn move_file_in(&self, user_id: &ObjectId, file_id: &ObjectId, destination: Option<ObjectId>, policy: ConflictPolicy, session: &mut ClientSession) -> Result<(), StorageError> {
+        let filter = doc! {"_id": file_id, "user_id": user_id};
+
+        let file = match self.file_collection.get_file_with_session(filter.clone(), session).await?.into_iter().next() {
+            Some(file) => file,
+            None => return Err(StatusCode::NOT_FOUND.into())
+        };
+
+        if file.folder_id == destination {
+            return Ok(());
+        }
+
+        let destination_folder = self.destination_folder(destination, user_id, session).await?;
+        let name = self.resolve_file_name(user_id, destination, &file.file_name, file.id, policy, session).await?;
+        let path = Some(moved_path(&destination_folder, &name));
+
+        let update = doc! {
+            "$set": {
+                "folder_id": destination,
+                "file_name": &name,
+                "original_file_name": &name,
+                "path": path,
+                "updatedAt": bson::to_bson(&Utc::now()).unwrap_or_default(),
+            }
+        };
+
+        self.file_collection.file_collection.update_one_with_session(filter, update, None, session).await?;
+
+        Ok(())
+    }
+
+    async fn move_folder_in(&self, user_id: &ObjectId, folder_id: &ObjectId, destination: Option<ObjectId>, policy: ConflictPolicy, session: &mut ClientSession) -> Result<(), StorageError> {
+        let filter = doc! {"_id": folder_id, "user_id": user_id};
+
+        let folder = match self.folder_collection.get_folder_with_session(filter.clone(), session).await?.into_iter().next() {
+            Some(folder) => folder,
+            None => return Err(StatusCode::NOT_FOUND.into())
+        };
+
+        if folder.parent_id == destination {
+            return Ok(());
+        }
+
+        let destination_folder = self.destination_folder(destination, user_id, session).await?;
+
+        // a folder can't end up inside itself
+        if let Some(destination) = destination {
+            let ancestors = self.folder_ancestors(Some(destination), user_id, session).await?;
+
+            if ancestors.iter().any(|ancestor| ancestor.id == Some(*folder_id)) {
+                return Err(StatusCode::BAD_REQUEST.into());
+            }
+        }
+
+        let name = self.resolve_folder_name(user_id, destination, &folder.folder_name, folder.id, policy, session).await?;
+        let old_path = folder.path.clone();
+        let new_path = Some(moved_path(&destination_folder, &name));
+
+        let update = doc! {
+            "$set": {
+                "parent_id": destination,
+                "folder_type": folder_type_for(destination),
+                "folder_name": &name,
+                "path": &new_path,
+                "updatedAt": bson::to_bson(&Utc::now()).unwrap_or_default(),
+            }
+        };
+
+        self.folder_collection.folder_collection.update_one_with_session(filter, update, None, session).await?;
+
+        if let (Some(old_path), Some(new_path)) = (old_path, new_path) {
+            self.rewrite_descendant_paths(folder, &old_path, &new_path, user_id, session).await?;
+        }
+
+        Ok(())
+    }
+
+    // Moves everything or nothing.
+    pub async fn move_items(&self, user_id: &ObjectId, file_ids: &[ObjectId], folder_ids: &[ObjectId], destination: Option<ObjectId>, policy: ConflictPolicy) -> Result<MovedItems, StorageError> {
+        let mut session = start_transaction().await?;
+
+        let result = async {
+            for folder_id in folder_ids {
+                self.move_folder_in(user_id, folder_id, destination, policy, &mut session).await?;
+            }
+
+            for file_id in file_ids {
+                self.move_file_in(user_id, file_id, destination, policy, &mut session).await?;
+            }
+
+            Ok::<(), StorageError>(())
+        }.await;
+
+        finish_transaction(session, result).await?;
+        self.invalidate_stats(user_id);
+
+        Ok(MovedItems{
+            files: self.file_collection.get_file(doc! {"_id": {"$in": file_ids}, "user_id": user_id}).await?,
+            folders: self.folder_collection.get_folder(doc! {"_id": {"$in": folder_ids}, "user_id": user_id}).await?,
+        })
+    }
+
+    // A new File document pointing at the same content. Deduplicated content gets one more
+    // reference so deleting either file leaves the other intact.
+    async fn copy_file_document(&self, file: &File, file_id: ObjectId, folder_id: Option<ObjectId>, file_name: String, path: Option<String>, session: &mut ClientSession) -> Result<(), Error> {
+        if let Some(hash) = &file.content_hash {
+            self.blob_collection.blob_collection.update_one_with_session(doc! {"_id": hash}, doc! {"$inc": {"ref_count": 1}}, None, session).await?;
+        }
+
+        let now = Utc::now();
+
+        let copy = File{
+            id: Some(file_id),
+            original_file_name: Some(file_name.clone()),
+            file_name,
+            created_at: Some(now),
+            updated_at: Some(now),
+            folder_id,
+            path,
+            version: None,
+            ..file.clone()
+        };
+
+        self.file_collection.file_collection.insert_one_with_session(&copy, None, session).await?;
+
+        Ok(())
+    }
+
+    async fn copy_file_in(&self, user_id: &ObjectId, file: &File, destination: Option<ObjectId>, policy: ConflictPolicy, id_map: &mut HashMap<String, ObjectId>, session: &mut ClientSession) -> Result<ObjectId, StorageError> {
+        let destination_folder = self.destination_folder(destination, user_id, session).await?;
+        let name = self.resolve_file_name(user_id, destination, &file.file_name, None, policy, session).await?;
+        let path = Some(moved_path(&destination_folder, &name));
+
+        let copy_id = ObjectId::new();
+        self.copy_file_document(file, copy_id, destination, name, path, session).await?;
+
+        id_map.insert(file.id.unwrap().to_hex(), copy_id);
+        Ok(copy_id)
+    }
+
+    // Recreates the whole subtree below `destination` with fresh ids, like save_folders_to_db
+    // does for an uploaded FolderJSON.
+    async fn copy_folder_in(&self, user_id: &ObjectId, folder: Folder, destination: Option<ObjectId>, policy: ConflictPolicy, id_map: &mut HashMap<String, ObjectId>, session: &mut ClientSession) -> Result<ObjectId, StorageError> {
+        let folder_id = folder.id.unwrap();
+        let destination_folder = self.destination_folder(destination, user_id, session).await?;
+        let name = self.resolve_folder_name(user_id, destination, &folder.folder_name, None, policy, session).await?;
+
+        let old_root_path = folder.path.clone();
+        let new_root_path = Some(moved_path(&destination_folder, &name));
+
+        let (folders, files) = self.folder_subtree(folder, user_id, session).await?;
+
+        let mut copies: HashMap<ObjectId, ObjectId> = HashMap::new();
+        for id in folders.iter().filter_map(|folder| folder.id).chain(files.iter().filter_map(|file| file.id)) {
+            copies.insert(id, ObjectId::new());
+        }
+
+        let now = Utc::now();
+
+        let folder_copies = folders.iter().map(|folder| {
+            let is_root = folder.id == Some(folder_id);
+            let parent_id = if is_root { destination } else { folder.parent_id.and_then(|parent_id| copies.get(&parent_id).copied()) };
+
+            Folder{
+                id: Some(copies[&folder.id.unwrap()]),
+                folder_name: if is_root { name.clone() } else { folder.folder_name.clone() },
+                folder_type: Some(folder_type_for(parent_id)),
+                files: None,
+                folders: None,
+                created_at: Some(now),
+                updated_at: Some(now),
+                parent_id,
+                user_id: Some(*user_id),
+                owner_kind: folder.owner_kind,
+                path: if is_root { new_root_path.clone() } else { rebased_path(&folder.path, &old_root_path, &new_root_path) },
+                stats: None,
+            }
+        }).collect::<Vec<_>>();
+
+        self.folder_collection.folder_collection.insert_many_with_session(&folder_copies, None, session).await?;
+
+        for file in files.iter() {
+            let file_id = copies[&file.id.unwrap()];
+            let folder_id = file.folder_id.and_then(|folder_id| copies.get(&folder_id).copied());
+            let path = rebased_path(&file.path, &old_root_path, &new_root_path);
+
+            self.copy_file_document(file, file_id, folder_id, file.file_name.clone(), path, session).await?;
+        }
+
+        for (id, copy_id) in copies.iter() {
+            id_map.insert(id.to_hex(), *copy_id);
+        }
+
+        Ok(copies[&folder_id])
+    }
+
+    pub async fn copy_items(&self, owner: &Owner, file_ids: &[ObjectId], folder_ids: &[ObjectId], destination: Option<ObjectId>, policy: ConflictPolicy) -> Result<CopiedItems, StorageError> {
+        let user_id = &owner.id;
+        let files = self.file_collection.get_file(doc! {"_id": {"$in": file_ids}, "user_id": user_id}).await?;
+        let folders = self.folder_collection.get_folder(doc! {"_id": {"$in": folder_ids}, "user_id": user_id}).await?;
+
+        if files.len() != file_ids.len() || folders.len() != folder_ids.len() {
+            return Err(StatusCode::NOT_FOUND.into());
+        }
+
+        let mut session = start_transaction().await?;
+        let mut id_map = HashMap::new();
+
+        let result = async {
+            let mut folder_copies = vec![];
+            let mut file_copies = vec![];
+
+            for folder in folders {
+                folder_copies.push(self.copy_folder_in(user_id, folder, destination, policy, &mut id_map, &mut session).await?);
+            }
+
+            for file in files.iter() {
+                file_copies.push(self.copy_file_in(user_id, file, destination, policy, &mut id_map, &mut session).await?);
+            }
+
+            // the copies are all in the user's files now, quota is checked against the result
+            let copied_size = self.copied_size(user_id, &id_map, &mut session).await?;
+            self.check_quota(owner, copied_size).await?;
+
+            Ok::<_, StorageError>((folder_copies, file_copies))
+        }.await;
+
+        let (folder_copies, file_copies) = finish_transaction(session, result).await?;
+        self.invalidate_stats(user_id);
+
+        Ok(CopiedItems{
+            files: self.file_collection.get_file(doc! {"_id": {"$in": file_copies}, "user_id": user_id}).await?,
+            folders: self.folder_collection.get_folder(doc! {"_id": {"$in": folder_copies}, "user_id": user_id}).await?,
+            id_map,
+        })
+    }
+
+    async fn copied_size(&self, user_id: &ObjectId, id_map: &HashMap<String, ObjectId>, session: &mut ClientSession) -> Result<u64, Error> {
+        let copy_ids = id_map.values().copied().collect::<Vec<_>>();
+        let copies = self.file_collection.get_file_with_session(doc! {"_id": {"$in": copy_ids}, "user_id": user_id}, session).await?;
+
+        Ok(copies.iter().map(|file| file.size).sum())
+    }
+
+    // Folders used to store their children as id arrays next to the children's own folder_id and
+    // parent_id. Fills in any pointer only the array had, then drops the arrays.
+    pub async fn migrate_folder_arrays(&self) -> Result<(), Error> {
+        let folders = self.folder_collection.folder_collection.clone_with_type::<Document>();
+        let filter = doc! {"$or": [{"files": {"$exists": true}}, {"folders": {"$exists": true}}]};
+
+        let mut cursor = folders.find(filter.clone(), None).await?;
+
+        while let Some(folder) = cursor.try_next().await? {
+            let folder_id = match folder.get_object_id("_id") {
+                Ok(folder_id) => folder_id,
+                Err(_) => continue
+            };
+
+            if let Ok(files) = folder.get_array("files") {
+                let update = doc! {"$set": {"folder_id": folder_id}};
+                self.file_collection.file_collection.update_many(doc! {"_id": {"$in": files.clone()}, "folder_id": null}, update, None).await?;
+            }
+
+            if let Ok(subfolders) = folder.get_array("folders") {
+                let update = doc! {"$set": {"parent_id": folder_id, "folder_type": FolderType::Subfolder}};
+                self.folder_collection.folder_collection.update_many(doc! {"_id": {"$in": subfolders.clone()}, "parent_id": null}, update, None).await?;
+            }
+        }
+
+        folders.update_many(filter, doc! {"$unset": {"files": "", "folders": ""}}, None).await?;
+
+        Ok(())
+    }
+
+    // Paths used to be whatever the client sent. Rebuilds them from the parent pointers once,
+    // as long as anything is missing a path or still has one that isn't absolute.
+    pub async fn migrate_paths(&self) -> Result<(), Error> {
+        let stale = doc! {"$or": [{"path": null}, {"path": {"$not": {"$regex": "^/"}}}]};
+
+        let stale_folders = self.folder_collection.folder_collection.count_documents(stale.clone(), None).await?;
+        let stale_files = self.file_collection.file_collection.count_documents(stale, None).await?;
+
+        if stale_folders == 0 && stale_files == 0 {
+            return Ok(());
+        }
+
+        let folders = self.folder_collection.folder_collection.find(doc! {}, None).await?.try_collect::<Vec<Folder>>().await?;
+        let by_id = folders.iter().filter_map(|folder| folder.id.map(|id| (id, folder))).collect::<HashMap<_, _>>();
+
+        let mut paths: HashMap<ObjectId, String> = HashMap::new();
+
+        for folder in folders.iter() {
+            // walk up to the root, a broken or cyclic chain ends the walk where it breaks
+            let mut chain = vec![];
+            let mut current = Some(folder);
+
+            while let Some(folder) = current {
+                if chain.len() > by_id.len() {
+                    break;
+                }
+                chain.push(folder);
+                current = folder.parent_id.and_then(|parent_id| by_id.get(&parent_id).copied());
+            }
+
+            let mut parent_path: Option<String> = None;
+
+            for folder in chain.into_iter().rev() {
+                let path = child_path(parent_path.as_deref(), &folder.folder_name);
+                paths.insert(folder.id.unwrap(), path.clone());
+                parent_path = Some(path);
+            }
+        }
+
+        for folder in folders.iter() {
+            let folder_id = folder.id.unwrap();
+            let path = &paths[&folder_id];
+
+            if folder.path.as_ref() != Some(path) {
+                self.folder_collection.folder_collection.update_one(doc! {"_id": folder_id}, doc! {"$set": {"path": path}}, None).await?;
+            }
+        }
+
+        let mut files = self.file_collection.file_collection.find(doc! {}, None).await?;
+
+        while let Some(file) = files.try_next().await? {
+            let parent_path = file.folder_id.and_then(|folder_id| paths.get(&folder_id)).map(String::as_str);
+            let path = child_path(parent_path, &file.file_name);
+
+            if file.path.as_ref() != Some(&path) {
+                self.file_collection.file_collection.update_one(doc! {"_id": file.id}, doc! {"$set": {"path": path}}, None).await?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::{child_path, rebased_path};
+
+    #[test]
+    fn child_paths_start_at_the_root() {
+        assert_eq!(child_path(None, "Photos"), "/Photos");
+        assert_eq!(child_path(Some("/Photos"), "2024"), "/Photos/2024");
+        assert_eq!(child_path(Some("/Photos/"), "2024"), "/Photos/2024");
+    }
+
+    #[test]
+    fn rebases_paths_below_the_old_root() {
+        let old_root = Some("/Photos".to_string());
+        let new_root = Some("/Archive/Photos (1)".to_string());
+
+        assert_eq!(rebased_path(&Some("/Photos/2024/beach.jpg".to_string()), &old_root, &new_root), Some("/Archive/Photos (1)/2024/beach.jpg".to_string()));
+        assert_eq!(rebased_path(&Some("/Photos".to_string()), &old_root, &new_root), Some("/Photos".to_string()));
+    }
+
+    #[test]
+    fn keeps_paths_outside_of_the_old_root() {
+        let old_root = Some("/Photos".to_string());
+        let new_root = Some("/Archive".to_string());
+
+        // a sibling sharing the prefix is not below the root
+        assert_eq!(rebased_path(&Some("/Photos2/a.jpg".to_string()), &old_root, &new_root), Some("/Photos2/a.jpg".to_string()));
+        assert_eq!(rebased_path(&None, &old_root, &new_root), None);
+        assert_eq!(rebased_path(&Some("/Photos/a.jpg".to_string()), &None, &new_root), Some("/Photos/a.jpg".to_string()));
+    }
+}
@@ -0,0 +1,107 @@
+use std::env;
+use std::path::PathBuf;
+use async_trait::async_trait;
+use bson::{doc, Document};
+use bson::oid::ObjectId;
+use dotenv::dotenv;
+use futures::TryStreamExt;
+use chrono::{Duration, Utc};
+use mongodb::Collection;
+use mongodb::options::{FindOneAndUpdateOptions, ReturnDocument};
+use mongodb::results::{DeleteResult, InsertOneResult, UpdateResult};
+use tokio::fs;
+use crate::AppState;
+use crate::models::upload_model::Upload;
+use crate::services::trait_service::{mongo_client, StorageCollection};
+
+
+#[derive(Debug, Clone)]
+pub struct UploadCollection{
+    pub upload_collection: Collection<Upload>,
+    pub staging_path: PathBuf,
+}
+
+impl UploadCollection {
+
+    pub async fn get_upload(&self, filter: Document) -> Result<Option<Upload>, mongodb::error::Error>{
+        self.upload_collection.find_one(filter, None).await
+    }
+
+    pub async fn create_upload(&self, upload: &Upload) -> Result<InsertOneResult, mongodb::error::Error>{
+        self.upload_collection.insert_one(upload, None).await
+    }
+
+    pub async fn update_upload(&self, filter: Document, update: Document) -> Result<UpdateResult, mongodb::error::Error>{
+        self.upload_collection.update_one(filter, update, None).await
+    }
+
+    pub async fn delete_upload(&self, filter: Document) -> Result<DeleteResult, mongodb::error::Error>{
+        self.upload_collection.delete_one(filter, None).await
+    }
+
+    // Takes the upload for one PATCH starting at `offset`. None when the offset is not the current
+    // one or another PATCH holds it. Locks left behind by a crashed request lapse after a while.
+    pub async fn claim_upload(&self, upload_id: &ObjectId, user_id: &ObjectId, offset: u64) -> Result<Option<Upload>, mongodb::error::Error>{
+        let stale = bson::DateTime::from_chrono(Utc::now() - Duration::minutes(15));
+
+        let filter = doc! {
+            "_id": upload_id,
+            "user_id": user_id,
+            "offset": offset as i64,
+            "file_id": null,
+            "$or": [{"locked_at": null}, {"locked_at": {"$lt": stale}}],
+        };
+
+        let options = FindOneAndUpdateOptions::builder().return_document(ReturnDocument::After).build();
+        self.upload_collection.find_one_and_update(filter, doc! {"$set": {"locked_at": bson::DateTime::now()}}, options).await
+    }
+
+    // Records how far the claimed PATCH got and lets the next one in.
+    pub async fn release_upload(&self, upload_id: &ObjectId, offset: u64) -> Result<UpdateResult, mongodb::error::Error>{
+        self.update_upload(doc! {"_id": upload_id}, doc! {"$set": {"offset": offset as i64, "locked_at": null}}).await
+    }
+
+    pub fn staging_file(&self, upload_id: &ObjectId) -> PathBuf {
+        self.staging_path.join(upload_id.to_hex())
+    }
+}
+
+
+impl AppState {
+
+    // Drops uploads whose expiry has passed together with whatever was staged for them.
+    pub async fn purge_expired_uploads(&self) -> Result<u64, mongodb::error::Error>{
+        let filter = doc! {"expiresAt": {"$lt": bson::DateTime::now()}};
+        let expired = self.upload_collection.upload_collection.find(filter, None).await?.try_collect::<Vec<Upload>>().await?;
+
+        let mut purged = 0;
+
+        for upload in expired {
+            if let Some(upload_id) = upload.id {
+                let _ = fs::remove_file(self.upload_collection.staging_file(&upload_id)).await;
+                purged += self.upload_collection.delete_upload(doc! {"_id": upload_id}).await?.deleted_count;
+            }
+        }
+
+        Ok(purged)
+    }
+}
+
+
+#[async_trait]
+impl StorageCollection for UploadCollection{
+    type Error = Box<dyn std::error::Error>;
+
+    async fn init() -> Result<Self, Self::Error> where Self: Sized {
+        dotenv().ok();
+        let staging_path = env::var("UPLOAD_STAGING_PATH").unwrap_or("./staging".to_string());
+
+        fs::create_dir_all(&staging_path).await?;
+
+        let client = mongo_client().await?;
+        let db = client.database("cloud_storage");
+        let col: Collection<Upload> = db.collection("uploads");
+
+        Ok(Self{ upload_collection: col, staging_path: PathBuf::from(staging_path) })
+    }
+}
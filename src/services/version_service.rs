@@ -0,0 +1,251 @@
+use std::env;
+use async_trait::async_trait;
+use axum::Json;
+use bson::{doc, Document};
+use bson::oid::ObjectId;
+use chrono::{Duration, Utc};
+use dotenv::dotenv;
+use futures::TryStreamExt;
+use mongodb::{ClientSession, Collection};
+use mongodb::error::Error;
+use mongodb::options::FindOptions;
+use mongodb::results::{DeleteResult, InsertOneResult};
+use crate::AppState;
+use crate::models::file_model::File;
+use crate::models::owner_model::{Owner, OwnerKind};
+use crate::models::version_model::FileVersion;
+use crate::services::trait_service::{finish_transaction, mongo_client, start_transaction, StorageCollection};
+
+
+#[derive(Debug, Clone)]
+pub struct VersionCollection{
+    pub version_collection: Collection<FileVersion>,
+}
+
+impl VersionCollection {
+
+    pub async fn get_versions(&self, filter: Document) -> Result<Vec<FileVersion>, Error>{
+        let options = FindOptions::builder().sort(doc! {"version": -1}).build();
+        self.version_collection.find(filter, options).await?.try_collect::<Vec<FileVersion>>().await
+    }
+
+    pub async fn create_version(&self, version: &FileVersion) -> Result<InsertOneResult, Error>{
+        self.version_collection.insert_one(version, None).await
+    }
+
+    pub async fn delete_versions(&self, filter: Document) -> Result<DeleteResult, Error>{
+        self.version_collection.delete_many(filter, None).await
+    }
+}
+
+// Whether an upload became a new File or a new version of one that already existed
+#[derive(Debug, Clone, Copy)]
+pub enum StoredFile {
+    Created(ObjectId),
+    NewVersion(ObjectId),
+}
+
+impl StoredFile {
+    pub fn file_id(&self) -> ObjectId {
+        match self {
+            Self::Created(file_id) | Self::NewVersion(file_id) => *file_id
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct VersionLimits {
+    pub max_versions: Option<usize>,
+    pub max_age: Option<Duration>,
+}
+
+impl VersionLimits {
+    pub fn from_env() -> Self {
+        dotenv().ok();
+        Self {
+            max_versions: env::var("MAX_FILE_VERSIONS").ok().and_then(|count| count.parse::<usize>().ok()),
+            max_age: env::var("MAX_FILE_VERSION_AGE_DAYS").ok().and_then(|days| days.parse::<i64>().ok()).map(Duration::days),
+        }
+    }
+}
+
+fn version_of(file: &File, file_id: ObjectId, user_id: ObjectId) -> FileVersion {
+    FileVersion{
+        id: None,
+        file_id,
+        user_id,
+        version: file.version.unwrap_or(1),
+        file_type: file.file_type.clone(),
+        aws_file_name: file.aws_file_name.clone(),
+        file_location: file.file_location.clone(),
+        size: file.size,
+        content_hash: file.content_hash.clone(),
+        created_at: file.updated_at.map(bson::DateTime::from_chrono).unwrap_or(bson::DateTime::now()),
+    }
+}
+
+fn content_update(file_type: &str, aws_file_name: &str, file_location: &str, size: u64, content_hash: &Option<String>, version: i64) -> Document {
+    doc! {
+        "$set": {
+            "file_type": file_type,
+            "aws_file_name": aws_file_name,
+            "file_location": file_location,
+            "size": size as i64,
+            "content_hash": content_hash,
+            "version": version,
+            "updatedAt": bson::to_bson(&Utc::now()).unwrap_or_default(),
+        }
+    }
+}
+
+impl AppState {
+
+    // A setting of the user, or of the shared drive, that owns the files.
+    async fn versioning_enabled(&self, owner: &Owner) -> bool {
+        let versioning = match owner.kind {
+            OwnerKind::User => self.user_collection.user_collection.find_one(doc! {"_id": owner.id}, None).await.map(|user| user.and_then(|user| user.versioning)),
+            OwnerKind::Drive => self.drive_collection.drive_collection.find_one(doc! {"_id": owner.id}, None).await.map(|drive| drive.and_then(|drive| drive.versioning))
+        };
+
+        versioning.ok().flatten().unwrap_or(false)
+    }
+
+    // Moves the current content of `current` into file_versions and puts the content of `content`
+    // in its place. Returns the new version number and the versions pruned to make room, whose content
+    // is only released once the transaction has committed.
+    async fn push_version_in(&self, current: &File, content: &FileVersion, session: &mut ClientSession) -> Result<(i64, Vec<FileVersion>), Error> {
+        let file_id = current.id.unwrap();
+        let user_id = current.user_id.unwrap();
+
+        let archived = version_of(current, file_id, user_id);
+        let next_version = archived.version + 1;
+
+        let update = content_update(&content.file_type, &content.aws_file_name, &content.file_location, content.size, &content.content_hash, next_version);
+
+        self.version_collection.version_collection.insert_one_with_session(&archived, None, session).await?;
+        self.file_collection.file_collection.update_one_with_session(doc! {"_id": file_id}, update, None, session).await?;
+        let pruned = self.prune_versions_in(&file_id, VersionLimits::from_env(), session).await?;
+
+        Ok((next_version, pruned))
+    }
+
+    // Entry point for every upload that carries bytes. With versioning on, uploading a name that
+    // already exists in the same folder replaces the content and keeps the old one as a version.
+    pub async fn store_file(&self, mut file: File, owner: Owner) -> Result<StoredFile, Error> {
+        let user_id = owner.id;
+        file.owner_kind = owner.kind;
+
+        if self.versioning_enabled(&owner).await {
+            let filter = doc! {"user_id": user_id, "folder_id": file.folder_id, "file_name": &file.file_name};
+            let mut session = start_transaction().await?;
+
+            let result = async {
+                match self.file_collection.get_file_with_session(filter, &mut session).await?.into_iter().next() {
+                    Some(current) => {
+                        let file_id = current.id.unwrap();
+                        let (_, pruned) = self.push_version_in(&current, &version_of(&file, file_id, user_id), &mut session).await?;
+                        Ok(Some((file_id, pruned)))
+                    },
+                    None => Ok::<_, Error>(None)
+                }
+            }.await;
+
+            if let Some((file_id, pruned)) = finish_transaction(session, result).await? {
+                self.invalidate_stats(&user_id);
+                self.release_versions(&pruned).await;
+                return Ok(StoredFile::NewVersion(file_id));
+            }
+        }
+
+        let parent_path = self.folder_path(file.folder_id, &user_id).await?;
+        let new_file = self.file_collection.create_file(Json(file), user_id, parent_path.as_deref()).await?;
+        self.invalidate_stats(&user_id);
+        Ok(StoredFile::Created(new_file.inserted_id.as_object_id().unwrap()))
+    }
+
+    pub async fn restore_version(&self, current: &File, version: &FileVersion) -> Result<i64, Error> {
+        // the restored content is now referenced by the file and by the version entry
+        if let Some(hash) = &version.content_hash {
+            let _ = self.retain_blob(hash).await;
+        }
+
+        let mut session = start_transaction().await?;
+        let result = self.push_version_in(current, version, &mut session).await;
+        let (next_version, pruned) = finish_transaction(session, result).await?;
+
+        self.invalidate_stats(&current.user_id.unwrap());
+        self.release_versions(&pruned).await;
+
+        Ok(next_version)
+    }
+
+    // Keeps at most `max_versions` of the newest versions and drops anything older than `max_age`.
+    async fn prune_versions_in(&self, file_id: &ObjectId, limits: VersionLimits, session: &mut ClientSession) -> Result<Vec<FileVersion>, Error> {
+        let options = FindOptions::builder().sort(doc! {"version": -1}).build();
+        let versions = self.version_collection.version_collection.find_with_session(doc! {"file_id": file_id}, options, session).await?
+            .stream(session)
+            .try_collect::<Vec<FileVersion>>().await?;
+        let oldest_allowed = limits.max_age.map(|max_age| Utc::now() - max_age);
+
+        let pruned = versions.into_iter()
+            .enumerate()
+            .filter(|(index, version)| {
+                let too_many = limits.max_versions.map(|max_versions| *index >= max_versions).unwrap_or(false);
+                let too_old = oldest_allowed.map(|oldest| version.created_at.to_chrono() < oldest).unwrap_or(false);
+                too_many || too_old
+            })
+            .map(|(_, version)| version)
+            .collect::<Vec<_>>();
+
+        if !pruned.is_empty() {
+            let ids = pruned.iter().filter_map(|version| version.id).collect::<Vec<_>>();
+            self.version_collection.version_collection.delete_many_with_session(doc! {"_id": {"$in": ids}}, None, session).await?;
+        }
+
+        Ok(pruned)
+    }
+
+    pub async fn prune_versions(&self, file_id: &ObjectId, limits: VersionLimits) -> Result<Vec<FileVersion>, Error> {
+        let mut session = start_transaction().await?;
+        let result = self.prune_versions_in(file_id, limits, &mut session).await;
+        let pruned = finish_transaction(session, result).await?;
+
+        self.release_versions(&pruned).await;
+        Ok(pruned)
+    }
+
+    pub async fn release_versions(&self, versions: &[FileVersion]) {
+        for version in versions {
+            if let Some(hash) = &version.content_hash {
+                if let Err(err) = self.release_blob(hash).await {
+                    println!("Failed to release blob {}: {}", hash, err);
+                }
+            }
+        }
+    }
+
+    // Deleting a file takes all of its versions with it.
+    pub async fn delete_file_versions(&self, file_ids: &[ObjectId]) -> Result<(), Error> {
+        let filter = doc! {"file_id": {"$in": file_ids}};
+        let versions = self.version_collection.get_versions(filter.clone()).await?;
+
+        self.version_collection.delete_versions(filter).await?;
+        self.release_versions(&versions).await;
+
+        Ok(())
+    }
+}
+
+
+#[async_trait]
+impl StorageCollection for VersionCollection{
+    type Error = Box<dyn std::error::Error>;
+
+    async fn init() -> Result<Self, Self::Error> where Self: Sized {
+        let client = mongo_client().await?;
+        let db = client.database("cloud_storage");
+        let col: Collection<FileVersion> = db.collection("file_versions");
+
+        Ok(Self{ version_collection: col })
+    }
+}
@@ -0,0 +1,52 @@
+use std::io;
+use std::time::Duration;
+use async_trait::async_trait;
+use axum::body::Bytes;
+use chrono::{DateTime, Utc};
+use futures::stream::BoxStream;
+use serde::{Serialize, Deserialize};
+
+
+pub type ByteStream<'a> = BoxStream<'a, io::Result<Bytes>>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PresignMethod {
+    Get,
+    Put,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BlobMeta{
+    pub key: String,
+    pub size: u64,
+    pub last_modified: Option<DateTime<Utc>>,
+}
+
+
+// Everything that stores file bytes goes through this trait, so handlers never
+// care whether the object ends up on local disk or in a bucket.
+#[async_trait]
+pub trait BlobStore: Send + Sync {
+
+    async fn put(&self, key: &str, body: ByteStream<'_>) -> io::Result<BlobMeta>;
+
+    async fn get(&self, key: &str) -> io::Result<Bytes>;
+
+    async fn delete(&self, key: &str) -> io::Result<()>;
+
+    async fn head(&self, key: &str) -> io::Result<Option<BlobMeta>>;
+
+    // `range` is an inclusive (start, end) byte range, `None` streams the whole object
+    async fn stream(&self, key: &str, range: Option<(u64, u64)>) -> io::Result<ByteStream<'static>>;
+
+    // every object in the store, used by the garbage collector to find orphans
+    async fn list(&self) -> io::Result<Vec<BlobMeta>>;
+
+    fn location(&self, key: &str) -> String;
+
+    // Backends that clients can talk to directly hand out a short lived signed URL,
+    // everything else keeps the bytes going through the server.
+    fn presigned_url(&self, _method: PresignMethod, _key: &str, _expires_in: Duration, _file_name: Option<&str>) -> Option<String> {
+        None
+    }
+}
@@ -0,0 +1,159 @@
+use std::env;
+use std::io;
+use std::io::SeekFrom;
+use std::path::{Component, Path, PathBuf};
+use async_trait::async_trait;
+use axum::body::Bytes;
+use bson::oid::ObjectId;
+use chrono::{DateTime, Utc};
+use dotenv::dotenv;
+use futures::StreamExt;
+use tokio::fs;
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+use tokio_util::io::ReaderStream;
+use crate::storage::blob_store::{BlobMeta, BlobStore, ByteStream};
+
+
+#[derive(Debug, Clone)]
+pub struct LocalBlobStore{
+    pub root: PathBuf,
+}
+
+impl LocalBlobStore {
+
+    pub async fn init() -> io::Result<Self> {
+        dotenv().ok();
+        let root = env::var("STORAGE_PATH").unwrap_or("./storage".to_string());
+
+        fs::create_dir_all(&root).await?;
+
+        Ok(Self{ root: PathBuf::from(root) })
+    }
+
+    fn path_for(&self, key: &str) -> io::Result<PathBuf> {
+        let key_path = Path::new(key);
+
+        let is_safe = key_path.components().all(|component| matches!(component, Component::Normal(_)));
+
+        if key.is_empty() || !is_safe {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("Invalid blob key: {key}")));
+        }
+
+        Ok(self.root.join(key_path))
+    }
+
+    async fn meta_for(key: &str, path: &Path) -> io::Result<BlobMeta> {
+        let metadata = fs::metadata(path).await?;
+
+        Ok(BlobMeta{
+            key: key.to_string(),
+            size: metadata.len(),
+            last_modified: metadata.modified().ok().map(DateTime::<Utc>::from),
+        })
+    }
+}
+
+
+#[async_trait]
+impl BlobStore for LocalBlobStore {
+
+    async fn put(&self, key: &str, mut body: ByteStream<'_>) -> io::Result<BlobMeta> {
+        let path = self.path_for(key)?;
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+
+        // write next to the final file and rename, so a failed upload never leaves a half written blob
+        let tmp_path = path.with_file_name(format!(".{}.part", ObjectId::new()));
+        let mut tmp_file = fs::File::create(&tmp_path).await?;
+
+        let written: io::Result<()> = async {
+            while let Some(chunk) = body.next().await {
+                tmp_file.write_all(&chunk?).await?;
+            }
+            tmp_file.flush().await
+        }.await;
+
+        if let Err(err) = written {
+            let _ = fs::remove_file(&tmp_path).await;
+            return Err(err);
+        }
+
+        fs::rename(&tmp_path, &path).await?;
+
+        Self::meta_for(key, &path).await
+    }
+
+    async fn get(&self, key: &str) -> io::Result<Bytes> {
+        let path = self.path_for(key)?;
+        fs::read(path).await.map(Bytes::from)
+    }
+
+    async fn delete(&self, key: &str) -> io::Result<()> {
+        let path = self.path_for(key)?;
+
+        match fs::remove_file(path).await {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+            _ => Ok(())
+        }
+    }
+
+    async fn head(&self, key: &str) -> io::Result<Option<BlobMeta>> {
+        let path = self.path_for(key)?;
+
+        match Self::meta_for(key, &path).await {
+            Ok(meta) => Ok(Some(meta)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err)
+        }
+    }
+
+    async fn stream(&self, key: &str, range: Option<(u64, u64)>) -> io::Result<ByteStream<'static>> {
+        let path = self.path_for(key)?;
+        let mut file = fs::File::open(path).await?;
+
+        match range {
+            Some((start, end)) => {
+                file.seek(SeekFrom::Start(start)).await?;
+                let limited = file.take(end - start + 1);
+                Ok(ReaderStream::new(limited).boxed())
+            },
+            None => Ok(ReaderStream::new(file).boxed())
+        }
+    }
+
+    async fn list(&self) -> io::Result<Vec<BlobMeta>> {
+        let mut blobs = vec![];
+        let mut pending = vec![self.root.clone()];
+
+        while let Some(dir) = pending.pop() {
+            let mut entries = fs::read_dir(&dir).await?;
+
+            while let Some(entry) = entries.next_entry().await? {
+                let path = entry.path();
+
+                // dot files are in-progress writes from put
+                if entry.file_name().to_string_lossy().starts_with('.') {
+                    continue;
+                }
+
+                if entry.file_type().await?.is_dir() {
+                    pending.push(path);
+                    continue;
+                }
+
+                if let Ok(relative) = path.strip_prefix(&self.root) {
+                    let key = relative.components().map(|component| component.as_os_str().to_string_lossy()).collect::<Vec<_>>().join("/");
+                    blobs.push(Self::meta_for(&key, &path).await?);
+                }
+            }
+        }
+
+        Ok(blobs)
+    }
+
+    fn location(&self, key: &str) -> String {
+        format!("local://{}", key)
+    }
+}
@@ -0,0 +1,3 @@
+pub mod blob_store;
+pub mod local_store;
+pub mod s3_store;